@@ -1,185 +1,10697 @@
-use std::fmt;
-use std::cmp::Eq;
-use std::hash::Hash;
-use std::collections::{DList, HashMap, HashSet, PriorityQueue};
+extern crate arena;
+extern crate time;
 
-use priority::MinPriorityNode;
+use std::cmp::{Eq, max, min};
+use std::hash::{Hash, hash};
+use std::i32;
+use std::io::{IoError, IoResult, InvalidInput};
+use std::mem;
+use std::num::{Bounded, CheckedMul, One, ToPrimitive, Zero};
+use std::rand;
+use std::rand::task_rng;
+use std::collections::{Deque, DList, HashMap, HashSet, PriorityQueue};
+use std::sync::Arc;
+use std::sync::atomics::{AtomicBool, SeqCst};
+use std::time::Duration;
 
-pub mod graph {
-    use std::cmp::Eq;
-    use std::hash::Hash;
-    use std::iter::FromIterator;
+use cost::{Cost, OrderedFloat};
+use graph::WeightedGraph;
+use heuristic::Heuristic;
+use priority::{MaxPriorityNode, MinPriorityNode};
+
+pub mod cost {
+    use std::num::{Bounded, Float, One, ToPrimitive, Zero};
+
+    /// The operations the search algorithms need from an edge weight.
+    ///
+    /// This is implemented automatically for any type that can be added,
+    /// totally ordered, and has a zero value, which covers all of the
+    /// built-in integer types.
+    pub trait Cost: Add<Self, Self> + Ord + Zero + Copy {}
+
+    impl<W: Add<W, W> + Ord + Zero + Copy> Cost for W {}
+
+    /// A wrapper that gives floating-point numbers a total order, so that
+    /// they can be used as edge weights.
+    ///
+    /// NaN is considered equal to itself and greater than every other value.
+    /// Since NaN also propagates through addition, any path that crosses a
+    /// NaN-weighted edge is treated as more expensive than every path that
+    /// does not.
+    ///
+    /// ```rust
+    /// use pathfinding::cost::OrderedFloat;
+    ///
+    /// let nan: f64 = Float::nan();
+    /// assert!(OrderedFloat(1.5f64) < OrderedFloat(nan));
+    /// ```
+    #[deriving(Clone, Show)]
+    pub struct OrderedFloat<F>(pub F);
+
+    impl<F: Float> PartialEq for OrderedFloat<F> {
+        fn eq(&self, other: &OrderedFloat<F>) -> bool {
+            self.cmp(other) == Equal
+        }
+    }
+
+    impl<F: Float> Eq for OrderedFloat<F> {}
+
+    impl<F: Float> Ord for OrderedFloat<F> {
+        fn cmp(&self, other: &OrderedFloat<F>) -> Ordering {
+            let (&OrderedFloat(ref a), &OrderedFloat(ref b)) = (self, other);
+
+            match (a.is_nan(), b.is_nan()) {
+                (true, true) => Equal,
+                (true, false) => Greater,
+                (false, true) => Less,
+                // Neither is NaN, so the comparison is always defined.
+                (false, false) => a.partial_cmp(b).unwrap()
+            }
+        }
+    }
+
+    impl<F: Float> PartialOrd for OrderedFloat<F> {
+        fn partial_cmp(&self, other: &OrderedFloat<F>) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl<F: Float> Add<OrderedFloat<F>, OrderedFloat<F>> for OrderedFloat<F> {
+        fn add(&self, other: &OrderedFloat<F>) -> OrderedFloat<F> {
+            let (&OrderedFloat(a), &OrderedFloat(b)) = (self, other);
+            OrderedFloat(a + b)
+        }
+    }
+
+    impl<F: Float> Mul<OrderedFloat<F>, OrderedFloat<F>> for OrderedFloat<F> {
+        fn mul(&self, other: &OrderedFloat<F>) -> OrderedFloat<F> {
+            let (&OrderedFloat(a), &OrderedFloat(b)) = (self, other);
+            OrderedFloat(a * b)
+        }
+    }
+
+    impl<F: Float> Sub<OrderedFloat<F>, OrderedFloat<F>> for OrderedFloat<F> {
+        fn sub(&self, other: &OrderedFloat<F>) -> OrderedFloat<F> {
+            let (&OrderedFloat(a), &OrderedFloat(b)) = (self, other);
+            OrderedFloat(a - b)
+        }
+    }
+
+    impl<F: Float> Bounded for OrderedFloat<F> {
+        fn min_value() -> OrderedFloat<F> {
+            OrderedFloat(Float::neg_infinity())
+        }
+
+        fn max_value() -> OrderedFloat<F> {
+            OrderedFloat(Float::infinity())
+        }
+    }
+
+    impl<F: Float> ToPrimitive for OrderedFloat<F> {
+        fn to_i64(&self) -> Option<i64> {
+            let &OrderedFloat(ref a) = self;
+            a.to_i64()
+        }
+
+        fn to_u64(&self) -> Option<u64> {
+            let &OrderedFloat(ref a) = self;
+            a.to_u64()
+        }
+
+        fn to_f64(&self) -> Option<f64> {
+            let &OrderedFloat(ref a) = self;
+            a.to_f64()
+        }
+    }
+
+    impl<F: Float> One for OrderedFloat<F> {
+        fn one() -> OrderedFloat<F> {
+            OrderedFloat(One::one())
+        }
+    }
+
+    impl<F: Float> Zero for OrderedFloat<F> {
+        fn zero() -> OrderedFloat<F> {
+            OrderedFloat(Zero::zero())
+        }
+
+        fn is_zero(&self) -> bool {
+            let &OrderedFloat(ref a) = self;
+            a.is_zero()
+        }
+    }
+}
+
+pub mod heuristic {
+    use std::cmp::{max, min};
     use std::collections::HashMap;
-    
-    pub trait WeightedGraph<'a, T, I: Iterator<(uint, &'a T)>> {
-        fn neighbours(&'a self, node: &T) -> I;
+    use std::hash::Hash;
+    use std::num::Zero;
+    use cost::{Cost, OrderedFloat};
+    use graph;
+    use graph::{GridGraph, WeightedGraph};
+    use {SearchError, dijkstra_all};
+
+    /// An estimate of the cost of getting from one node to another, for
+    /// guiding `a_star_search` and its relatives. Heuristics are passed to
+    /// the searches as a closure over the goal:
+    ///
+    /// ```ignore
+    /// let h = max_of(landmarks, scaled(manhattan, 2));
+    /// a_star_search(&g, start, goal, |n| h.estimate(n, goal), None);
+    /// ```
+    ///
+    /// So long as a heuristic never overestimates, the paths found are
+    /// optimal; `find_overestimates` checks this against a real graph.
+    pub trait Heuristic<T, W> {
+        fn estimate(&self, from: &T, goal: &T) -> W;
+    }
+
+    impl<'h, T, W, H: Heuristic<T, W>> Heuristic<T, W> for &'h H {
+        fn estimate(&self, from: &T, goal: &T) -> W {
+            (**self).estimate(from, goal)
+        }
+    }
+
+    /// The heuristic made by `zero`.
+    pub struct ZeroHeuristic;
+
+    impl<T, W: Zero> Heuristic<T, W> for ZeroHeuristic {
+        fn estimate(&self, _from: &T, _goal: &T) -> W {
+            Zero::zero()
+        }
     }
 
-    pub struct SimpleGraph<T> {
-        edges: HashMap<T, Vec<T>>
+    /// A heuristic that always estimates zero, which turns A* into Dijkstra's
+    /// algorithm.
+    pub fn zero() -> ZeroHeuristic {
+        ZeroHeuristic
     }
 
-    impl<T: Eq + Hash> SimpleGraph<T> {
-        pub fn new(edges: HashMap<T, Vec<T>>) -> SimpleGraph<T> {
-            SimpleGraph { edges: edges }
+    /// The heuristic made by `max_of`.
+    pub struct MaxOf<A, B> {
+        first: A,
+        second: B
+    }
+
+    impl<T, W: Ord, A: Heuristic<T, W>, B: Heuristic<T, W>> Heuristic<T, W> for MaxOf<A, B> {
+        fn estimate(&self, from: &T, goal: &T) -> W {
+            max(self.first.estimate(from, goal), self.second.estimate(from, goal))
         }
     }
 
-    impl<'a, T: Eq + Hash> WeightedGraph<'a, T, Neighbours<'a, T>> for SimpleGraph<T> {
-        fn neighbours(&'a self, node: &T) -> Neighbours<'a, T> {
-            match self.edges.find(node) {
-                Some(vec) => Neighbours { nodes: FromIterator::from_iter(vec.iter().map(|v| (1u, v))) },
-                None => Neighbours { nodes: Vec::new() }
+    /// A heuristic that takes the larger of two estimates. If neither ever
+    /// overestimates then neither does their maximum, and it is at least as
+    /// well informed as either.
+    pub fn max_of<A, B>(first: A, second: B) -> MaxOf<A, B> {
+        MaxOf { first: first, second: second }
+    }
+
+    /// The heuristic made by `scaled`.
+    pub struct Scaled<H, W> {
+        heuristic: H,
+        factor: W
+    }
+
+    impl<T, W: Mul<W, W>, H: Heuristic<T, W>> Heuristic<T, W> for Scaled<H, W> {
+        fn estimate(&self, from: &T, goal: &T) -> W {
+            self.heuristic.estimate(from, goal) * self.factor
+        }
+    }
+
+    /// A heuristic that multiplies another's estimates by `factor`, such as
+    /// to convert a distance into a travel time. Scaling up an admissible
+    /// heuristic can make it overestimate, trading optimality for speed as
+    /// in `weighted_a_star_search`.
+    pub fn scaled<H, W>(heuristic: H, factor: W) -> Scaled<H, W> {
+        Scaled { heuristic: heuristic, factor: factor }
+    }
+
+    /// Find every node from which `heuristic` overestimates the cost of
+    /// reaching `goal`, along with the true cost, by running Dijkstra's
+    /// algorithm backwards from the goal over `reverse`, the graph with its
+    /// edges flipped. An empty result means the heuristic is admissible for
+    /// that goal; nodes that can't reach the goal aren't checked.
+    pub fn find_overestimates<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>, H: Heuristic<T, W>>
+        (reverse: &'a WeightedGraph<'a, T, W, I>, goal: &'a T, heuristic: &H)
+        -> Result<Vec<(&'a T, W)>, SearchError> {
+
+        let costs: HashMap<&'a T, W> = try!(dijkstra_all(reverse, goal));
+        Ok(costs.move_iter().filter(|&(node, cost)| heuristic.estimate(node, goal) > cost).collect())
+    }
+
+    /// How far apart two grid cells are along each axis.
+    fn offsets(a: &(uint, uint), b: &(uint, uint)) -> (uint, uint) {
+        let (&(ax, ay), &(bx, by)) = (a, b);
+        (if ax > bx { ax - bx } else { bx - ax }, if ay > by { ay - by } else { by - ay })
+    }
+
+    /// The number of moves between two cells when only moves up, down, left
+    /// and right are allowed, each costing one. This matches a `FourWay`
+    /// `GridGraph`.
+    pub struct Manhattan;
+
+    impl Heuristic<(uint, uint), uint> for Manhattan {
+        fn estimate(&self, from: &(uint, uint), goal: &(uint, uint)) -> uint {
+            let (dx, dy) = offsets(from, goal);
+            dx + dy
+        }
+    }
+
+    /// The number of moves between two cells when diagonal moves are allowed
+    /// too, each costing one. This underestimates on an eight-way
+    /// `GridGraph`, whose diagonal moves cost more; use `Octile` there.
+    pub struct Chebyshev;
+
+    impl Heuristic<(uint, uint), uint> for Chebyshev {
+        fn estimate(&self, from: &(uint, uint), goal: &(uint, uint)) -> uint {
+            let (dx, dy) = offsets(from, goal);
+            max(dx, dy)
+        }
+    }
+
+    /// The cost of moving between two cells on an eight-way `GridGraph` if
+    /// there were nothing in the way, with orthogonal moves costing
+    /// `ORTHOGONAL_COST` and diagonal ones `DIAGONAL_COST`.
+    pub struct Octile;
+
+    impl Heuristic<(uint, uint), uint> for Octile {
+        fn estimate(&self, from: &(uint, uint), goal: &(uint, uint)) -> uint {
+            let (dx, dy) = offsets(from, goal);
+            graph::ORTHOGONAL_COST * max(dx, dy) + (graph::DIAGONAL_COST - graph::ORTHOGONAL_COST) * min(dx, dy)
+        }
+    }
+
+    /// The straight-line distance between the centres of two cells. This
+    /// never overestimates under any movement model with moves costing their
+    /// length, such as the any-angle paths of `theta_star_search`. For a
+    /// `TerrainGrid`, scale it by the cheapest cell cost.
+    pub struct Euclidean;
+
+    impl Heuristic<(uint, uint), OrderedFloat<f64>> for Euclidean {
+        fn estimate(&self, from: &(uint, uint), goal: &(uint, uint)) -> OrderedFloat<f64> {
+            let (dx, dy) = offsets(from, goal);
+            let (dx, dy) = (dx as f64, dy as f64);
+            OrderedFloat((dx * dx + dy * dy).sqrt())
+        }
+    }
+
+    /// The heuristic made by `for_grid`.
+    pub struct GridDistance {
+        connectivity: graph::Connectivity
+    }
+
+    impl Heuristic<(uint, uint), uint> for GridDistance {
+        fn estimate(&self, from: &(uint, uint), goal: &(uint, uint)) -> uint {
+            match self.connectivity {
+                graph::FourWay => Manhattan.estimate(from, goal),
+                graph::EightWay | graph::EightWayNoCorners => Octile.estimate(from, goal)
             }
         }
     }
 
-    /// A simple interator over a node's neighbours in a weighted graph.
+    /// The most informed admissible distance for a `GridGraph`'s movement
+    /// model: `Manhattan` for four-way grids and `Octile` for eight-way ones.
     ///
-    /// Each call to `next()` produces a tuple of the edge's weight and a
-    /// pointer to the neighbouring node.
-    ///
-    /// ```rust
-    /// use pathfinding::graph::Graph;
+    /// ```ignore
+    /// let h = for_grid(&grid);
+    /// a_star_search(&grid, start, goal, |cell| h.estimate(cell, goal), None);
+    /// ```
+    pub fn for_grid(grid: &GridGraph) -> GridDistance {
+        GridDistance { connectivity: grid.connectivity() }
+    }
+}
+
+pub mod graph {
+    use std::cmp::{Eq, max, min};
+    use std::hash::Hash;
+    use std::iter::{FromIterator, range_inclusive};
+    use std::cell::RefCell;
+    use std::collections::{HashMap, HashSet};
+    use std::f64::consts::SQRT2;
+    use std::slice::Items;
+    use arena::TypedArena;
+    use cost::OrderedFloat;
+    
+    pub trait WeightedGraph<'a, T, W, I: Iterator<(W, &'a T)>> {
+        fn neighbours(&'a self, node: &T) -> I;
+
+        /// Check whether `node` belongs to the graph, so that searches can
+        /// tell a start or goal that doesn't exist from one that can't be
+        /// reached. Graphs that can't tell assume that every node does.
+        fn has_node(&'a self, _node: &T) -> bool {
+            true
+        }
+    }
+
+    /// Graphs that can also list the edges leading into a node, each as its
+    /// weight and the node it comes from. Undirected graphs just give their
+    /// neighbours again.
+    pub trait Predecessors<'a, T, W, I: Iterator<(W, &'a T)>> {
+        fn predecessors(&'a self, node: &T) -> I;
+
+        /// As `WeightedGraph::has_node`, so that a `Reversed` view can tell
+        /// which nodes belong to the graph it wraps.
+        fn contains_node(&'a self, _node: &T) -> bool {
+            true
+        }
+    }
+
+    /// A graph with the direction of every edge flipped, for searching
+    /// backwards over a graph without copying it. This is what the
+    /// bidirectional searches want as their `reverse` graph:
     ///
-    /// fn print_neighbours<T: std::fmt::Show>(node: &T, graph: &Graph<T>) {
-    ///     let neighbours = graph.neighbours(node).collect();
-    ///     println!("Neighbours: {}", neighbours);
-    /// }
+    /// ```ignore
+    /// let path = bidirectional_dijkstra(&roads, &Reversed(&roads), start, goal);
     /// ```
-    pub struct Neighbours<'a, T> {
-        nodes: Vec<(uint, &'a T)>
+    pub struct Reversed<'a, G>(pub &'a G);
+
+    impl<'a, T, W, I: Iterator<(W, &'a T)>, G: Predecessors<'a, T, W, I>>
+        WeightedGraph<'a, T, W, I> for Reversed<'a, G> {
+
+        fn neighbours(&'a self, node: &T) -> I {
+            let &Reversed(graph) = self;
+            graph.predecessors(node)
+        }
+
+        fn has_node(&'a self, node: &T) -> bool {
+            let &Reversed(graph) = self;
+            graph.contains_node(node)
+        }
     }
 
-    impl<'a, T> Iterator<(uint, &'a T)> for Neighbours<'a, T> {
-        fn next(&mut self) -> Option<(uint, &'a T)> {
-            self.nodes.pop()
+    /// A graph stored as a map from each node to its outgoing edges, each
+    /// given as a weight and the node it leads to.
+    pub struct SimpleGraph<T, W> {
+        edges: HashMap<T, Vec<(W, T)>>
+    }
+
+    impl<T: Eq + Hash + Clone, W> SimpleGraph<T, W> {
+        /// Create a graph from a map of each node's outgoing edges. Nodes
+        /// that only appear as the target of an edge are added with no edges
+        /// of their own.
+        pub fn new(mut edges: HashMap<T, Vec<(W, T)>>) -> SimpleGraph<T, W> {
+            let mut missing = Vec::new();
+            for targets in edges.values() {
+                for &(_, ref to) in targets.iter() {
+                    if !edges.contains_key(to) {
+                        missing.push(to.clone());
+                    }
+                }
+            }
+
+            for node in missing.move_iter() {
+                edges.find_or_insert(node, Vec::new());
+            }
+            SimpleGraph { edges: edges }
         }
+    }
 
-        fn size_hint(&self) -> (uint, Option<uint>) {
-            (self.nodes.len(), Some(self.nodes.len()))
+    impl<T: Eq + Hash, W> SimpleGraph<T, W> {
+
+        /// Remove a node, along with every edge leading to or from it.
+        /// Returns false if the node was not in the graph.
+        pub fn remove_node(&mut self, node: &T) -> bool {
+            let found = self.edges.remove(node);
+            for (_, targets) in self.edges.mut_iter() {
+                targets.retain(|&(_, ref to)| to != node);
+            }
+            found
+        }
+
+        /// Remove every edge from `from` to `to`, returning false if there
+        /// weren't any.
+        pub fn remove_edge(&mut self, from: &T, to: &T) -> bool {
+            match self.edges.find_mut(from) {
+                Some(targets) => {
+                    let before = targets.len();
+                    targets.retain(|&(_, ref t)| t != to);
+                    targets.len() < before
+                },
+                None => false
+            }
         }
     }
-}
 
-/// Search exhaustively over the graph, starting at the given node.
-///
-/// If `goal` is specified, stop searching if it is reached.
-pub fn breadth_first_search<'a, T: Eq + Hash + fmt::Show, I: Iterator<(uint, &'a T)>>
-    (graph: &'a graph::WeightedGraph<'a, T, I>, start: &'a T, goal: Option<&'a T>) {
+    impl<T: Eq + Hash + Clone, W: Clone> SimpleGraph<T, W> {
+        /// Add a node with no edges, if it isn't already in the graph.
+        pub fn add_node(&mut self, node: T) {
+            self.edges.find_or_insert(node, Vec::new());
+        }
 
-    let mut frontier = DList::new();
-    let mut visited = HashSet::new();
+        /// Add an edge from `from` to `to`, adding either node to the graph
+        /// if it isn't already there. Any existing edges between them are
+        /// kept, so this can make parallel edges.
+        pub fn add_edge(&mut self, from: T, to: T, weight: W) {
+            self.add_node(to.clone());
+            self.edges.find_or_insert(from, Vec::new()).push((weight, to));
+        }
 
-    frontier.push(start);
-    visited.insert(start);
+        /// Change the weight of every edge from `from` to `to`, returning
+        /// false if there weren't any.
+        pub fn update_weight(&mut self, from: &T, to: &T, weight: W) -> bool {
+            let mut found = false;
+            match self.edges.find_mut(from) {
+                Some(targets) => {
+                    for &mut (ref mut w, ref t) in targets.mut_iter() {
+                        if t == to {
+                            *w = weight.clone();
+                            found = true;
+                        }
+                    }
+                },
+                None => ()
+            }
+            found
+        }
 
-    loop {
-        // Break the loop when we run out of new nodes.
-        let current = match frontier.pop() {
-            Some(node) => node,
-            None => break
-        };
-        
-        println!("    Visiting: {}", current);
+        /// Create a graph from a list of `(from, to, weight)` edges.
+        pub fn from_edges(edges: &[(T, T, W)]) -> SimpleGraph<T, W> {
+            edges.iter().map(|e| e.clone()).collect()
+        }
 
-        // If `goal` is not None, check if we've reached it and break out
-        // early if we have.
-        if goal.map_or(false, |g| g.eq(current)) {
-            println!("    Goal reached.");
-            break;
+        /// Add every node and edge of `other` to this graph, for stitching
+        /// together chunks of a map as they arrive.
+        ///
+        /// Where both graphs have an edge between the same two nodes, the
+        /// one already here is kept, with its weight replaced by the result
+        /// of `resolve` applied to its weight and the other edge's. Pass
+        /// `|_, new| new.clone()` to let `other` win, or `|a, b| min(*a, *b)`
+        /// to keep the lighter edge.
+        pub fn merge(&mut self, other: SimpleGraph<T, W>, resolve: |&W, &W| -> W) {
+            for (from, targets) in other.edges.move_iter() {
+                self.add_node(from.clone());
+                for (weight, to) in targets.move_iter() {
+                    let resolved = match self.edges.get(&from).iter().find(|&&(_, ref t)| *t == to) {
+                        Some(&(ref w, _)) => Some(resolve(w, &weight)),
+                        None => None
+                    };
+
+                    match resolved {
+                        Some(w) => { self.update_weight(&from, &to, w); },
+                        None => self.add_edge(from.clone(), to, weight)
+                    }
+                }
+            }
         }
 
-        for (_, next) in graph.neighbours(current) {
-            // Ensure that we only visit each connected node once by
-            // keeping track of previously visited nodes.
-            if visited.contains(&next) {
-                continue;
-            } else {
-                visited.insert(next);
-                frontier.push(next);
+        /// A new graph holding every node and edge of both graphs, with
+        /// conflicting weights resolved as for `merge`.
+        pub fn union(&self, other: &SimpleGraph<T, W>, resolve: |&W, &W| -> W) -> SimpleGraph<T, W> {
+            let mut union = SimpleGraph::new(self.edges.clone());
+            union.merge(SimpleGraph::new(other.edges.clone()), resolve);
+            union
+        }
+    }
+
+    impl<T: Eq + Hash + Clone, W: Clone> FromIterator<(T, T, W)> for SimpleGraph<T, W> {
+        fn from_iter<I: Iterator<(T, T, W)>>(mut iterator: I) -> SimpleGraph<T, W> {
+            let mut graph = SimpleGraph::new(HashMap::new());
+            for (from, to, weight) in iterator {
+                graph.add_edge(from, to, weight);
             }
+            graph
         }
     }
-}
 
-mod priority {
+    impl<T: Eq + Hash + Clone> SimpleGraph<T, uint> {
+        /// Create a graph in which every edge has a weight of one.
+        pub fn unweighted(edges: HashMap<T, Vec<T>>) -> SimpleGraph<T, uint> {
+            let edges = edges.move_iter()
+                .map(|(node, targets)| (node, targets.move_iter().map(|t| (1u, t)).collect()))
+                .collect();
+            SimpleGraph::new(edges)
+        }
+    }
 
-    /// This is a simple struct to modify the PriortyQueue's behaviour so that
-    /// it uses the minimum instead of the maximum element.
-    ///
-    /// Taken almost straight from the `std::collections::priority_queue` docs.
-    #[deriving(Eq, PartialEq)]
-    pub struct MinPriorityNode<'a, T> {
-        pub node: T,
-        pub cost: uint
+    impl<'a, T: Eq + Hash, W: Clone> WeightedGraph<'a, T, W, Neighbours<'a, T, W>> for SimpleGraph<T, W> {
+        fn neighbours(&'a self, node: &T) -> Neighbours<'a, T, W> {
+            match self.edges.find(node) {
+                Some(vec) => Neighbours { nodes: FromIterator::from_iter(vec.iter().map(|&(ref w, ref v)| (w.clone(), v))) },
+                None => Neighbours { nodes: Vec::new() }
+            }
+        }
+
+        fn has_node(&'a self, node: &T) -> bool {
+            self.edges.contains_key(node)
+        }
     }
 
-    impl<'a, T: Eq> Ord for MinPriorityNode<'a, T> {
-        fn cmp(&self, other: &MinPriorityNode<'a, T>) -> Ordering {
-            other.cost.cmp(&self.cost)
+    /// A directed graph, built up one edge at a time. An edge added from `a`
+    /// to `b` can only be followed from `a`.
+    pub struct DiGraph<T, W> {
+        edges: HashMap<T, Vec<(W, T)>>,
+        // The same edges, stored at the node they lead to.
+        incoming: HashMap<T, Vec<(W, T)>>
+    }
+
+    impl<T: Eq + Hash + Clone, W: Clone> DiGraph<T, W> {
+        pub fn new() -> DiGraph<T, W> {
+            DiGraph { edges: HashMap::new(), incoming: HashMap::new() }
+        }
+
+        /// Add a node with no edges, if it isn't already in the graph.
+        pub fn add_node(&mut self, node: T) {
+            self.incoming.find_or_insert(node.clone(), Vec::new());
+            self.edges.find_or_insert(node, Vec::new());
+        }
+
+        pub fn add_edge(&mut self, from: T, to: T, weight: W) {
+            self.add_node(from.clone());
+            self.add_node(to.clone());
+            self.incoming.get_mut(&to).push((weight.clone(), from.clone()));
+            self.edges.get_mut(&from).push((weight, to));
+        }
+
+        /// Every node in the graph, in no particular order.
+        pub fn nodes<'a>(&'a self) -> Vec<&'a T> {
+            self.edges.keys().collect()
+        }
+
+        /// A copy of the graph with every edge pointing the other way. To
+        /// search backwards without making a copy, use `Reversed` instead.
+        pub fn reversed(&self) -> DiGraph<T, W> {
+            DiGraph { edges: self.incoming.clone(), incoming: self.edges.clone() }
         }
     }
 
-    impl<'a, T: PartialEq + Eq> PartialOrd for MinPriorityNode<'a, T> {
-        fn partial_cmp(&self, other: &MinPriorityNode<'a, T>) -> Option<Ordering> {
-            Some(self.cmp(other))
+    impl<'a, T: Eq + Hash, W: Clone> WeightedGraph<'a, T, W, Neighbours<'a, T, W>> for DiGraph<T, W> {
+        fn neighbours(&'a self, node: &T) -> Neighbours<'a, T, W> {
+            match self.edges.find(node) {
+                Some(vec) => Neighbours { nodes: FromIterator::from_iter(vec.iter().map(|&(ref w, ref v)| (w.clone(), v))) },
+                None => Neighbours { nodes: Vec::new() }
+            }
+        }
+
+        fn has_node(&'a self, node: &T) -> bool {
+            self.edges.contains_key(node)
         }
     }
-}
 
-pub fn dijkstra_search<'a, T: Eq + Hash + fmt::Show, I: Iterator<(uint, &'a T)>>
-    (graph: &'a graph::WeightedGraph<'a, T, I>, start: &'a T, goal: &'a T) {
+    impl<'a, T: Eq + Hash, W: Clone> Predecessors<'a, T, W, Neighbours<'a, T, W>> for DiGraph<T, W> {
+        fn predecessors(&'a self, node: &T) -> Neighbours<'a, T, W> {
+            match self.incoming.find(node) {
+                Some(vec) => Neighbours { nodes: FromIterator::from_iter(vec.iter().map(|&(ref w, ref v)| (w.clone(), v))) },
+                None => Neighbours { nodes: Vec::new() }
+            }
+        }
 
-    let mut frontier = PriorityQueue::new();
-    let mut came_from = HashMap::new();
-    let mut cost_so_far = HashMap::new();
+        fn contains_node(&'a self, node: &T) -> bool {
+            self.incoming.contains_key(node)
+        }
+    }
 
-    frontier.push(MinPriorityNode { node: start, cost: 0 });
-    came_from.insert(start, start);
-    cost_so_far.insert(start, 0u);
+    /// An undirected graph, built up one edge at a time. Each edge is stored
+    /// at both of its ends, so it can be followed either way.
+    ///
+    /// This is the kind of graph expected by `connected_components`,
+    /// `find_undirected_cycle`, `kruskal`, `prim`, `articulation_points`, and
+    /// `bridges`, which give misleading answers on a graph with one-way
+    /// edges. It can also be passed as its own `reverse` graph.
+    pub struct UnGraph<T, W> {
+        edges: HashMap<T, Vec<(W, T)>>
+    }
 
-    while !frontier.is_empty() {
-        let MinPriorityNode { node: current, cost: _ } = frontier.pop().unwrap();
+    impl<T: Eq + Hash + Clone, W: Clone> UnGraph<T, W> {
+        pub fn new() -> UnGraph<T, W> {
+            UnGraph { edges: HashMap::new() }
+        }
 
-        println!("    Visiting: {}", current);
+        /// Add a node with no edges, if it isn't already in the graph.
+        pub fn add_node(&mut self, node: T) {
+            self.edges.find_or_insert(node, Vec::new());
+        }
 
-        // Check if we've reached the goal.
-        if goal == current {
-            println!("    Goal reached.");
-            break;
+        /// Add an edge between `a` and `b`. A loop from a node to itself is
+        /// only stored once.
+        pub fn add_edge(&mut self, a: T, b: T, weight: W) {
+            if a != b {
+                self.edges.find_or_insert(b.clone(), Vec::new()).push((weight.clone(), a.clone()));
+            }
+            self.edges.find_or_insert(a, Vec::new()).push((weight, b));
         }
 
-        for (cost, next) in graph.neighbours(current) {
-            let new_cost = cost_so_far.get(&current) + cost;
-            
-            if cost_so_far.contains_key(&next) && new_cost > *cost_so_far.get(&next) {
-                continue;
-            } else {
-                cost_so_far.insert_or_update_with(next, new_cost, |_, v| *v = new_cost);
-                came_from.insert_or_update_with(next, current, |_, v| *v = current);
-                frontier.push(MinPriorityNode { node: next, cost: new_cost });
+        /// Every node in the graph, in no particular order.
+        pub fn nodes<'a>(&'a self) -> Vec<&'a T> {
+            self.edges.keys().collect()
+        }
+    }
+
+    impl<'a, T: Eq + Hash, W: Clone> WeightedGraph<'a, T, W, Neighbours<'a, T, W>> for UnGraph<T, W> {
+        fn neighbours(&'a self, node: &T) -> Neighbours<'a, T, W> {
+            match self.edges.find(node) {
+                Some(vec) => Neighbours { nodes: FromIterator::from_iter(vec.iter().map(|&(ref w, ref v)| (w.clone(), v))) },
+                None => Neighbours { nodes: Vec::new() }
             }
         }
+
+        fn has_node(&'a self, node: &T) -> bool {
+            self.edges.contains_key(node)
+        }
     }
-}
 
-fn main() {
-    let mut map = HashMap::new();
-    map.insert("A", vec!("B"));
-    map.insert("B", vec!("A", "C", "D"));
-    map.insert("C", vec!("A"));
-    map.insert("D", vec!("E", "A"));
-    map.insert("E", vec!("B"));
+    impl<'a, T: Eq + Hash, W: Clone> Predecessors<'a, T, W, Neighbours<'a, T, W>> for UnGraph<T, W> {
+        fn predecessors(&'a self, node: &T) -> Neighbours<'a, T, W> {
+            self.neighbours(node)
+        }
 
-    let g = graph::SimpleGraph::new(map);
+        fn contains_node(&'a self, node: &T) -> bool {
+            self.edges.contains_key(node)
+        }
+    }
 
-    println!("Searching over the whole graph:");
-    breadth_first_search(&g, &"A", None);
+    /// A graph over a fixed set of nodes stored as a matrix of edge weights,
+    /// with at most one edge from each node to each other.
+    ///
+    /// Looking up an edge takes constant time and each node's edges are a
+    /// single contiguous row, at the cost of space for every possible edge,
+    /// so this suits small or dense graphs such as the ones given to
+    /// `floyd_warshall` or `hungarian`.
+    pub struct AdjMatrixGraph<T, W> {
+        nodes: Vec<T>,
+        index: HashMap<T, uint>,
+        // Stored row-major, indexed by `from * n + to`.
+        weights: Vec<Option<W>>
+    }
 
-    println!("Searching over the graph with goal 'D':");
-    breadth_first_search(&g, &"A", Some(&"D"));
+    impl<T: Eq + Hash + Clone, W: Clone> AdjMatrixGraph<T, W> {
+        /// Create a graph over `nodes` with no edges.
+        pub fn new(nodes: Vec<T>) -> AdjMatrixGraph<T, W> {
+            let n = nodes.len();
+            let index = nodes.iter().enumerate().map(|(i, node)| (node.clone(), i)).collect();
+            AdjMatrixGraph { nodes: nodes, index: index, weights: Vec::from_fn(n * n, |_| None) }
+        }
+
+        pub fn nodes<'a>(&'a self) -> Vec<&'a T> {
+            self.nodes.iter().collect()
+        }
+
+        /// The weight of the edge from `from` to `to`, if there is one.
+        pub fn edge(&self, from: &T, to: &T) -> Option<W> {
+            match (self.index.find(from), self.index.find(to)) {
+                (Some(&i), Some(&j)) => self.weights.get(i * self.nodes.len() + j).clone(),
+                _ => None
+            }
+        }
+
+        /// Add an edge from `from` to `to`, replacing any that was already
+        /// there. Returns false, and does nothing, if either node is not in
+        /// the graph.
+        pub fn set_edge(&mut self, from: &T, to: &T, weight: W) -> bool {
+            self.replace_edge(from, to, Some(weight))
+        }
+
+        /// Remove the edge from `from` to `to`, returning false if either
+        /// node is not in the graph.
+        pub fn remove_edge(&mut self, from: &T, to: &T) -> bool {
+            self.replace_edge(from, to, None)
+        }
+
+        fn replace_edge(&mut self, from: &T, to: &T, weight: Option<W>) -> bool {
+            let n = self.nodes.len();
+            match (self.index.find(from), self.index.find(to)) {
+                (Some(&i), Some(&j)) => { *self.weights.get_mut(i * n + j) = weight; true },
+                _ => false
+            }
+        }
+    }
+
+    impl<'a, T: Eq + Hash, W: Clone> WeightedGraph<'a, T, W, Neighbours<'a, T, W>> for AdjMatrixGraph<T, W> {
+        fn neighbours(&'a self, node: &T) -> Neighbours<'a, T, W> {
+            let n = self.nodes.len();
+            let i = match self.index.find(node) {
+                Some(&i) => i,
+                None => return Neighbours { nodes: Vec::new() }
+            };
+
+            let row = self.weights.slice(i * n, (i + 1) * n);
+            let nodes = row.iter().zip(self.nodes.iter())
+                .filter_map(|(weight, to)| weight.as_ref().map(|w| (w.clone(), to)))
+                .collect();
+            Neighbours { nodes: nodes }
+        }
+
+        fn has_node(&'a self, node: &T) -> bool {
+            self.index.contains_key(node)
+        }
+    }
+
+    /// An immutable graph in compressed sparse row form: the edges are
+    /// sorted by the node they leave, so that each node's edges are a
+    /// contiguous slice of one shared array rather than a `Vec` of their own.
+    ///
+    /// This takes far less memory than the map-based graphs and is quicker
+    /// to search, which matters for graphs the size of a road network, but
+    /// it can't be changed once built.
+    pub struct CsrGraph<T, W> {
+        nodes: Vec<T>,
+        index: HashMap<T, uint>,
+        // The edges leaving node i are at offsets[i]..offsets[i + 1] in
+        // targets and weights.
+        offsets: Vec<uint>,
+        targets: Vec<uint>,
+        weights: Vec<W>
+    }
+
+    impl<T: Eq + Hash + Clone, W> CsrGraph<T, W> {
+        /// Build a graph from a list of `(from, to, weight)` edges. Its nodes
+        /// are those that appear in the list, in order of first appearance.
+        pub fn new(edges: Vec<(T, T, W)>) -> CsrGraph<T, W> {
+            let mut nodes = Vec::new();
+            let mut index = HashMap::new();
+            let mut ends = Vec::with_capacity(edges.len());
+
+            for &(ref from, ref to, _) in edges.iter() {
+                for node in [from, to].iter() {
+                    if !index.contains_key(*node) {
+                        index.insert((*node).clone(), nodes.len());
+                        nodes.push((*node).clone());
+                    }
+                }
+                ends.push((*index.get(from), *index.get(to)));
+            }
+
+            // Count the edges leaving each node, then place each edge in the
+            // next free slot of its node's range.
+            let mut offsets = Vec::from_elem(nodes.len() + 1, 0u);
+            for &(from, _) in ends.iter() {
+                *offsets.get_mut(from + 1) += 1;
+            }
+            for i in range(0, nodes.len()) {
+                let before = *offsets.get(i);
+                *offsets.get_mut(i + 1) += before;
+            }
+
+            let mut next = offsets.clone();
+            let mut slots = Vec::from_elem(edges.len(), 0u);
+            for (k, &(from, _)) in ends.iter().enumerate() {
+                *slots.get_mut(k) = *next.get(from);
+                *next.get_mut(from) += 1;
+            }
+
+            let mut targets = Vec::from_elem(edges.len(), 0u);
+            let mut placed: Vec<Option<W>> = Vec::from_fn(edges.len(), |_| None);
+            for (k, (_, _, weight)) in edges.move_iter().enumerate() {
+                let slot = *slots.get(k);
+                let &(_, to) = ends.get(k);
+                *targets.get_mut(slot) = to;
+                *placed.get_mut(slot) = Some(weight);
+            }
+            let weights = placed.move_iter().map(|w| w.unwrap()).collect();
+
+            CsrGraph { nodes: nodes, index: index, offsets: offsets, targets: targets, weights: weights }
+        }
+
+        pub fn nodes<'a>(&'a self) -> Vec<&'a T> {
+            self.nodes.iter().collect()
+        }
+
+        pub fn edge_count(&self) -> uint {
+            self.targets.len()
+        }
+    }
+
+    impl<'a, T: Eq + Hash, W: Clone> WeightedGraph<'a, T, W, CsrNeighbours<'a, T, W>> for CsrGraph<T, W> {
+        fn neighbours(&'a self, node: &T) -> CsrNeighbours<'a, T, W> {
+            let (start, end) = match self.index.find(node) {
+                Some(&i) => (*self.offsets.get(i), *self.offsets.get(i + 1)),
+                None => (0, 0)
+            };
+
+            CsrNeighbours {
+                nodes: self.nodes.as_slice(),
+                targets: self.targets.slice(start, end).iter(),
+                weights: self.weights.slice(start, end).iter()
+            }
+        }
+
+        fn has_node(&'a self, node: &T) -> bool {
+            self.index.contains_key(node)
+        }
+    }
+
+    /// An iterator over a node's neighbours in a `CsrGraph`, which walks its
+    /// slice of the edge arrays without copying them.
+    pub struct CsrNeighbours<'a, T, W> {
+        nodes: &'a [T],
+        targets: Items<'a, uint>,
+        weights: Items<'a, W>
+    }
+
+    impl<'a, T, W: Clone> Iterator<(W, &'a T)> for CsrNeighbours<'a, T, W> {
+        fn next(&mut self) -> Option<(W, &'a T)> {
+            match (self.weights.next(), self.targets.next()) {
+                (Some(w), Some(&t)) => Some((w.clone(), &self.nodes[t])),
+                _ => None
+            }
+        }
+
+        fn size_hint(&self) -> (uint, Option<uint>) {
+            self.targets.size_hint()
+        }
+    }
+
+    /// A graph whose edges are produced on demand by a closure, for state
+    /// spaces such as puzzles that are far too large to build in full.
+    ///
+    /// The closure is given a node and returns its successors along with the
+    /// weight of the edge to each. Successors are moved into an arena owned
+    /// by the graph so that searches can borrow them, which means memory use
+    /// grows with every node expanded until the graph is dropped. Use `node`
+    /// to get a start node with the right lifetime:
+    ///
+    /// ```ignore
+    /// let graph = FnGraph::new(|&n: &int| vec!((1u, n + 1), (1u, n * 2)));
+    /// let start = graph.node(1);
+    /// let path = dijkstra_search(&graph, start, &20, None);
+    /// ```
+    pub struct FnGraph<'f, T, W> {
+        successors: RefCell<|&T|: 'f -> Vec<(W, T)>>,
+        arena: TypedArena<T>
+    }
+
+    impl<'f, T, W> FnGraph<'f, T, W> {
+        pub fn new(successors: |&T|: 'f -> Vec<(W, T)>) -> FnGraph<'f, T, W> {
+            FnGraph { successors: RefCell::new(successors), arena: TypedArena::new() }
+        }
+
+        /// Move a node into the graph, returning a reference to it that
+        /// lives as long as the graph does.
+        pub fn node<'a>(&'a self, node: T) -> &'a T {
+            self.arena.alloc(node)
+        }
+    }
+
+    impl<'a, 'f, T, W> WeightedGraph<'a, T, W, Neighbours<'a, T, W>> for FnGraph<'f, T, W> {
+        fn neighbours(&'a self, node: &T) -> Neighbours<'a, T, W> {
+            let successors = (*self.successors.borrow_mut())(node);
+            let nodes = successors.move_iter().map(|(w, next)| (w, self.node(next))).collect();
+            Neighbours { nodes: nodes }
+        }
+    }
+
+    /// A directed graph that stores a value of type `N` with each node and
+    /// one of type `E` with each edge, such as the terrain of a cell or the
+    /// class of a road.
+    ///
+    /// The graph has no weights of its own. Instead, `with_costs` gives a
+    /// view of it that the searches can use, pricing each edge from the data
+    /// stored with it and its two ends:
+    ///
+    /// ```ignore
+    /// let view = roads.with_costs(|_, road: &Road, _| road.length / road.speed_limit);
+    /// let route = dijkstra_search(&view, start, goal, None);
+    /// ```
+    pub struct PayloadGraph<T, N, E> {
+        entries: HashMap<T, (N, Vec<(E, T)>)>
+    }
+
+    impl<T: Eq + Hash, N, E> PayloadGraph<T, N, E> {
+        pub fn new() -> PayloadGraph<T, N, E> {
+            PayloadGraph { entries: HashMap::new() }
+        }
+
+        /// Add a node with the given data, or replace the data of a node
+        /// that is already in the graph (keeping its edges). Returns true if
+        /// the node is new.
+        pub fn add_node(&mut self, node: T, data: N) -> bool {
+            match self.entries.find_mut(&node) {
+                Some(&mut (ref mut existing, _)) => { *existing = data; return false; },
+                None => ()
+            }
+            self.entries.insert(node, (data, Vec::new()));
+            true
+        }
+
+        /// Add an edge from `from` to `to` with the given data. Both nodes
+        /// must already be in the graph; if not, nothing is added and this
+        /// returns false.
+        pub fn add_edge(&mut self, from: &T, to: T, data: E) -> bool {
+            if !self.entries.contains_key(&to) {
+                return false;
+            }
+            match self.entries.find_mut(from) {
+                Some(&mut (_, ref mut edges)) => { edges.push((data, to)); true },
+                None => false
+            }
+        }
+
+        /// Every node in the graph, in no particular order.
+        pub fn nodes<'a>(&'a self) -> Vec<&'a T> {
+            self.entries.keys().collect()
+        }
+
+        pub fn node_data<'a>(&'a self, node: &T) -> Option<&'a N> {
+            self.entries.find(node).map(|&(ref data, _)| data)
+        }
+
+        pub fn node_data_mut<'a>(&'a mut self, node: &T) -> Option<&'a mut N> {
+            self.entries.find_mut(node).map(|&mut (ref mut data, _)| data)
+        }
+
+        /// The data of the first edge from `from` to `to`, if there is one.
+        pub fn edge_data<'a>(&'a self, from: &T, to: &T) -> Option<&'a E> {
+            self.edges(from).and_then(|edges| {
+                edges.iter().find(|&&(_, ref t)| t == to).map(|&(ref data, _)| data)
+            })
+        }
+
+        /// The edges leaving `node`, each as its data and the node it leads
+        /// to, or `None` if the node is not in the graph.
+        pub fn edges<'a>(&'a self, node: &T) -> Option<&'a [(E, T)]> {
+            self.entries.find(node).map(|&(_, ref edges)| edges.as_slice())
+        }
+
+        /// A view of the graph that the searches can use, with the weight
+        /// of each edge given by `cost` from the data at its start, the edge
+        /// itself, and its end.
+        pub fn with_costs<'a, 'f, W>(&'a self, cost: |&N, &E, &N|: 'f -> W)
+            -> CostView<'a, 'f, T, N, E, W> {
+            CostView { graph: self, cost: RefCell::new(cost) }
+        }
+    }
+
+    /// A `PayloadGraph` with edge weights worked out from its data, as made
+    /// by `PayloadGraph::with_costs`.
+    pub struct CostView<'a, 'f, T, N, E, W> {
+        graph: &'a PayloadGraph<T, N, E>,
+        cost: RefCell<|&N, &E, &N|: 'f -> W>
+    }
+
+    impl<'a, 'f, T: Eq + Hash, N, E, W> WeightedGraph<'a, T, W, Neighbours<'a, T, W>>
+        for CostView<'a, 'f, T, N, E, W> {
+
+        fn neighbours(&'a self, node: &T) -> Neighbours<'a, T, W> {
+            let (from, edges) = match (self.graph.node_data(node), self.graph.edges(node)) {
+                (Some(data), Some(edges)) => (data, edges),
+                _ => return Neighbours { nodes: Vec::new() }
+            };
+
+            let mut cost = self.cost.borrow_mut();
+            let nodes = edges.iter().map(|&(ref data, ref to)| {
+                let weight = (*cost)(from, data, self.graph.node_data(to).unwrap());
+                (weight, to)
+            }).collect();
+            Neighbours { nodes: nodes }
+        }
+
+        fn has_node(&'a self, node: &T) -> bool {
+            self.graph.node_data(node).is_some()
+        }
+    }
+
+    /// A dense handle for a node of an `IndexGraph`.
+    #[deriving(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Show)]
+    pub struct NodeId(pub u32);
+
+    impl NodeId {
+        /// The position of the node in its graph, for indexing arrays.
+        pub fn index(&self) -> uint {
+            let &NodeId(i) = self;
+            i as uint
+        }
+    }
+
+    /// A directed graph that interns its nodes as consecutive `NodeId`s.
+    ///
+    /// It is searched over the ids rather than the nodes themselves, so the
+    /// searches only ever hash small integers, however large the original
+    /// node values are; `indexed_dijkstra_search` goes further and avoids
+    /// hashing altogether. Use `id` and `node` to convert between the two.
+    pub struct IndexGraph<T, W> {
+        nodes: Vec<T>,
+        index: HashMap<T, NodeId>,
+        // The graph's own copy of every id, for handing out references.
+        ids: Vec<NodeId>,
+        edges: Vec<Vec<(W, NodeId)>>
+    }
+
+    impl<T: Eq + Hash + Clone, W: Clone> IndexGraph<T, W> {
+        pub fn new() -> IndexGraph<T, W> {
+            IndexGraph { nodes: Vec::new(), index: HashMap::new(), ids: Vec::new(), edges: Vec::new() }
+        }
+
+        /// Add a node to the graph, returning its id. Adding a node that is
+        /// already there returns the id it already has.
+        pub fn add_node(&mut self, node: T) -> NodeId {
+            match self.index.find(&node) {
+                Some(&id) => return id,
+                None => ()
+            }
+
+            let id = NodeId(self.nodes.len() as u32);
+            self.index.insert(node.clone(), id);
+            self.nodes.push(node);
+            self.ids.push(id);
+            self.edges.push(Vec::new());
+            id
+        }
+
+        /// Add an edge between two nodes, adding them first if need be.
+        pub fn add_edge(&mut self, from: T, to: T, weight: W) {
+            let (from, to) = (self.add_node(from), self.add_node(to));
+            self.edges.get_mut(from.index()).push((weight, to));
+        }
+
+        pub fn id(&self, node: &T) -> Option<NodeId> {
+            self.index.find(node).map(|&id| id)
+        }
+
+        pub fn node<'a>(&'a self, id: NodeId) -> &'a T {
+            self.nodes.get(id.index())
+        }
+
+        /// The graph's own copy of an id, for passing to the searches.
+        pub fn find_id<'a>(&'a self, id: NodeId) -> &'a NodeId {
+            self.ids.get(id.index())
+        }
+
+        /// Every id in the graph, in order.
+        pub fn ids<'a>(&'a self) -> Vec<&'a NodeId> {
+            self.ids.iter().collect()
+        }
+
+        pub fn len(&self) -> uint {
+            self.nodes.len()
+        }
+
+        /// The edges leaving a node, each as its weight and the id of the
+        /// node it leads to.
+        pub fn edges<'a>(&'a self, id: NodeId) -> &'a [(W, NodeId)] {
+            self.edges.get(id.index()).as_slice()
+        }
+    }
+
+    impl<'a, T, W: Clone> WeightedGraph<'a, NodeId, W, Neighbours<'a, NodeId, W>> for IndexGraph<T, W> {
+        fn neighbours(&'a self, node: &NodeId) -> Neighbours<'a, NodeId, W> {
+            if node.index() >= self.edges.len() {
+                return Neighbours { nodes: Vec::new() };
+            }
+
+            let nodes = self.edges.get(node.index()).iter()
+                .map(|&(ref w, to)| (w.clone(), self.ids.get(to.index())))
+                .collect();
+            Neighbours { nodes: nodes }
+        }
+
+        fn has_node(&'a self, node: &NodeId) -> bool {
+            node.index() < self.nodes.len()
+        }
+    }
+
+    /// A view of part of a graph: only the nodes for which `keep` returns
+    /// true, and the edges between them. Nothing is copied, so a new view
+    /// can be made cheaply for each query, to leave out hazardous cells say.
+    ///
+    /// ```ignore
+    /// let open = NodeFilter::new(&grid, |cell| !flooded.contains(cell));
+    /// let path = a_star_search(&open, start, goal, heuristic, None);
+    /// ```
+    pub struct NodeFilter<'a, 'f, T, W, I> {
+        graph: &'a WeightedGraph<'a, T, W, I>,
+        keep: RefCell<|&T|: 'f -> bool>
+    }
+
+    impl<'a, 'f, T, W, I: Iterator<(W, &'a T)>> NodeFilter<'a, 'f, T, W, I> {
+        pub fn new(graph: &'a WeightedGraph<'a, T, W, I>, keep: |&T|: 'f -> bool)
+            -> NodeFilter<'a, 'f, T, W, I> {
+            NodeFilter { graph: graph, keep: RefCell::new(keep) }
+        }
+    }
+
+    impl<'a, 'f, T, W, I: Iterator<(W, &'a T)>> WeightedGraph<'a, T, W, Neighbours<'a, T, W>>
+        for NodeFilter<'a, 'f, T, W, I> {
+
+        fn neighbours(&'a self, node: &T) -> Neighbours<'a, T, W> {
+            let mut keep = self.keep.borrow_mut();
+            if !(*keep)(node) {
+                return Neighbours { nodes: Vec::new() };
+            }
+            Neighbours { nodes: self.graph.neighbours(node).filter(|&(_, next)| (*keep)(next)).collect() }
+        }
+
+        fn has_node(&'a self, node: &T) -> bool {
+            (*self.keep.borrow_mut())(node) && self.graph.has_node(node)
+        }
+    }
+
+    /// A view of a graph with only the edges for which `keep` returns true,
+    /// given the edge's two ends and its weight, to leave out closed roads
+    /// say. As with `NodeFilter`, nothing is copied.
+    pub struct EdgeFilter<'a, 'f, T, W, I> {
+        graph: &'a WeightedGraph<'a, T, W, I>,
+        keep: RefCell<|&T, &T, &W|: 'f -> bool>
+    }
+
+    impl<'a, 'f, T, W, I: Iterator<(W, &'a T)>> EdgeFilter<'a, 'f, T, W, I> {
+        pub fn new(graph: &'a WeightedGraph<'a, T, W, I>, keep: |&T, &T, &W|: 'f -> bool)
+            -> EdgeFilter<'a, 'f, T, W, I> {
+            EdgeFilter { graph: graph, keep: RefCell::new(keep) }
+        }
+    }
+
+    impl<'a, 'f, T, W, I: Iterator<(W, &'a T)>> WeightedGraph<'a, T, W, Neighbours<'a, T, W>>
+        for EdgeFilter<'a, 'f, T, W, I> {
+
+        fn neighbours(&'a self, node: &T) -> Neighbours<'a, T, W> {
+            let mut keep = self.keep.borrow_mut();
+            Neighbours { nodes: self.graph.neighbours(node).filter(|&(ref w, next)| (*keep)(node, next, w)).collect() }
+        }
+
+        fn has_node(&'a self, node: &T) -> bool {
+            self.graph.has_node(node)
+        }
+    }
+
+    /// A graph whose edges can only be set off along at certain times, such
+    /// as the services of a transit timetable, for finding the earliest
+    /// arrival at a destination (see `earliest_arrival`).
+    ///
+    /// Its nodes are `(node, time)` pairs: being at a node at a given time.
+    /// An edge can be taken from a node at any time inside one of its
+    /// departure windows, and travellers may wait at a node for the next
+    /// window to open. Each state's neighbours are the earliest arrivals
+    /// along each edge, weighted by the time taken including the wait, so
+    /// `dijkstra_search` and `a_star_search` work on it as on any other
+    /// graph. States are created as they are reached and kept until the
+    /// graph is dropped; use `state` to make the first one.
+    pub struct TimeExpandedGraph<T> {
+        edges: HashMap<T, Vec<(T, uint, Vec<(uint, uint)>)>>,
+        arena: TypedArena<(T, uint)>
+    }
+
+    impl<T: Eq + Hash + Clone> TimeExpandedGraph<T> {
+        pub fn new() -> TimeExpandedGraph<T> {
+            TimeExpandedGraph { edges: HashMap::new(), arena: TypedArena::new() }
+        }
+
+        /// Add an edge from `from` to `to` that takes `duration` to travel
+        /// and can be set off along at any time between the start and end
+        /// (inclusive) of one of `windows`.
+        pub fn add_edge(&mut self, from: T, to: T, duration: uint, windows: Vec<(uint, uint)>) {
+            self.edges.find_or_insert(from, Vec::new()).push((to, duration, windows));
+        }
+
+        /// Be at `node` at `time`, as a state that lives as long as the
+        /// graph does.
+        pub fn state<'a>(&'a self, node: T, time: uint) -> &'a (T, uint) {
+            self.arena.alloc((node, time))
+        }
+    }
+
+    impl<'a, T: Eq + Hash + Clone> WeightedGraph<'a, (T, uint), uint, Neighbours<'a, (T, uint), uint>>
+        for TimeExpandedGraph<T> {
+
+        fn neighbours(&'a self, state: &(T, uint)) -> Neighbours<'a, (T, uint), uint> {
+            let &(ref node, time) = state;
+            let mut nodes = Vec::new();
+
+            match self.edges.find(node) {
+                Some(edges) => {
+                    for &(ref to, duration, ref windows) in edges.iter() {
+                        let departure = windows.iter()
+                            .filter(|&&(_, close)| close >= time)
+                            .map(|&(open, _)| max(open, time))
+                            .min();
+                        match departure {
+                            Some(d) => nodes.push((d + duration - time, self.state(to.clone(), d + duration))),
+                            None => ()
+                        }
+                    }
+                },
+                None => ()
+            }
+
+            Neighbours { nodes: nodes }
+        }
+    }
+
+    /// How a `MultiGraph` reports parallel edges between the same two nodes.
+    #[deriving(Clone, PartialEq, Eq, Show)]
+    pub enum ParallelEdges {
+        /// Report every edge, so searches can choose between them. Since
+        /// they all lead to the same node, only the cheapest will ever be on
+        /// a shortest path.
+        AllParallelEdges,
+        /// Report only the cheapest edge to each neighbour, which saves the
+        /// searches from considering edges that can't help them.
+        CheapestParallelEdge
+    }
+
+    /// A directed graph that may have any number of edges between the same
+    /// two nodes, such as a transport network with several lines along the
+    /// same stretch. Each edge is kept when another is added alongside it,
+    /// and `policy` decides how they are reported to the searches.
+    pub struct MultiGraph<T, W> {
+        edges: HashMap<T, Vec<(W, T)>>,
+        policy: ParallelEdges
+    }
+
+    impl<T: Eq + Hash + Clone, W: Clone> MultiGraph<T, W> {
+        pub fn new(policy: ParallelEdges) -> MultiGraph<T, W> {
+            MultiGraph { edges: HashMap::new(), policy: policy }
+        }
+
+        pub fn policy(&self) -> ParallelEdges {
+            self.policy
+        }
+
+        pub fn set_policy(&mut self, policy: ParallelEdges) {
+            self.policy = policy;
+        }
+
+        /// Add a node with no edges, if it isn't already in the graph.
+        pub fn add_node(&mut self, node: T) {
+            self.edges.find_or_insert(node, Vec::new());
+        }
+
+        /// Add an edge from `from` to `to`, alongside any that are already
+        /// there.
+        pub fn add_edge(&mut self, from: T, to: T, weight: W) {
+            self.add_node(to.clone());
+            self.edges.find_or_insert(from, Vec::new()).push((weight, to));
+        }
+
+        /// The weights of every edge from `from` to `to`, in the order they
+        /// were added.
+        pub fn edges_between(&self, from: &T, to: &T) -> Vec<W> {
+            match self.edges.find(from) {
+                Some(edges) => edges.iter().filter(|&&(_, ref t)| t == to).map(|&(ref w, _)| w.clone()).collect(),
+                None => Vec::new()
+            }
+        }
+
+        /// Every node in the graph, in no particular order.
+        pub fn nodes<'a>(&'a self) -> Vec<&'a T> {
+            self.edges.keys().collect()
+        }
+    }
+
+    impl<'a, T: Eq + Hash, W: Clone + Ord> WeightedGraph<'a, T, W, Neighbours<'a, T, W>> for MultiGraph<T, W> {
+        fn neighbours(&'a self, node: &T) -> Neighbours<'a, T, W> {
+            let edges = match self.edges.find(node) {
+                Some(edges) => edges,
+                None => return Neighbours { nodes: Vec::new() }
+            };
+
+            if self.policy == AllParallelEdges {
+                return Neighbours { nodes: edges.iter().map(|&(ref w, ref v)| (w.clone(), v)).collect() };
+            }
+
+            let mut cheapest: HashMap<&'a T, W> = HashMap::new();
+            for &(ref w, ref v) in edges.iter() {
+                let better = cheapest.find(&v).map_or(true, |c| w < c);
+                if better {
+                    cheapest.insert(v, w.clone());
+                }
+            }
+            Neighbours { nodes: cheapest.move_iter().map(|(v, w)| (w, v)).collect() }
+        }
+
+        fn has_node(&'a self, node: &T) -> bool {
+            self.edges.contains_key(node)
+        }
+    }
+
+    /// The cost of an orthogonal move on an eight-way grid.
+    pub static ORTHOGONAL_COST: uint = 10;
+
+    /// The cost of a diagonal move on an eight-way grid, approximating
+    /// `ORTHOGONAL_COST` times the square root of two.
+    pub static DIAGONAL_COST: uint = 14;
+
+    static ORTHOGONAL_MOVES: [(int, int), ..4] = [(-1, 0), (0, -1), (1, 0), (0, 1)];
+
+    static ALL_MOVES: [(int, int), ..8] = [(-1, 0), (0, -1), (1, 0), (0, 1),
+                                           (-1, -1), (1, -1), (1, 1), (-1, 1)];
+
+    /// The moves that are allowed between cells in a `GridGraph`.
+    #[deriving(Clone, PartialEq, Eq, Show)]
+    pub enum Connectivity {
+        /// Moves to the cells directly above, below, and to either side, each
+        /// with a cost of one.
+        FourWay,
+        /// Moves to all eight surrounding cells, with orthogonal moves costing
+        /// `ORTHOGONAL_COST` and diagonal moves costing `DIAGONAL_COST`.
+        EightWay,
+        /// As `EightWay`, but a diagonal move is only allowed when both of the
+        /// orthogonal cells it passes between are walkable, so that paths
+        /// never cut across the corner of a blocked cell.
+        EightWayNoCorners
+    }
+
+    /// A two-dimensional grid of cells, some of which may be blocked.
+    ///
+    /// Nodes are `(x, y)` coordinates, with `(0, 0)` in one corner. Which
+    /// walkable cells are connected, and at what cost, is determined by the
+    /// grid's `Connectivity`.
+    pub struct GridGraph {
+        width: uint,
+        height: uint,
+        connectivity: Connectivity,
+        cells: Vec<(uint, uint)>,
+        blocked: HashSet<(uint, uint)>
+    }
+
+    impl GridGraph {
+        /// Create a four-way connected grid.
+        pub fn new(width: uint, height: uint, blocked: HashSet<(uint, uint)>) -> GridGraph {
+            GridGraph::with_connectivity(width, height, blocked, FourWay)
+        }
+
+        pub fn with_connectivity(width: uint, height: uint, blocked: HashSet<(uint, uint)>,
+                                 connectivity: Connectivity) -> GridGraph {
+            let mut cells = Vec::with_capacity(width * height);
+            for y in range(0, height) {
+                for x in range(0, width) {
+                    cells.push((x, y));
+                }
+            }
+
+            GridGraph {
+                width: width,
+                height: height,
+                connectivity: connectivity,
+                cells: cells,
+                blocked: blocked
+            }
+        }
+
+        pub fn width(&self) -> uint {
+            self.width
+        }
+
+        pub fn height(&self) -> uint {
+            self.height
+        }
+
+        pub fn connectivity(&self) -> Connectivity {
+            self.connectivity
+        }
+
+        /// Check whether a cell lies within the grid and is not blocked.
+        pub fn is_walkable(&self, cell: &(uint, uint)) -> bool {
+            let &(x, y) = cell;
+            x < self.width && y < self.height && !self.blocked.contains(cell)
+        }
+
+        /// Check whether a straight line between the centres of two cells
+        /// passes only through walkable cells.
+        ///
+        /// Where the line passes exactly through a corner shared by four
+        /// cells, both of the cells beside it must be walkable too, so that
+        /// lines never squeeze between two diagonally adjacent obstacles.
+        pub fn has_line_of_sight(&self, from: &(uint, uint), to: &(uint, uint)) -> bool {
+            let (&(x0, y0), &(x1, y1)) = (from, to);
+            let (nx, ny) = ((x1 as int - x0 as int).abs(), (y1 as int - y0 as int).abs());
+            let sx = if x1 > x0 { 1 } else { -1 };
+            let sy = if y1 > y0 { 1 } else { -1 };
+
+            if !self.is_walkable(from) {
+                return false;
+            }
+
+            let (mut x, mut y) = (x0 as int, y0 as int);
+            let (mut ix, mut iy) = (0i, 0i);
+
+            // Step through every cell the line touches, choosing at each
+            // step whichever of the horizontal or vertical cell boundaries
+            // the line crosses first.
+            while ix < nx || iy < ny {
+                let decision = (1 + 2 * ix) * ny - (1 + 2 * iy) * nx;
+
+                if decision == 0 {
+                    if !self.is_walkable(&((x + sx) as uint, y as uint))
+                        || !self.is_walkable(&(x as uint, (y + sy) as uint)) {
+                        return false;
+                    }
+
+                    x += sx;
+                    y += sy;
+                    ix += 1;
+                    iy += 1;
+                } else if decision < 0 {
+                    x += sx;
+                    ix += 1;
+                } else {
+                    y += sy;
+                    iy += 1;
+                }
+
+                if !self.is_walkable(&(x as uint, y as uint)) {
+                    return false;
+                }
+            }
+
+            true
+        }
+
+        /// Get a reference to the grid's own copy of a cell, for passing to
+        /// searches that need nodes borrowed from the graph itself.
+        pub fn find_cell<'a>(&'a self, cell: &(uint, uint)) -> Option<&'a (uint, uint)> {
+            let &(x, y) = cell;
+            if x < self.width && y < self.height { Some(self.cell(x, y)) } else { None }
+        }
+
+        /// Get a reference to the grid's own copy of a cell, so that it can be
+        /// handed out by `neighbours()`.
+        fn cell<'a>(&'a self, x: uint, y: uint) -> &'a (uint, uint) {
+            self.cells.get(y * self.width + x)
+        }
+    }
+
+    impl<'a> WeightedGraph<'a, (uint, uint), uint, Neighbours<'a, (uint, uint), uint>> for GridGraph {
+        fn neighbours(&'a self, node: &(uint, uint)) -> Neighbours<'a, (uint, uint), uint> {
+            let mut nodes = Vec::new();
+
+            if !self.is_walkable(node) {
+                return Neighbours { nodes: nodes };
+            }
+
+            let moves = match self.connectivity {
+                FourWay => ORTHOGONAL_MOVES.as_slice(),
+                EightWay | EightWayNoCorners => ALL_MOVES.as_slice()
+            };
+
+            let &(x, y) = node;
+            for &(dx, dy) in moves.iter() {
+                let (nx, ny) = (x as int + dx, y as int + dy);
+                if nx < 0 || ny < 0 {
+                    continue;
+                }
+
+                let (nx, ny) = (nx as uint, ny as uint);
+                if !self.is_walkable(&(nx, ny)) {
+                    continue;
+                }
+
+                let diagonal = dx != 0 && dy != 0;
+                if diagonal && self.connectivity == EightWayNoCorners
+                    && !(self.is_walkable(&(nx, y)) && self.is_walkable(&(x, ny))) {
+                    continue;
+                }
+
+                let cost = match self.connectivity {
+                    FourWay => 1,
+                    _ if diagonal => DIAGONAL_COST,
+                    _ => ORTHOGONAL_COST
+                };
+
+                nodes.push((cost, self.cell(nx, ny)));
+            }
+
+            Neighbours { nodes: nodes }
+        }
+
+        fn has_node(&'a self, node: &(uint, uint)) -> bool {
+            self.find_cell(node).is_some()
+        }
+    }
+
+    impl<'a> Predecessors<'a, (uint, uint), uint, Neighbours<'a, (uint, uint), uint>> for GridGraph {
+        fn predecessors(&'a self, node: &(uint, uint)) -> Neighbours<'a, (uint, uint), uint> {
+            self.neighbours(node)
+        }
+
+        fn contains_node(&'a self, node: &(uint, uint)) -> bool {
+            self.find_cell(node).is_some()
+        }
+    }
+
+    /// A grid in which every cell has its own movement cost, such as 3 for
+    /// swamp and 0.5 for road, or no cost at all if it can't be entered.
+    ///
+    /// Moving between two cells costs the length of the move (one, or the
+    /// square root of two for a diagonal) times the average of their costs,
+    /// so that half of each move is spent in each cell. Weights are
+    /// `OrderedFloat`s. Nodes and connectivity work as for `GridGraph`. For
+    /// an admissible `a_star_search` heuristic, scale the usual distance by
+    /// the cheapest cost in the grid.
+    pub struct TerrainGrid {
+        width: uint,
+        height: uint,
+        connectivity: Connectivity,
+        cells: Vec<(uint, uint)>,
+        costs: Vec<Option<f64>>
+    }
+
+    impl TerrainGrid {
+        /// Create a grid from each cell's cost, row by row. Cells beyond the
+        /// end of `costs` are impassable.
+        pub fn new(width: uint, height: uint, costs: Vec<Option<f64>>,
+                   connectivity: Connectivity) -> TerrainGrid {
+            let mut cells = Vec::with_capacity(width * height);
+            for y in range(0, height) {
+                for x in range(0, width) {
+                    cells.push((x, y));
+                }
+            }
+
+            let mut costs = costs;
+            costs.truncate(width * height);
+            while costs.len() < width * height {
+                costs.push(None);
+            }
+
+            TerrainGrid { width: width, height: height, connectivity: connectivity, cells: cells, costs: costs }
+        }
+
+        pub fn width(&self) -> uint {
+            self.width
+        }
+
+        pub fn height(&self) -> uint {
+            self.height
+        }
+
+        /// The cost of moving through a cell, or `None` if it is impassable
+        /// or outside the grid.
+        pub fn cost(&self, cell: &(uint, uint)) -> Option<f64> {
+            let &(x, y) = cell;
+            if x < self.width && y < self.height { *self.costs.get(y * self.width + x) } else { None }
+        }
+
+        /// Change the cost of a cell, returning false if it is outside the
+        /// grid.
+        pub fn set_cost(&mut self, cell: &(uint, uint), cost: Option<f64>) -> bool {
+            let &(x, y) = cell;
+            if x >= self.width || y >= self.height {
+                return false;
+            }
+            *self.costs.get_mut(y * self.width + x) = cost;
+            true
+        }
+
+        /// Get a reference to the grid's own copy of a cell, for passing to
+        /// searches that need nodes borrowed from the graph itself.
+        pub fn find_cell<'a>(&'a self, cell: &(uint, uint)) -> Option<&'a (uint, uint)> {
+            let &(x, y) = cell;
+            if x < self.width && y < self.height { Some(self.cells.get(y * self.width + x)) } else { None }
+        }
+    }
+
+    impl<'a> WeightedGraph<'a, (uint, uint), OrderedFloat<f64>, Neighbours<'a, (uint, uint), OrderedFloat<f64>>>
+        for TerrainGrid {
+
+        fn neighbours(&'a self, node: &(uint, uint)) -> Neighbours<'a, (uint, uint), OrderedFloat<f64>> {
+            let mut nodes = Vec::new();
+
+            let here = match self.cost(node) {
+                Some(cost) => cost,
+                None => return Neighbours { nodes: nodes }
+            };
+
+            let moves = match self.connectivity {
+                FourWay => ORTHOGONAL_MOVES.as_slice(),
+                EightWay | EightWayNoCorners => ALL_MOVES.as_slice()
+            };
+
+            let &(x, y) = node;
+            for &(dx, dy) in moves.iter() {
+                let (nx, ny) = (x as int + dx, y as int + dy);
+                if nx < 0 || ny < 0 {
+                    continue;
+                }
+
+                let (nx, ny) = (nx as uint, ny as uint);
+                let there = match self.cost(&(nx, ny)) {
+                    Some(cost) => cost,
+                    None => continue
+                };
+
+                let diagonal = dx != 0 && dy != 0;
+                if diagonal && self.connectivity == EightWayNoCorners
+                    && !(self.cost(&(nx, y)).is_some() && self.cost(&(x, ny)).is_some()) {
+                    continue;
+                }
+
+                let length = if diagonal { SQRT2 } else { 1.0 };
+                nodes.push((OrderedFloat(length * (here + there) / 2.0), self.cells.get(ny * self.width + nx)));
+            }
+
+            Neighbours { nodes: nodes }
+        }
+
+        fn has_node(&'a self, node: &(uint, uint)) -> bool {
+            self.find_cell(node).is_some()
+        }
+    }
+
+    impl<'a> Predecessors<'a, (uint, uint), OrderedFloat<f64>, Neighbours<'a, (uint, uint), OrderedFloat<f64>>>
+        for TerrainGrid {
+
+        fn predecessors(&'a self, node: &(uint, uint)) -> Neighbours<'a, (uint, uint), OrderedFloat<f64>> {
+            self.neighbours(node)
+        }
+
+        fn contains_node(&'a self, node: &(uint, uint)) -> bool {
+            self.find_cell(node).is_some()
+        }
+    }
+
+    /// How a `NavMesh` prices a move between two neighbouring polygons.
+    #[deriving(Clone, PartialEq, Eq, Show)]
+    pub enum NavMeshCost {
+        /// The straight-line distance between the polygons' centroids.
+        CentroidDistance,
+        /// The distance from the first centroid to the midpoint of the edge
+        /// the polygons share, and on to the second centroid. This follows
+        /// the route a walker would take more closely around long, thin
+        /// polygons.
+        PortalDistance
+    }
+
+    /// A navigation mesh: a walkable area made up of convex polygons, such
+    /// as those exported by a level editor.
+    ///
+    /// Nodes are polygon numbers, in the order the polygons were given, and
+    /// two polygons are neighbours if they share an edge, meaning two
+    /// consecutive corners with the same vertex numbers. Use `locate` to find
+    /// the polygon containing a point, and `portal` to get the shared edge
+    /// that a path crosses from one polygon to the next.
+    pub struct NavMesh {
+        vertices: Vec<(f64, f64)>,
+        polygons: Vec<Vec<uint>>,
+        centroids: Vec<(f64, f64)>,
+        ids: Vec<uint>,
+        edges: Vec<Vec<(OrderedFloat<f64>, uint)>>,
+        // The shared edge between each pair of neighbours, as vertex numbers,
+        // keyed with the lower polygon number first.
+        portals: HashMap<(uint, uint), (uint, uint)>
+    }
+
+    impl NavMesh {
+        /// Build a mesh from its vertices and its polygons, each given as
+        /// the numbers of its corners in order around it.
+        pub fn new(vertices: Vec<(f64, f64)>, polygons: Vec<Vec<uint>>, costs: NavMeshCost) -> NavMesh {
+            let centroids: Vec<(f64, f64)> = polygons.iter().map(|corners| {
+                let (sx, sy) = corners.iter().fold((0.0, 0.0), |(sx, sy), &v| {
+                    let &(x, y) = vertices.get(v);
+                    (sx + x, sy + y)
+                });
+                let n = corners.len() as f64;
+                (sx / n, sy / n)
+            }).collect();
+
+            // Find the polygons on either side of every edge.
+            let mut sides: HashMap<(uint, uint), Vec<uint>> = HashMap::new();
+            for (i, corners) in polygons.iter().enumerate() {
+                for k in range(0, corners.len()) {
+                    let (a, b) = (*corners.get(k), *corners.get((k + 1) % corners.len()));
+                    sides.find_or_insert((min(a, b), max(a, b)), Vec::new()).push(i);
+                }
+            }
+
+            let mut edges = Vec::from_fn(polygons.len(), |_| Vec::new());
+            let mut portals = HashMap::new();
+            for (&(a, b), owners) in sides.iter() {
+                let (ax, ay) = *vertices.get(a);
+                let (bx, by) = *vertices.get(b);
+                let midpoint = ((ax + bx) / 2.0, (ay + by) / 2.0);
+
+                for (k, &i) in owners.iter().enumerate() {
+                    for &j in owners.slice_from(k + 1).iter() {
+                        let (ci, cj) = (*centroids.get(i), *centroids.get(j));
+                        let cost = match costs {
+                            CentroidDistance => distance(ci, cj),
+                            PortalDistance => distance(ci, midpoint) + distance(midpoint, cj)
+                        };
+                        edges.get_mut(i).push((OrderedFloat(cost), j));
+                        edges.get_mut(j).push((OrderedFloat(cost), i));
+                        portals.insert((min(i, j), max(i, j)), (a, b));
+                    }
+                }
+            }
+
+            NavMesh {
+                ids: range(0, polygons.len()).collect(),
+                vertices: vertices,
+                polygons: polygons,
+                centroids: centroids,
+                edges: edges,
+                portals: portals
+            }
+        }
+
+        pub fn len(&self) -> uint {
+            self.polygons.len()
+        }
+
+        pub fn centroid(&self, polygon: uint) -> (f64, f64) {
+            *self.centroids.get(polygon)
+        }
+
+        /// The mesh's own copy of a polygon number, for passing to searches.
+        pub fn find_polygon<'a>(&'a self, polygon: uint) -> Option<&'a uint> {
+            if polygon < self.ids.len() { Some(self.ids.get(polygon)) } else { None }
+        }
+
+        /// The polygon containing `point`, if any. Points on an edge shared
+        /// by two polygons belong to whichever comes first.
+        pub fn locate<'a>(&'a self, point: (f64, f64)) -> Option<&'a uint> {
+            let (px, py) = point;
+            for (i, corners) in self.polygons.iter().enumerate() {
+                let mut sign = 0.0;
+                let mut inside = true;
+                for k in range(0, corners.len()) {
+                    let (ax, ay) = *self.vertices.get(*corners.get(k));
+                    let (bx, by) = *self.vertices.get(*corners.get((k + 1) % corners.len()));
+                    // Which side of this edge the point is on; it must be
+                    // the same side (or on the edge) for every edge.
+                    let side = (bx - ax) * (py - ay) - (by - ay) * (px - ax);
+                    if side * sign < 0.0 {
+                        inside = false;
+                        break;
+                    }
+                    if side != 0.0 {
+                        sign = side;
+                    }
+                }
+                if inside {
+                    return Some(self.ids.get(i));
+                }
+            }
+            None
+        }
+
+        /// The endpoints of the edge shared by two neighbouring polygons.
+        pub fn portal(&self, a: uint, b: uint) -> Option<((f64, f64), (f64, f64))> {
+            self.portals.find(&(min(a, b), max(a, b)))
+                .map(|&(u, v)| (*self.vertices.get(u), *self.vertices.get(v)))
+        }
+    }
+
+    fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+        let ((ax, ay), (bx, by)) = (a, b);
+        ((ax - bx) * (ax - bx) + (ay - by) * (ay - by)).sqrt()
+    }
+
+    impl<'a> WeightedGraph<'a, uint, OrderedFloat<f64>, Neighbours<'a, uint, OrderedFloat<f64>>> for NavMesh {
+        fn neighbours(&'a self, node: &uint) -> Neighbours<'a, uint, OrderedFloat<f64>> {
+            if *node >= self.edges.len() {
+                return Neighbours { nodes: Vec::new() };
+            }
+            let nodes = self.edges.get(*node).iter().map(|&(cost, to)| (cost, self.ids.get(to))).collect();
+            Neighbours { nodes: nodes }
+        }
+
+        fn has_node(&'a self, node: &uint) -> bool {
+            *node < self.edges.len()
+        }
+    }
+
+    static HEX_MOVES: [(int, int), ..6] = [(1, 0), (1, -1), (0, -1), (-1, 0), (-1, 1), (0, 1)];
+
+    /// A map of hexagonal cells.
+    ///
+    /// Nodes are axial `(q, r)` coordinates, where the third cube coordinate
+    /// is implicitly `-q - r`. Every cell is connected to the (up to) six
+    /// cells that share an edge with it, and every move has a cost of one.
+    pub struct HexGrid {
+        cells: Vec<(int, int)>,
+        index: HashMap<(int, int), uint>
+    }
+
+    impl HexGrid {
+        /// Create a hex grid made up of exactly the given cells.
+        pub fn new(cells: Vec<(int, int)>) -> HexGrid {
+            let mut index = HashMap::with_capacity(cells.len());
+            for (i, &cell) in cells.iter().enumerate() {
+                index.insert(cell, i);
+            }
+
+            HexGrid { cells: cells, index: index }
+        }
+
+        /// Create a hexagon-shaped grid of the given radius centred on
+        /// `(0, 0)`, leaving out any blocked cells.
+        pub fn hexagon(radius: uint, blocked: &HashSet<(int, int)>) -> HexGrid {
+            let radius = radius as int;
+            let mut cells = Vec::new();
+
+            for q in range_inclusive(-radius, radius) {
+                for r in range_inclusive(max(-radius, -q - radius), min(radius, -q + radius)) {
+                    if !blocked.contains(&(q, r)) {
+                        cells.push((q, r));
+                    }
+                }
+            }
+
+            HexGrid::new(cells)
+        }
+
+        pub fn contains(&self, cell: &(int, int)) -> bool {
+            self.index.contains_key(cell)
+        }
+    }
+
+    impl<'a> WeightedGraph<'a, (int, int), uint, Neighbours<'a, (int, int), uint>> for HexGrid {
+        fn neighbours(&'a self, node: &(int, int)) -> Neighbours<'a, (int, int), uint> {
+            let mut nodes = Vec::new();
+
+            if !self.contains(node) {
+                return Neighbours { nodes: nodes };
+            }
+
+            let &(q, r) = node;
+            for &(dq, dr) in HEX_MOVES.iter() {
+                match self.index.find(&(q + dq, r + dr)) {
+                    Some(&i) => nodes.push((1u, self.cells.get(i))),
+                    None => ()
+                }
+            }
+
+            Neighbours { nodes: nodes }
+        }
+
+        fn has_node(&'a self, node: &(int, int)) -> bool {
+            self.contains(node)
+        }
+    }
+
+    impl<'a> Predecessors<'a, (int, int), uint, Neighbours<'a, (int, int), uint>> for HexGrid {
+        fn predecessors(&'a self, node: &(int, int)) -> Neighbours<'a, (int, int), uint> {
+            self.neighbours(node)
+        }
+
+        fn contains_node(&'a self, node: &(int, int)) -> bool {
+            self.contains(node)
+        }
+    }
+
+    /// The number of moves between two cells of a `HexGrid`, ignoring any
+    /// cells that are missing from the grid.
+    ///
+    /// This never overestimates the true distance, so it is an admissible
+    /// heuristic for `a_star_search`.
+    pub fn hex_distance(a: &(int, int), b: &(int, int)) -> uint {
+        let (&(aq, ar), &(bq, br)) = (a, b);
+        let (dq, dr) = (aq - bq, ar - br);
+        ((dq.abs() + dr.abs() + (dq + dr).abs()) / 2) as uint
+    }
+
+    /// A simple interator over a node's neighbours in a weighted graph.
+    ///
+    /// Each call to `next()` produces a tuple of the edge's weight and a
+    /// pointer to the neighbouring node.
+    ///
+    /// ```rust
+    /// use pathfinding::graph::Graph;
+    ///
+    /// fn print_neighbours<T: std::fmt::Show>(node: &T, graph: &Graph<T>) {
+    ///     let neighbours = graph.neighbours(node).collect();
+    ///     println!("Neighbours: {}", neighbours);
+    /// }
+    /// ```
+    pub struct Neighbours<'a, T, W> {
+        nodes: Vec<(W, &'a T)>
+    }
+
+    impl<'a, T, W> Iterator<(W, &'a T)> for Neighbours<'a, T, W> {
+        fn next(&mut self) -> Option<(W, &'a T)> {
+            self.nodes.pop()
+        }
+
+        fn size_hint(&self) -> (uint, Option<uint>) {
+            (self.nodes.len(), Some(self.nodes.len()))
+        }
+    }
+}
+
+/// The ways in which a search can fail, as opposed to simply finding that
+/// there is no path.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum SearchError {
+    /// An edge with a negative weight was encountered by an algorithm that
+    /// requires all weights to be non-negative.
+    NegativeWeight,
+    /// An edge was heavier than the maximum weight the algorithm was told to
+    /// expect.
+    WeightTooLarge,
+    /// The two paths asked for by `suurballe_search` couldn't be found,
+    /// because every pair of routes between the endpoints overlaps.
+    NoDisjointPaths,
+    /// The node the search was asked to start from isn't in the graph.
+    StartNotFound,
+    /// The node the search was asked to reach isn't in the graph.
+    GoalNotFound
+}
+
+/// Figures for comparing algorithms and tuning heuristics, reported whether
+/// or not a path is found by the `_with_stats` variants of the Dijkstra, A*,
+/// weighted A*, IDA*, fringe, breadth-first and bidirectional searches.
+#[deriving(Clone, Show)]
+pub struct SearchStats {
+    /// How many nodes were taken off the frontier and had their neighbours
+    /// looked at.
+    pub nodes_expanded: uint,
+    /// How many times a node was put on the frontier, counting each time a
+    /// cheaper route to it was found.
+    pub nodes_generated: uint,
+    /// The most nodes the frontier held at once.
+    pub max_frontier_len: uint,
+    /// How long the search took.
+    pub duration: Duration
+}
+
+/// What one of the `_with_stats` searches found.
+#[deriving(Clone, Show)]
+pub struct SearchResult<'a, T, W> {
+    /// The path found, including both endpoints, and its cost, or `None` if
+    /// the goal can't be reached.
+    pub path: Option<(Vec<&'a T>, W)>,
+    /// How much work the search did, including when it failed.
+    pub stats: SearchStats
+}
+
+/// The outcome of a search that may give up before it reaches the goal.
+#[deriving(PartialEq, Show)]
+pub enum LimitedSearchResult<'a, T, W> {
+    /// The goal was reached by the enclosed path, at the enclosed cost.
+    Reached(Vec<&'a T>, W),
+    /// The search gave up because every route left to try would cost more
+    /// than the limit it was given.
+    OverCostLimit,
+    /// The search gave up after expanding as many nodes as it was allowed.
+    /// The enclosed path leads to the node expanded so far that looked
+    /// closest to the goal, along with its cost, for moving towards the goal
+    /// while the search carries on another time.
+    OverExpansionLimit(Vec<&'a T>, W),
+    /// The search ran out of time. As with `OverExpansionLimit`, the path
+    /// leads to the node that looked closest to the goal.
+    TimedOut(Vec<&'a T>, W),
+    /// The search was stopped from outside through a `CancellationToken`.
+    Cancelled,
+    /// The goal can't be reached from the start at all. The enclosed path
+    /// leads to the reachable node the heuristic put closest to the goal,
+    /// so that an agent can at least head towards it.
+    NoRoute(Vec<&'a T>, W)
+}
+
+/// The points at which a search should give up, as passed to
+/// `dijkstra_search_limited` and `a_star_search_limited`. Start with `none`
+/// and add whichever limits are needed:
+///
+/// ```ignore
+/// let limits = SearchLimits::none().with_cost(movement).with_expansions(500);
+/// ```
+pub struct SearchLimits<W> {
+    cost: Option<W>,
+    expansions: Option<uint>,
+    time: Option<Duration>,
+    cancellation: Option<CancellationToken>
+}
+
+impl<W> SearchLimits<W> {
+    pub fn none() -> SearchLimits<W> {
+        SearchLimits { cost: None, expansions: None, time: None, cancellation: None }
+    }
+
+    /// Give up once every route left to try would cost more than `limit`.
+    pub fn with_cost(self, limit: W) -> SearchLimits<W> {
+        SearchLimits { cost: Some(limit), ..self }
+    }
+
+    /// Give up after expanding `limit` nodes.
+    pub fn with_expansions(self, limit: uint) -> SearchLimits<W> {
+        SearchLimits { expansions: Some(limit), ..self }
+    }
+
+    /// Give up once the search has been running for `limit`. The clock is
+    /// only checked every `DEADLINE_CHECK_INTERVAL` expansions, so a search
+    /// may overrun slightly.
+    pub fn with_time(self, limit: Duration) -> SearchLimits<W> {
+        SearchLimits { time: Some(limit), ..self }
+    }
+
+    /// Give up as soon as `token` is cancelled, which is checked before each
+    /// expansion.
+    pub fn with_cancellation(self, token: CancellationToken) -> SearchLimits<W> {
+        SearchLimits { cancellation: Some(token), ..self }
+    }
+}
+
+/// A flag that can be raised from another task to stop a search cleanly.
+/// Clones share the same flag, so keep one and give another to the search:
+///
+/// ```ignore
+/// let token = CancellationToken::new();
+/// let limits = SearchLimits::none().with_cancellation(token.clone());
+/// // ... and later, from elsewhere:
+/// token.cancel();
+/// ```
+#[deriving(Clone)]
+pub struct CancellationToken {
+    flag: Arc<AtomicBool>
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken { flag: Arc::new(AtomicBool::new(false)) }
+    }
+
+    pub fn cancel(&self) {
+        self.flag.store(true, SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(SeqCst)
+    }
+}
+
+/// How many nodes a search with a time limit expands between looks at the
+/// clock.
+pub static DEADLINE_CHECK_INTERVAL: uint = 64;
+
+/// The counts behind a `SearchResult`, kept as a search goes.
+struct SearchCounters {
+    expanded: uint,
+    generated: uint,
+    max_frontier: uint
+}
+
+impl SearchCounters {
+    fn new() -> SearchCounters {
+        SearchCounters { expanded: 0, generated: 0, max_frontier: 0 }
+    }
+
+    fn generated(&mut self, frontier_len: uint) {
+        self.generated += 1;
+        self.max_frontier = max(self.max_frontier, frontier_len);
+    }
+
+    fn stats(&self, started: u64) -> SearchStats {
+        SearchStats {
+            nodes_expanded: self.expanded,
+            nodes_generated: self.generated,
+            max_frontier_len: self.max_frontier,
+            duration: Duration::nanoseconds((time::precise_time_ns() - started) as i64)
+        }
+    }
+}
+
+/// Check that `start`, and `goal` if there is one, are in the graph before
+/// searching it, so that a typo isn't mistaken for an unreachable goal.
+///
+/// Every search over a `WeightedGraph` does this. The exceptions are the
+/// precomputed structures, such as `ContractionHierarchy`, `ArcFlags`,
+/// `JumpPointTable`, `HierarchicalGrid`, `WaypointGraph`, `FlowNetwork` and
+/// `SharedGraph`, whose queries answer `None` (or an empty result) for
+/// nodes they weren't built with and don't look at the graph again; and
+/// `prim` and `RandomWalk`, which have no goal and simply stop where `start`
+/// leads nowhere.
+fn check_endpoints<'a, T, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &T, goal: Option<&T>) -> Result<(), SearchError> {
+
+    if !graph.has_node(start) {
+        Err(StartNotFound)
+    } else if goal.map_or(false, |g| !graph.has_node(g)) {
+        Err(GoalNotFound)
+    } else {
+        Ok(())
+    }
+}
+
+/// The machinery behind `breadth_first_search` and its variants.
+///
+/// Stops at the first node for which `is_goal` returns true, returning it
+/// along with its depth. The node each one was first reached from is
+/// recorded in `came_from`, and the work done is counted in `counters`.
+fn breadth_first_visit<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, is_goal: |&'a T| -> bool,
+     visit: &mut Option<|&'a T, uint|>, came_from: &mut HashMap<&'a T, &'a T>,
+     counters: &mut SearchCounters) -> Option<(&'a T, uint)> {
+
+    let mut frontier = DList::new();
+
+    frontier.push_back((start, 0u));
+    came_from.insert(start, start);
+
+    loop {
+        // Break the loop when we run out of new nodes.
+        let (current, depth) = match frontier.pop_front() {
+            Some(entry) => entry,
+            None => break
+        };
+
+        match *visit {
+            Some(ref mut f) => (*f)(current, depth),
+            None => ()
+        }
+
+        // Check if we've reached a goal and break out early if we have.
+        if is_goal(current) {
+            return Some((current, depth));
+        }
+        counters.expanded += 1;
+
+        for (_, next) in graph.neighbours(current) {
+            // Ensure that we only visit each connected node once by
+            // keeping track of previously visited nodes.
+            if came_from.contains_key(&next) {
+                continue;
+            } else {
+                came_from.insert(next, current);
+                frontier.push_back((next, depth + 1));
+                counters.generated(frontier.len());
+            }
+        }
+    }
+
+    None
+}
+
+/// What a `breadth_first_search` found.
+#[deriving(Clone, Show)]
+pub struct BreadthFirstResult<'a, T> {
+    /// Every node that was expanded, in the order they were expanded.
+    pub visited: Vec<&'a T>,
+    /// The path to the goal with the fewest edges, including both
+    /// endpoints, along with its number of edges. This is `None` if no goal
+    /// was given or it couldn't be reached.
+    pub path: Option<(Vec<&'a T>, uint)>
+}
+
+/// Search exhaustively over the graph, starting at the given node.
+///
+/// If `goal` is specified, stop searching if it is reached. If `visit` is
+/// specified, it is called with each node as it is expanded, along with the
+/// number of edges between it and `start`.
+pub fn breadth_first_search<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: Option<&'a T>,
+     mut visit: Option<|&'a T, uint|>) -> Result<BreadthFirstResult<'a, T>, SearchError> {
+
+    try!(check_endpoints(graph, start, goal));
+    let mut came_from = HashMap::new();
+    let mut visited = Vec::new();
+
+    let found = {
+        let mut record = Some(|node: &'a T, depth: uint| {
+            visited.push(node);
+            match visit {
+                Some(ref mut f) => (*f)(node, depth),
+                None => ()
+            }
+        });
+        breadth_first_visit(graph, start, |n| goal.map_or(false, |g| g == n), &mut record, &mut came_from,
+                            &mut SearchCounters::new())
+    };
+
+    Ok(BreadthFirstResult {
+        visited: visited,
+        path: found.map(|(node, depth)| (reconstruct_path(&came_from, start, node), depth))
+    })
+}
+
+/// As `breadth_first_search`, but also report how much work the search did
+/// and how long it took, even if no path was found. The cost of the path is
+/// its number of edges.
+pub fn breadth_first_search_with_stats<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T)
+    -> Result<SearchResult<'a, T, uint>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+    let started = time::precise_time_ns();
+    let mut counters = SearchCounters::new();
+    let mut came_from = HashMap::new();
+    let found = breadth_first_visit(graph, start, |n| n == goal, &mut None, &mut came_from, &mut counters);
+
+    Ok(SearchResult {
+        path: found.map(|(node, depth)| (reconstruct_path(&came_from, start, node), depth)),
+        stats: counters.stats(started)
+    })
+}
+
+/// As `breadth_first_search`, but stop at the first node for which `is_goal`
+/// returns true, such as any cell next to water, returning it along with its
+/// depth.
+pub fn breadth_first_search_where<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, is_goal: |&'a T| -> bool,
+     mut visit: Option<|&'a T, uint|>) -> Result<Option<(&'a T, uint)>, SearchError> {
+
+    try!(check_endpoints(graph, start, None));
+    Ok(breadth_first_visit(graph, start, is_goal, &mut visit, &mut HashMap::new(), &mut SearchCounters::new()))
+}
+
+/// As `breadth_first_search`, but stop as soon as any of `goals` is reached,
+/// returning the one that was found first along with its depth, or `None` if
+/// none of them can be reached.
+pub fn breadth_first_search_any<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goals: &[&'a T],
+     mut visit: Option<|&'a T, uint|>) -> Result<Option<(&'a T, uint)>, SearchError> {
+
+    try!(check_endpoints(graph, start, None));
+    for &goal in goals.iter() {
+        try!(check_endpoints(graph, start, Some(goal)));
+    }
+
+    let goals: HashSet<&'a T> = goals.iter().map(|&g| g).collect();
+    Ok(breadth_first_visit(graph, start, |n| goals.contains(&n), &mut visit, &mut HashMap::new(),
+                           &mut SearchCounters::new()))
+}
+
+/// The nodes reachable from a start node in breadth-first order, as made by
+/// `bfs_iter`.
+pub struct BreadthFirstIter<'a, T, W, I> {
+    graph: &'a graph::WeightedGraph<'a, T, W, I>,
+    frontier: DList<(&'a T, uint)>,
+    visited: HashSet<&'a T>
+}
+
+/// Walk over the nodes reachable from `start` in breadth-first order, each
+/// along with the number of edges between it and `start`. Nodes are only
+/// expanded as they are asked for, so the traversal can be abandoned at any
+/// point without exploring the rest of the graph:
+///
+/// ```ignore
+/// for (cell, depth) in try!(bfs_iter(&grid, start)) {
+///     if depth > range || is_enemy(cell) { break; }
+/// }
+/// ```
+pub fn bfs_iter<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T)
+    -> Result<BreadthFirstIter<'a, T, W, I>, SearchError> {
+
+    try!(check_endpoints(graph, start, None));
+    let mut frontier = DList::new();
+    let mut visited = HashSet::new();
+
+    frontier.push_back((start, 0u));
+    visited.insert(start);
+
+    Ok(BreadthFirstIter { graph: graph, frontier: frontier, visited: visited })
+}
+
+impl<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>> Iterator<(&'a T, uint)> for BreadthFirstIter<'a, T, W, I> {
+    fn next(&mut self) -> Option<(&'a T, uint)> {
+        let (current, depth) = match self.frontier.pop_front() {
+            Some(entry) => entry,
+            None => return None
+        };
+
+        for (_, next) in self.graph.neighbours(current) {
+            if !self.visited.contains(&next) {
+                self.visited.insert(next);
+                self.frontier.push_back((next, depth + 1));
+            }
+        }
+
+        Some((current, depth))
+    }
+}
+
+/// Visit every node reachable from `start` that isn't already in `visited`,
+/// in depth-first order.
+///
+/// This is the machinery behind `depth_first_search`, exposed separately so
+/// that algorithms which sweep over many start nodes can share one `visited`
+/// set between them.
+fn depth_first_visit<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T,
+     visited: &mut HashSet<&'a T>, discover: &mut Option<|&'a T, uint|>,
+     finish: &mut Option<|&'a T|>) {
+
+    if visited.contains(&start) {
+        return;
+    }
+
+    visited.insert(start);
+    match *discover {
+        Some(ref mut f) => (*f)(start, 0),
+        None => ()
+    }
+
+    // Keep each node's neighbour iterator on the stack alongside it, so that
+    // we can pick up where we left off once its descendants are finished.
+    let mut stack = vec!((start, graph.neighbours(start)));
+
+    loop {
+        let next = match stack.mut_last() {
+            Some(&mut (_, ref mut neighbours)) => neighbours.next(),
+            None => break
+        };
+
+        match next {
+            Some((_, node)) => {
+                if visited.contains(&node) {
+                    continue;
+                }
+
+                visited.insert(node);
+                match *discover {
+                    Some(ref mut f) => (*f)(node, stack.len()),
+                    None => ()
+                }
+
+                stack.push((node, graph.neighbours(node)));
+            },
+            None => {
+                // Every neighbour has been explored, so this node is done.
+                let (node, _) = stack.pop().unwrap();
+                match *finish {
+                    Some(ref mut f) => (*f)(node),
+                    None => ()
+                }
+            }
+        }
+    }
+}
+
+/// Search exhaustively over the graph in depth-first order, starting at the
+/// given node.
+///
+/// If `discover` is specified, it is called with each node when it is first
+/// reached, along with its depth in the search tree. If `finish` is
+/// specified, it is called with each node once all of its descendants have
+/// been explored, so nodes are finished in reverse topological order.
+pub fn depth_first_search<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T,
+     mut discover: Option<|&'a T, uint|>, mut finish: Option<|&'a T|>) -> Result<(), SearchError> {
+
+    try!(check_endpoints(graph, start, None));
+    let mut visited = HashSet::new();
+    depth_first_visit(graph, start, &mut visited, &mut discover, &mut finish);
+    Ok(())
+}
+
+/// The nodes reachable from a start node in depth-first order, as made by
+/// `dfs_preorder` and `dfs_postorder`.
+pub struct DepthFirstIter<'a, T, W, I> {
+    graph: &'a graph::WeightedGraph<'a, T, W, I>,
+    // Each node on the current path, with the neighbours left to look at.
+    stack: Vec<(&'a T, I)>,
+    visited: HashSet<&'a T>,
+    // The start node, until a preorder traversal has handed it out.
+    pending: Option<&'a T>,
+    postorder: bool
+}
+
+impl<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>> DepthFirstIter<'a, T, W, I> {
+    fn new(graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, postorder: bool)
+        -> DepthFirstIter<'a, T, W, I> {
+
+        let mut visited = HashSet::new();
+        visited.insert(start);
+
+        DepthFirstIter {
+            graph: graph,
+            stack: vec!((start, graph.neighbours(start))),
+            visited: visited,
+            pending: if postorder { None } else { Some(start) },
+            postorder: postorder
+        }
+    }
+}
+
+impl<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>> Iterator<(&'a T, uint)> for DepthFirstIter<'a, T, W, I> {
+    fn next(&mut self) -> Option<(&'a T, uint)> {
+        match self.pending.take() {
+            Some(start) => return Some((start, 0)),
+            None => ()
+        }
+
+        loop {
+            let next = match self.stack.mut_last() {
+                Some(&mut (_, ref mut neighbours)) => neighbours.next(),
+                None => return None
+            };
+
+            match next {
+                Some((_, node)) => {
+                    if self.visited.contains(&node) {
+                        continue;
+                    }
+
+                    self.visited.insert(node);
+                    let depth = self.stack.len();
+                    self.stack.push((node, self.graph.neighbours(node)));
+
+                    if !self.postorder {
+                        return Some((node, depth));
+                    }
+                },
+                None => {
+                    let (node, _) = self.stack.pop().unwrap();
+                    if self.postorder {
+                        return Some((node, self.stack.len()));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Walk over the nodes reachable from `start` in depth-first order, each
+/// along with its depth in the search tree, yielding each node when it is
+/// first reached. This is the order in which `depth_first_search` calls
+/// `discover`, but nodes are only explored as they are asked for.
+pub fn dfs_preorder<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T)
+    -> Result<DepthFirstIter<'a, T, W, I>, SearchError> {
+
+    try!(check_endpoints(graph, start, None));
+    Ok(DepthFirstIter::new(graph, start, false))
+}
+
+/// As `dfs_preorder`, but yield each node once all of its descendants have
+/// been, as `depth_first_search` calls `finish`. On a DAG, this lists the
+/// nodes in reverse topological order.
+pub fn dfs_postorder<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T)
+    -> Result<DepthFirstIter<'a, T, W, I>, SearchError> {
+
+    try!(check_endpoints(graph, start, None));
+    Ok(DepthFirstIter::new(graph, start, true))
+}
+
+/// Label each of `nodes` with the connected component it belongs to, by
+/// sweeping a depth-first search over them.
+///
+/// Returns the component of every node (numbered from zero in the order
+/// they were found) and the number of components. Two nodes in different
+/// components can't have a path between them, which is much cheaper to
+/// check than running a search. The graph should be undirected, such as an
+/// `UnGraph` or a grid; for a directed graph, this finds the nodes reachable
+/// from the first node of each component rather than the weakly connected
+/// components.
+pub fn connected_components<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, nodes: &[&'a T]) -> (HashMap<&'a T, uint>, uint) {
+
+    let mut visited = HashSet::new();
+    let mut components = HashMap::new();
+    let mut count = 0u;
+
+    for &node in nodes.iter() {
+        if visited.contains(&node) {
+            continue;
+        }
+
+        depth_first_visit(graph, node, &mut visited, &mut Some(|n, _| { components.insert(n, count); }),
+                          &mut None);
+        count += 1;
+    }
+
+    (components, count)
+}
+
+/// Find the strongly connected components among `nodes` using Tarjan's
+/// algorithm.
+///
+/// Each component is a set of nodes that can all reach each other. The
+/// components are returned in reverse topological order: no component has
+/// an edge to one that comes after it in the list.
+pub fn strongly_connected_components<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, nodes: &[&'a T]) -> Vec<Vec<&'a T>> {
+
+    let mut components = Vec::new();
+    let mut index = HashMap::new();
+    let mut lowlink = HashMap::new();
+    let mut on_stack = HashSet::new();
+    let mut stack = Vec::new();
+
+    for &root in nodes.iter() {
+        if index.contains_key(&root) {
+            continue;
+        }
+
+        // As in `depth_first_visit`, each node on the search stack carries
+        // its own neighbour iterator.
+        let i = index.len();
+        index.insert(root, i);
+        lowlink.insert(root, i);
+        on_stack.insert(root);
+        stack.push(root);
+        let mut work = vec!((root, graph.neighbours(root)));
+
+        loop {
+            let (current, next) = match work.mut_last() {
+                Some(&mut (current, ref mut neighbours)) => (current, neighbours.next()),
+                None => break
+            };
+
+            match next {
+                Some((_, node)) => {
+                    if !index.contains_key(&node) {
+                        let i = index.len();
+                        index.insert(node, i);
+                        lowlink.insert(node, i);
+                        on_stack.insert(node);
+                        stack.push(node);
+                        work.push((node, graph.neighbours(node)));
+                    } else if on_stack.contains(&node) {
+                        let low = min(*lowlink.get(&current), *index.get(&node));
+                        lowlink.insert(current, low);
+                    }
+                },
+                None => {
+                    work.pop();
+
+                    // A node that can't reach anything earlier on the stack
+                    // is the root of a component made up of everything above
+                    // it.
+                    if *lowlink.get(&current) == *index.get(&current) {
+                        let mut component = Vec::new();
+                        loop {
+                            let node = stack.pop().unwrap();
+                            on_stack.remove(&node);
+                            component.push(node);
+                            if node == current {
+                                break;
+                            }
+                        }
+                        components.push(component);
+                    }
+
+                    match work.last() {
+                        Some(&(parent, _)) => {
+                            let low = min(*lowlink.get(&parent), *lowlink.get(&current));
+                            lowlink.insert(parent, low);
+                        },
+                        None => ()
+                    }
+                }
+            }
+        }
+    }
+
+    components
+}
+
+/// Build the condensation of a graph: the graph with each strongly connected
+/// component (as found by `strongly_connected_components`) collapsed into a
+/// single node.
+///
+/// Returns the number of the component each node belongs to, which is its
+/// position in `components`, and a `SimpleGraph` over those numbers with an
+/// edge wherever the graph has an edge between two different components.
+/// The condensation never has any cycles.
+pub fn condensation<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, components: &[Vec<&'a T>])
+    -> (HashMap<&'a T, uint>, graph::SimpleGraph<uint, uint>) {
+
+    let mut component_of = HashMap::new();
+    for (i, component) in components.iter().enumerate() {
+        for &node in component.iter() {
+            component_of.insert(node, i);
+        }
+    }
+
+    let mut edges = HashMap::new();
+    for (i, component) in components.iter().enumerate() {
+        let mut targets = HashSet::new();
+        for &node in component.iter() {
+            for (_, next) in graph.neighbours(node) {
+                match component_of.find(&next) {
+                    Some(&j) if j != i => { targets.insert(j); },
+                    _ => ()
+                }
+            }
+        }
+        edges.insert(i, targets.move_iter().collect());
+    }
+
+    (component_of, graph::SimpleGraph::unweighted(edges))
+}
+
+/// Order `nodes` so that every edge between them leads from an earlier node
+/// to a later one.
+///
+/// This is only possible if the graph has no cycles. If it does, the sort
+/// fails with `Err` holding a node that lies on one of them (see
+/// `find_cycle` to get the whole cycle).
+pub fn topological_sort<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, nodes: &[&'a T]) -> Result<Vec<&'a T>, &'a T> {
+
+    let mut order = Vec::new();
+    let mut visited = HashSet::new();
+    // The nodes on the current search path, which may not be reached again.
+    let mut in_progress = HashSet::new();
+
+    for &root in nodes.iter() {
+        if visited.contains(&root) {
+            continue;
+        }
+
+        visited.insert(root);
+        in_progress.insert(root);
+        let mut work = vec!((root, graph.neighbours(root)));
+
+        loop {
+            let (current, next) = match work.mut_last() {
+                Some(&mut (current, ref mut neighbours)) => (current, neighbours.next()),
+                None => break
+            };
+
+            match next {
+                Some((_, node)) => {
+                    if in_progress.contains(&node) {
+                        return Err(node);
+                    }
+
+                    if visited.insert(node) {
+                        in_progress.insert(node);
+                        work.push((node, graph.neighbours(node)));
+                    }
+                },
+                None => {
+                    work.pop();
+                    in_progress.remove(&current);
+                    order.push(current);
+                }
+            }
+        }
+    }
+
+    // Nodes were finished after everything they lead to.
+    order.reverse();
+    Ok(order)
+}
+
+/// Find the most expensive path among `nodes` in a directed acyclic graph,
+/// such as the critical path through a set of tasks whose edges are
+/// dependencies weighted by duration.
+///
+/// The nodes are put in `topological_sort` order and each edge is relaxed
+/// in turn, keeping the larger cost, which is only correct because there are
+/// no cycles; the general searches can't maximise. The path may start
+/// anywhere, and negative weights are allowed. As with `topological_sort`,
+/// a cycle makes this fail with `Err` holding a node on it.
+pub fn dag_longest_path<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, nodes: &[&'a T])
+    -> Result<(Vec<&'a T>, W), &'a T> {
+
+    let order = try!(topological_sort(graph, nodes));
+    let mut longest: HashMap<&'a T, W> = order.iter().map(|&n| (n, Zero::zero())).collect();
+    let mut came_from: HashMap<&'a T, &'a T> = HashMap::new();
+
+    for &node in order.iter() {
+        let here = *longest.get(&node);
+        for (cost, next) in graph.neighbours(node) {
+            let new_cost = here + cost;
+            let better = match longest.find(&next) {
+                Some(&c) => new_cost > c,
+                None => false
+            };
+            if better {
+                longest.insert(next, new_cost);
+                came_from.insert(next, node);
+            }
+        }
+    }
+
+    let end = match order.iter().max_by(|n| *longest.get(*n)) {
+        Some(&end) => end,
+        None => return Ok((Vec::new(), Zero::zero()))
+    };
+
+    let mut path = vec![end];
+    let mut current = end;
+    loop {
+        match came_from.find(&current) {
+            Some(&previous) => { path.push(previous); current = previous; },
+            None => break
+        }
+    }
+    path.reverse();
+    Ok((path, *longest.get(&end)))
+}
+
+/// Search for a cycle among `nodes`, as for `find_cycle`. In an undirected
+/// graph, the edge a node was reached by leads straight back to its parent,
+/// so one such edge is ignored.
+fn cycle_search<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, nodes: &[&'a T], undirected: bool) -> Option<Vec<&'a T>> {
+
+    let mut visited = HashSet::new();
+
+    for &root in nodes.iter() {
+        if visited.contains(&root) {
+            continue;
+        }
+
+        visited.insert(root);
+        // Each entry is a node on the current path, its neighbours, and (in
+        // an undirected graph) its parent until the edge back to it has been
+        // skipped.
+        let mut work = vec!((root, graph.neighbours(root), None));
+        // Where each node on the current path sits in `work`.
+        let mut on_path = HashMap::new();
+        on_path.insert(root, 0u);
+
+        loop {
+            let (next, parent) = match work.mut_last() {
+                Some(&mut (_, ref mut neighbours, parent)) => (neighbours.next(), parent),
+                None => break
+            };
+
+            let node = match next {
+                Some((_, node)) => node,
+                None => {
+                    let (done, _, _) = work.pop().unwrap();
+                    on_path.remove(&done);
+                    continue;
+                }
+            };
+
+            if parent == Some(node) {
+                match work.mut_last() {
+                    Some(&mut (_, _, ref mut parent)) => *parent = None,
+                    None => ()
+                }
+                continue;
+            }
+
+            match on_path.find(&node) {
+                Some(&i) => return Some(work.slice_from(i).iter().map(|&(n, _, _)| n).collect()),
+                None => ()
+            }
+
+            if visited.insert(node) {
+                let parent = if undirected { Some(work.last().map(|&(n, _, _)| n).unwrap()) } else { None };
+                on_path.insert(node, work.len());
+                work.push((node, graph.neighbours(node), parent));
+            }
+        }
+    }
+
+    None
+}
+
+/// Look for a cycle among `nodes` in a directed graph, returning the nodes
+/// along one if there is one.
+///
+/// The cycle starts at an arbitrary node on it, and that node isn't repeated
+/// at the end, so a self-loop is a cycle of one node.
+pub fn find_cycle<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, nodes: &[&'a T]) -> Option<Vec<&'a T>> {
+    cycle_search(graph, nodes, false)
+}
+
+/// As `find_cycle`, but for an undirected graph, where every edge appears in
+/// both directions but going back along the same edge doesn't count as a
+/// cycle. Parallel edges between two nodes do.
+pub fn find_undirected_cycle<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, nodes: &[&'a T]) -> Option<Vec<&'a T>> {
+    cycle_search(graph, nodes, true)
+}
+
+/// A union-find structure over nodes numbered from zero, with path halving
+/// and union by size.
+struct DisjointSets {
+    parent: Vec<uint>,
+    size: Vec<uint>
+}
+
+impl DisjointSets {
+    fn new(count: uint) -> DisjointSets {
+        DisjointSets { parent: Vec::from_fn(count, |i| i), size: Vec::from_elem(count, 1u) }
+    }
+
+    /// The representative of the set containing `node`.
+    fn find(&mut self, node: uint) -> uint {
+        let mut node = node;
+        while *self.parent.get(node) != node {
+            let grandparent = *self.parent.get(*self.parent.get(node));
+            *self.parent.get_mut(node) = grandparent;
+            node = grandparent;
+        }
+        node
+    }
+
+    /// Merge the sets containing `a` and `b`, returning false if they were
+    /// already the same set.
+    fn union(&mut self, a: uint, b: uint) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+
+        let (small, large) = if *self.size.get(a) < *self.size.get(b) { (a, b) } else { (b, a) };
+        *self.parent.get_mut(small) = large;
+        *self.size.get_mut(large) += *self.size.get(small);
+        true
+    }
+}
+
+/// Find a minimum spanning tree of the undirected graph made up of `nodes`
+/// with Kruskal's algorithm, returning its edges and their total weight.
+///
+/// Every edge is considered from lightest to heaviest, and kept if it joins
+/// two parts of the tree that weren't already connected. If the graph isn't
+/// connected, the result is a minimum spanning forest, with one tree for
+/// each component. Edges that lead outside of `nodes` are ignored.
+pub fn kruskal<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, nodes: &[&'a T]) -> (Vec<(&'a T, &'a T, W)>, W) {
+
+    let mut index = HashMap::new();
+    for (i, &node) in nodes.iter().enumerate() {
+        index.insert(node, i);
+    }
+
+    let mut edges = Vec::new();
+    for (i, &node) in nodes.iter().enumerate() {
+        for (cost, next) in graph.neighbours(node) {
+            match index.find(&next) {
+                Some(&j) if j != i => edges.push((cost, i, j)),
+                _ => ()
+            }
+        }
+    }
+    edges.sort_by(|&(a, _, _), &(b, _, _)| a.cmp(&b));
+
+    let mut sets = DisjointSets::new(nodes.len());
+    let mut tree = Vec::new();
+    let mut total = Zero::zero();
+
+    for &(cost, i, j) in edges.iter() {
+        if sets.union(i, j) {
+            tree.push((nodes[i], nodes[j], cost));
+            total = total + cost;
+        }
+    }
+
+    (tree, total)
+}
+
+/// Find a minimum spanning tree of the component of an undirected graph that
+/// contains `start` with Prim's algorithm, returning its edges and their
+/// total weight.
+///
+/// The tree is grown outwards from `start`, always adding the lightest edge
+/// that leads to a node not yet in it. Unlike `kruskal`, this never needs
+/// the whole graph's edges at once, and it tends to be faster on dense
+/// graphs.
+pub fn prim<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T) -> (Vec<(&'a T, &'a T, W)>, W) {
+
+    let mut frontier = PriorityQueue::new();
+    let mut in_tree = HashSet::new();
+    let mut tree = Vec::new();
+    let mut total = Zero::zero();
+
+    in_tree.insert(start);
+    for (cost, next) in graph.neighbours(start) {
+        frontier.push(MinPriorityNode { node: (start, next), cost: cost });
+    }
+
+    while !frontier.is_empty() {
+        let MinPriorityNode { node: (from, to), cost } = frontier.pop().unwrap();
+
+        // Edges are left in the queue when a cheaper way to their node is
+        // found, so skip any that lead back into the tree.
+        if !in_tree.insert(to) {
+            continue;
+        }
+
+        tree.push((from, to, cost));
+        total = total + cost;
+
+        for (cost, next) in graph.neighbours(to) {
+            if !in_tree.contains(&next) {
+                frontier.push(MinPriorityNode { node: (to, next), cost: cost });
+            }
+        }
+    }
+
+    (tree, total)
+}
+
+/// A flow network, where the weight of each edge is its capacity.
+///
+/// Every edge is paired with a reverse arc in the residual network, so that
+/// flow sent along it can later be cancelled. Arc `2 * k` is the `k`th edge
+/// and arc `2 * k + 1` its reverse.
+pub struct FlowNetwork<'a, T, W> {
+    nodes: Vec<&'a T>,
+    index: HashMap<&'a T, uint>,
+    // The edges as `(from, to, capacity)`, and the arcs leaving each node.
+    edges: Vec<(uint, uint, W)>,
+    arcs: Vec<Vec<uint>>
+}
+
+/// A maximum flow through a `FlowNetwork`.
+#[deriving(Clone, Show)]
+pub struct MaxFlow<'a, T, W> {
+    /// The total flow from the source to the sink.
+    pub value: W,
+    /// The flow along each edge of the network, in the same order as
+    /// `FlowNetwork::edges()`.
+    pub flows: Vec<(&'a T, &'a T, W)>
+}
+
+/// A minimum cut separating the source of a `MaxFlow` from its sink.
+#[deriving(Clone, Show)]
+pub struct MinCut<'a, T, W> {
+    /// The nodes that can still be reached from the source once the flow is
+    /// in place.
+    pub source_side: Vec<&'a T>,
+    /// The edges leading from the source side to the rest of the network,
+    /// all of which are saturated. Their capacities add up to the value of
+    /// the flow.
+    pub edges: Vec<(&'a T, &'a T, W)>
+}
+
+impl<'a, T: Eq + Hash, W: Cost + Sub<W, W>> FlowNetwork<'a, T, W> {
+
+    /// Build the network made up of `nodes`. Edges that lead outside of
+    /// `nodes` are ignored, and capacities must not be negative.
+    pub fn new<I: Iterator<(W, &'a T)>>(graph: &'a graph::WeightedGraph<'a, T, W, I>,
+                                       nodes: &[&'a T]) -> Result<FlowNetwork<'a, T, W>, SearchError> {
+        let mut index = HashMap::new();
+        for (i, &node) in nodes.iter().enumerate() {
+            index.insert(node, i);
+        }
+
+        let mut edges = Vec::new();
+        let mut arcs = Vec::from_fn(nodes.len(), |_| Vec::new());
+        for (i, &node) in nodes.iter().enumerate() {
+            for (capacity, next) in graph.neighbours(node) {
+                if capacity < Zero::zero() {
+                    return Err(NegativeWeight);
+                }
+
+                match index.find(&next) {
+                    Some(&j) => {
+                        arcs.get_mut(i).push(2 * edges.len());
+                        arcs.get_mut(j).push(2 * edges.len() + 1);
+                        edges.push((i, j, capacity));
+                    },
+                    None => ()
+                }
+            }
+        }
+
+        Ok(FlowNetwork { nodes: nodes.to_vec(), index: index, edges: edges, arcs: arcs })
+    }
+
+    /// The edges of the network with their capacities.
+    pub fn edges(&self) -> Vec<(&'a T, &'a T, W)> {
+        self.edges.iter().map(|&(from, to, capacity)| (*self.nodes.get(from), *self.nodes.get(to), capacity))
+                  .collect()
+    }
+
+    /// The node an arc leads to.
+    fn head(&self, arc: uint) -> uint {
+        let &(from, to, _) = self.edges.get(arc / 2);
+        if arc % 2 == 0 { to } else { from }
+    }
+
+    /// The residual capacities of the arcs when nothing is flowing yet.
+    fn empty_residual(&self) -> Vec<W> {
+        let mut residual = Vec::with_capacity(2 * self.edges.len());
+        for &(_, _, capacity) in self.edges.iter() {
+            residual.push(capacity);
+            residual.push(Zero::zero());
+        }
+        residual
+    }
+
+    /// The flow when the source or sink isn't part of the network.
+    fn no_flow(&self) -> MaxFlow<'a, T, W> {
+        let flows = self.edges.iter().map(|&(from, to, _)| {
+            (*self.nodes.get(from), *self.nodes.get(to), Zero::zero())
+        }).collect();
+        MaxFlow { value: Zero::zero(), flows: flows }
+    }
+
+    /// Package up the flow described by the residual capacities.
+    fn max_flow(&self, residual: &Vec<W>, source: uint) -> MaxFlow<'a, T, W> {
+        let flows: Vec<(&'a T, &'a T, W)> = self.edges.iter().enumerate().map(|(k, &(from, to, _))| {
+            (*self.nodes.get(from), *self.nodes.get(to), *residual.get(2 * k + 1))
+        }).collect();
+
+        // The flow out of the source, less any that comes back into it.
+        let mut value = Zero::zero();
+        for &arc in self.arcs.get(source).iter() {
+            if arc % 2 == 0 {
+                value = value + *residual.get(arc + 1);
+            }
+        }
+        for &arc in self.arcs.get(source).iter() {
+            if arc % 2 == 1 {
+                value = value - *residual.get(arc);
+            }
+        }
+
+        MaxFlow { value: value, flows: flows }
+    }
+
+    /// Find a maximum flow from `source` to `sink` with the Edmonds-Karp
+    /// algorithm, which repeatedly sends as much flow as it can along the
+    /// shortest path (by number of edges) that still has room.
+    pub fn edmonds_karp(&self, source: &'a T, sink: &'a T) -> MaxFlow<'a, T, W> {
+        let mut residual = self.empty_residual();
+        let (s, t) = match (self.index.find(&source), self.index.find(&sink)) {
+            (Some(&s), Some(&t)) => (s, t),
+            _ => return self.no_flow()
+        };
+
+        while s != t {
+            // Search breadth-first for a path with room left on every arc.
+            let mut via: Vec<Option<uint>> = Vec::from_elem(self.nodes.len(), None);
+            let mut frontier = DList::new();
+            frontier.push_back(s);
+
+            while !frontier.is_empty() && via.get(t).is_none() {
+                let current = frontier.pop_front().unwrap();
+                for &arc in self.arcs.get(current).iter() {
+                    let next = self.head(arc);
+                    if next != s && via.get(next).is_none() && *residual.get(arc) > Zero::zero() {
+                        *via.get_mut(next) = Some(arc);
+                        frontier.push_back(next);
+                    }
+                }
+            }
+
+            if via.get(t).is_none() {
+                break;
+            }
+
+            // Push as much as the tightest arc along it allows.
+            let mut bottleneck = None;
+            let mut node = t;
+            while node != s {
+                let arc = via.get(node).unwrap();
+                bottleneck = infinite_min(bottleneck, Some(*residual.get(arc)));
+                node = self.head(arc ^ 1);
+            }
+
+            let amount = bottleneck.unwrap();
+            node = t;
+            while node != s {
+                let arc = via.get(node).unwrap();
+                *residual.get_mut(arc) = *residual.get(arc) - amount;
+                *residual.get_mut(arc ^ 1) = *residual.get(arc ^ 1) + amount;
+                node = self.head(arc ^ 1);
+            }
+        }
+
+        self.max_flow(&residual, s)
+    }
+
+    /// Find a maximum flow from `source` to `sink` with Dinic's algorithm.
+    ///
+    /// Each phase sorts the nodes into levels by their distance from the
+    /// source in the residual network, then saturates every shortest path
+    /// at once by searching depth-first along arcs that lead down a level
+    /// (a blocking flow). Arcs that turn out to be dead ends are skipped for
+    /// the rest of the phase, which makes this much faster than
+    /// `edmonds_karp` on large networks.
+    pub fn dinic(&self, source: &'a T, sink: &'a T) -> MaxFlow<'a, T, W> {
+        let mut residual = self.empty_residual();
+        let (s, t) = match (self.index.find(&source), self.index.find(&sink)) {
+            (Some(&s), Some(&t)) => (s, t),
+            _ => return self.no_flow()
+        };
+
+        while s != t {
+            let mut level: Vec<Option<uint>> = Vec::from_elem(self.nodes.len(), None);
+            let mut frontier = DList::new();
+            *level.get_mut(s) = Some(0);
+            frontier.push_back(s);
+
+            while !frontier.is_empty() {
+                let current = frontier.pop_front().unwrap();
+                for &arc in self.arcs.get(current).iter() {
+                    let next = self.head(arc);
+                    if level.get(next).is_none() && *residual.get(arc) > Zero::zero() {
+                        *level.get_mut(next) = Some(level.get(current).unwrap() + 1);
+                        frontier.push_back(next);
+                    }
+                }
+            }
+
+            if level.get(t).is_none() {
+                break;
+            }
+
+            // The next arc of each node still worth trying this phase.
+            let mut next_arc = Vec::from_elem(self.nodes.len(), 0u);
+            let mut path: Vec<uint> = Vec::new();
+            let mut node = s;
+
+            loop {
+                if node == t {
+                    let mut amount = None;
+                    for &arc in path.iter() {
+                        amount = infinite_min(amount, Some(*residual.get(arc)));
+                    }
+
+                    let amount = amount.unwrap();
+                    for &arc in path.iter() {
+                        *residual.get_mut(arc) = *residual.get(arc) - amount;
+                        *residual.get_mut(arc ^ 1) = *residual.get(arc ^ 1) + amount;
+                    }
+
+                    path.clear();
+                    node = s;
+                    continue;
+                }
+
+                let arcs = self.arcs.get(node);
+                let mut advanced = false;
+                while *next_arc.get(node) < arcs.len() {
+                    let arc = *arcs.get(*next_arc.get(node));
+                    let next = self.head(arc);
+                    if *residual.get(arc) > Zero::zero()
+                        && *level.get(next) == level.get(node).map(|l| l + 1) {
+                        path.push(arc);
+                        node = next;
+                        advanced = true;
+                        break;
+                    }
+                    *next_arc.get_mut(node) += 1;
+                }
+
+                if advanced {
+                    continue;
+                }
+
+                // Nothing more can get through this node, so back up and
+                // move past the arc that led here.
+                *level.get_mut(node) = None;
+                match path.pop() {
+                    Some(arc) => {
+                        node = self.head(arc ^ 1);
+                        *next_arc.get_mut(node) += 1;
+                    },
+                    None => break
+                }
+            }
+        }
+
+        self.max_flow(&residual, s)
+    }
+
+    /// Recover a minimum cut from a maximum flow out of `source`, as found
+    /// by `edmonds_karp` or `dinic`.
+    ///
+    /// Once no more flow can get through, the nodes the source can still
+    /// send flow to form one side of the cut, and the edges out of them are
+    /// exactly the bottleneck that limits the flow.
+    pub fn min_cut(&self, source: &'a T, flow: &MaxFlow<'a, T, W>) -> MinCut<'a, T, W> {
+        let s = match self.index.find(&source) {
+            Some(&s) => s,
+            None => return MinCut { source_side: vec!(source), edges: Vec::new() }
+        };
+
+        let mut residual = Vec::with_capacity(2 * self.edges.len());
+        for (&(_, _, capacity), &(_, _, amount)) in self.edges.iter().zip(flow.flows.iter()) {
+            residual.push(capacity - amount);
+            residual.push(amount);
+        }
+
+        let mut reached = Vec::from_elem(self.nodes.len(), false);
+        let mut frontier = DList::new();
+        *reached.get_mut(s) = true;
+        frontier.push_back(s);
+
+        while !frontier.is_empty() {
+            let current = frontier.pop_front().unwrap();
+            for &arc in self.arcs.get(current).iter() {
+                let next = self.head(arc);
+                if !*reached.get(next) && *residual.get(arc) > Zero::zero() {
+                    *reached.get_mut(next) = true;
+                    frontier.push_back(next);
+                }
+            }
+        }
+
+        let source_side = range(0, self.nodes.len()).filter(|&n| *reached.get(n))
+                                                    .map(|n| *self.nodes.get(n)).collect();
+        let edges = self.edges.iter().filter(|&&(from, to, _)| *reached.get(from) && !*reached.get(to))
+                        .map(|&(from, to, capacity)| (*self.nodes.get(from), *self.nodes.get(to), capacity))
+                        .collect();
+
+        MinCut { source_side: source_side, edges: edges }
+    }
+}
+
+/// Look for an augmenting path from the left node `u` along the layers found
+/// by the last breadth-first phase of `hopcroft_karp`, flipping the matching
+/// along it if there is one.
+///
+/// The search keeps its own stack rather than recursing, since augmenting
+/// paths can be as long as the matching is big.
+fn hopcroft_karp_augment(u: uint, adjacent: &Vec<Vec<uint>>, left_pair: &mut Vec<Option<uint>>,
+                         right_pair: &mut Vec<Option<uint>>, layer: &mut Vec<Option<uint>>) -> bool {
+    // Each left node on the current path, with the index of the next of its
+    // edges to try.
+    let mut stack = vec!((u, 0u));
+
+    loop {
+        let (u, i) = match stack.last() {
+            Some(&top) => top,
+            None => return false
+        };
+
+        if i == adjacent.get(u).len() {
+            // There's no way through `u`, so don't try it again this phase.
+            *layer.get_mut(u) = None;
+            stack.pop();
+            continue;
+        }
+
+        *stack.mut_last().unwrap() = (u, i + 1);
+        let v = *adjacent.get(u).get(i);
+        match *right_pair.get(v) {
+            None => break,
+            Some(w) => {
+                if *layer.get(w) == layer.get(u).map(|l| l + 1) {
+                    stack.push((w, 0));
+                }
+            }
+        }
+    }
+
+    // Every node on the stack took the edge just before its index.
+    for &(u, i) in stack.iter() {
+        let v = *adjacent.get(u).get(i - 1);
+        *left_pair.get_mut(u) = Some(v);
+        *right_pair.get_mut(v) = Some(u);
+    }
+
+    true
+}
+
+/// Find a maximum matching between the `left` and `right` nodes of a
+/// bipartite graph with the Hopcroft-Karp algorithm, returning the matched
+/// pairs.
+///
+/// The graph's edges should lead from the left nodes to the right ones;
+/// any others are ignored. Each phase searches breadth-first from every
+/// unmatched left node at once to find the length of the shortest
+/// augmenting paths, then depth-first along those layers to apply as many
+/// of them as possible.
+pub fn hopcroft_karp<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, left: &[&'a T], right: &[&'a T])
+    -> Vec<(&'a T, &'a T)> {
+
+    let mut right_index = HashMap::new();
+    for (i, &node) in right.iter().enumerate() {
+        right_index.insert(node, i);
+    }
+
+    let adjacent: Vec<Vec<uint>> = left.iter().map(|&node| {
+        graph.neighbours(node).filter_map(|(_, next)| right_index.find(&next).map(|&j| j)).collect()
+    }).collect();
+
+    let mut left_pair: Vec<Option<uint>> = Vec::from_elem(left.len(), None);
+    let mut right_pair: Vec<Option<uint>> = Vec::from_elem(right.len(), None);
+
+    loop {
+        // Layer the left nodes by how many matched edges lie between them
+        // and an unmatched left node.
+        let mut layer = Vec::from_elem(left.len(), None);
+        let mut frontier = DList::new();
+        for u in range(0, left.len()) {
+            if left_pair.get(u).is_none() {
+                *layer.get_mut(u) = Some(0u);
+                frontier.push_back(u);
+            }
+        }
+
+        // The layer of the first left nodes found next to an unmatched
+        // right node, which is as far as the shortest augmenting paths go.
+        let mut found = None;
+        while !frontier.is_empty() {
+            let u = frontier.pop_front().unwrap();
+            if found.is_some() && *layer.get(u) > found {
+                break;
+            }
+
+            for &v in adjacent.get(u).iter() {
+                match *right_pair.get(v) {
+                    None => found = *layer.get(u),
+                    Some(w) if layer.get(w).is_none() => {
+                        *layer.get_mut(w) = layer.get(u).map(|l| l + 1);
+                        frontier.push_back(w);
+                    },
+                    _ => ()
+                }
+            }
+        }
+
+        if found.is_none() {
+            break;
+        }
+
+        // Forget anything layered beyond that, so the depth-first phase
+        // only follows shortest augmenting paths.
+        for l in layer.mut_iter() {
+            if *l > found {
+                *l = None;
+            }
+        }
+
+        for u in range(0, left.len()) {
+            if left_pair.get(u).is_none() {
+                hopcroft_karp_augment(u, &adjacent, &mut left_pair, &mut right_pair, &mut layer);
+            }
+        }
+    }
+
+    left_pair.iter().enumerate().filter_map(|(u, v)| v.map(|v| (left[u], right[v]))).collect()
+}
+
+/// Solve the assignment problem for a cost matrix with the Hungarian
+/// (Kuhn-Munkres) algorithm, where `costs[i][j]` is the cost of assigning
+/// row `i` to column `j`.
+///
+/// Returns the cheapest way to pair every row with a different column, as
+/// `(row, column)` pairs, and its total cost. If there are more rows than
+/// columns, it's the columns that are all paired instead. Every row must be
+/// the same length, and no cost may be negative.
+///
+/// Rows are added one at a time, each by finding the cheapest augmenting
+/// path under the current dual potentials, so this takes cubic time.
+pub fn hungarian<W: Cost + Sub<W, W>>(costs: &[Vec<W>]) -> (Vec<(uint, uint)>, W) {
+    let rows = costs.len();
+    let columns = costs.head().map_or(0, |row| row.len());
+
+    // Work on the transpose if that's the way round that fits.
+    if rows > columns {
+        let transposed: Vec<Vec<W>> = range(0, columns).map(|j| {
+            range(0, rows).map(|i| *costs[i].get(j)).collect()
+        }).collect();
+
+        let (pairs, total) = hungarian(transposed.as_slice());
+        return (pairs.iter().map(|&(j, i)| (i, j)).collect(), total);
+    }
+
+    // The usual potentials are kept as `u` for the rows and `-v` for the
+    // columns, which are never negative, so that this works for unsigned
+    // costs too. Column zero is a dummy where each new row starts out.
+    let mut u: Vec<W> = Vec::from_elem(rows + 1, Zero::zero());
+    let mut v: Vec<W> = Vec::from_elem(columns + 1, Zero::zero());
+    let mut assigned: Vec<uint> = Vec::from_elem(columns + 1, 0u);
+    let mut way: Vec<uint> = Vec::from_elem(columns + 1, 0u);
+
+    for i in range(1, rows + 1) {
+        *assigned.get_mut(0) = i;
+        let mut j0 = 0u;
+        let mut slack: Vec<Option<W>> = Vec::from_elem(columns + 1, None);
+        let mut used = Vec::from_elem(columns + 1, false);
+
+        loop {
+            *used.get_mut(j0) = true;
+            let i0 = *assigned.get(j0);
+            let mut delta = None;
+            let mut j1 = 0u;
+
+            for j in range(1, columns + 1) {
+                if *used.get(j) {
+                    continue;
+                }
+
+                let reduced = *costs[i0 - 1].get(j - 1) + *v.get(j) - *u.get(i0);
+                if infinite_lt(Some(reduced), *slack.get(j)) {
+                    *slack.get_mut(j) = Some(reduced);
+                    *way.get_mut(j) = j0;
+                }
+                if infinite_lt(*slack.get(j), delta) {
+                    delta = *slack.get(j);
+                    j1 = j;
+                }
+            }
+
+            let delta = delta.unwrap();
+            for j in range(0, columns + 1) {
+                if *used.get(j) {
+                    let row = *assigned.get(j);
+                    *u.get_mut(row) = *u.get(row) + delta;
+                    *v.get_mut(j) = *v.get(j) + delta;
+                } else {
+                    *slack.get_mut(j) = slack.get(j).map(|s| s - delta);
+                }
+            }
+
+            j0 = j1;
+            if *assigned.get(j0) == 0 {
+                break;
+            }
+        }
+
+        // Flip the assignments along the augmenting path.
+        while j0 != 0 {
+            let j1 = *way.get(j0);
+            *assigned.get_mut(j0) = *assigned.get(j1);
+            j0 = j1;
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let mut total = Zero::zero();
+    for j in range(1, columns + 1) {
+        let i = *assigned.get(j);
+        if i != 0 {
+            pairs.push((i - 1, j - 1));
+            total = total + *costs[i - 1].get(j - 1);
+        }
+    }
+    pairs.sort();
+
+    (pairs, total)
+}
+
+/// Find the articulation points and bridges among `nodes` in one sweep of
+/// depth-first searches, by comparing when each node was discovered with
+/// the earliest node its subtree can reach without going back the way it
+/// came.
+fn cut_points_and_bridges<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, nodes: &[&'a T])
+    -> (Vec<&'a T>, Vec<(&'a T, &'a T)>) {
+
+    let mut discovered = HashMap::new();
+    let mut low = HashMap::new();
+    let mut points = Vec::new();
+    let mut is_point = HashSet::new();
+    let mut bridges = Vec::new();
+
+    for &root in nodes.iter() {
+        if discovered.contains_key(&root) {
+            continue;
+        }
+
+        let mut root_children = 0u;
+        let i = discovered.len();
+        discovered.insert(root, i);
+        low.insert(root, i);
+
+        // Each entry is a node, its neighbours, its parent, and whether the
+        // edge back to the parent has been skipped yet.
+        let mut work = vec!((root, graph.neighbours(root), None, false));
+
+        loop {
+            let (current, next, parent) = match work.mut_last() {
+                Some(&mut (current, ref mut neighbours, parent, ref mut skipped)) => {
+                    let mut next = neighbours.next();
+                    match next {
+                        Some((_, node)) if !*skipped && parent == Some(node) => {
+                            *skipped = true;
+                            next = neighbours.next();
+                        },
+                        _ => ()
+                    }
+                    (current, next, parent)
+                },
+                None => break
+            };
+
+            match next {
+                Some((_, node)) => {
+                    let seen = discovered.find(&node).map(|&d| d);
+                    match seen {
+                        Some(d) => {
+                            let l = min(*low.get(&current), d);
+                            low.insert(current, l);
+                        },
+                        None => {
+                            let i = discovered.len();
+                            discovered.insert(node, i);
+                            low.insert(node, i);
+                            work.push((node, graph.neighbours(node), Some(current), false));
+                        }
+                    }
+                },
+                None => {
+                    work.pop();
+
+                    let parent = match parent {
+                        Some(parent) => parent,
+                        None => continue
+                    };
+
+                    let (current_low, parent_discovered) = (*low.get(&current), *discovered.get(&parent));
+                    let l = min(*low.get(&parent), current_low);
+                    low.insert(parent, l);
+
+                    // Nothing below `current` can get around its parent, or
+                    // even around the edge between them.
+                    if current_low > parent_discovered {
+                        bridges.push((parent, current));
+                    }
+
+                    if parent == root {
+                        root_children += 1;
+                    } else if current_low >= parent_discovered && is_point.insert(parent) {
+                        points.push(parent);
+                    }
+                }
+            }
+        }
+
+        // The root couldn't be checked as it went, since nothing comes
+        // before it, but it's a cut point if it has several subtrees.
+        if root_children > 1 {
+            points.push(root);
+        }
+    }
+
+    (points, bridges)
+}
+
+/// Find the articulation points of an undirected graph made up of `nodes`:
+/// the nodes whose removal would disconnect part of the graph from the rest.
+pub fn articulation_points<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, nodes: &[&'a T]) -> Vec<&'a T> {
+    let (points, _) = cut_points_and_bridges(graph, nodes);
+    points
+}
+
+/// Find the bridges of an undirected graph made up of `nodes`: the edges
+/// whose removal would disconnect part of the graph from the rest. Each is
+/// given once, in the direction the search happened to cross it.
+pub fn bridges<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, nodes: &[&'a T]) -> Vec<(&'a T, &'a T)> {
+    let (_, bridges) = cut_points_and_bridges(graph, nodes);
+    bridges
+}
+
+/// The reasons a graph might not have an Eulerian path or circuit.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum EulerianError {
+    /// The number of nodes whose in-degree and out-degree differ, when
+    /// there were too many of them or they differed by too much. A path
+    /// allows one node with an extra edge out and one with an extra edge in,
+    /// and a circuit allows none.
+    UnbalancedDegrees(uint),
+    /// The edges don't all belong to the same connected part of the graph.
+    DisconnectedEdges
+}
+
+/// Find an Eulerian path through the directed graph made up of `nodes`, or
+/// a circuit if `circuit` is true, with Hierholzer's algorithm.
+fn hierholzer<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, nodes: &[&'a T], circuit: bool)
+    -> Result<Vec<&'a T>, EulerianError> {
+
+    let mut index = HashMap::new();
+    for (i, &node) in nodes.iter().enumerate() {
+        index.insert(node, i);
+    }
+
+    let adjacent: Vec<Vec<uint>> = nodes.iter().map(|&node| {
+        graph.neighbours(node).filter_map(|(_, next)| index.find(&next).map(|&j| j)).collect()
+    }).collect();
+
+    let mut in_degree = Vec::from_elem(nodes.len(), 0i);
+    let mut edge_count = 0u;
+    for out in adjacent.iter() {
+        for &j in out.iter() {
+            *in_degree.get_mut(j) += 1;
+            edge_count += 1;
+        }
+    }
+
+    if edge_count == 0 {
+        return Ok(Vec::new());
+    }
+
+    // A path has to start at the node with an extra edge out, if there is
+    // one, and end at the node with an extra edge in.
+    let mut start = None;
+    let mut unbalanced = 0u;
+    let mut extra_out = 0u;
+    let mut extra_in = 0u;
+    for (i, out) in adjacent.iter().enumerate() {
+        let difference = out.len() as int - *in_degree.get(i);
+        if difference != 0 {
+            unbalanced += 1;
+        }
+        if difference == 1 {
+            extra_out += 1;
+            start = Some(i);
+        } else if difference == -1 {
+            extra_in += 1;
+        }
+        if start.is_none() && out.len() > 0 {
+            start = Some(i);
+        }
+    }
+
+    let balanced_enough = unbalanced == 0 || (!circuit && unbalanced == 2 && extra_out == 1 && extra_in == 1);
+    if !balanced_enough {
+        return Err(UnbalancedDegrees(unbalanced));
+    }
+
+    // Follow unused edges until getting stuck, which can only happen back
+    // at the start of the current trail, then back up and splice in trails
+    // from anywhere along it that still has unused edges.
+    let mut used = Vec::from_elem(nodes.len(), 0u);
+    let mut stack = vec!(start.unwrap());
+    let mut path = Vec::new();
+
+    while !stack.is_empty() {
+        let current = *stack.last().unwrap();
+        let out = adjacent.get(current);
+        if *used.get(current) < out.len() {
+            stack.push(*out.get(*used.get(current)));
+            *used.get_mut(current) += 1;
+        } else {
+            path.push(nodes[current]);
+            stack.pop();
+        }
+    }
+
+    if path.len() != edge_count + 1 {
+        return Err(DisconnectedEdges);
+    }
+
+    path.reverse();
+    Ok(path)
+}
+
+/// Find a path through the directed graph made up of `nodes` that follows
+/// every edge exactly once, returning the nodes along it.
+///
+/// If the path happens to end where it started, it's also a circuit. An
+/// empty graph gives an empty path.
+pub fn eulerian_path<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, nodes: &[&'a T]) -> Result<Vec<&'a T>, EulerianError> {
+    hierholzer(graph, nodes, false)
+}
+
+/// As `eulerian_path`, but the path must end where it started.
+pub fn eulerian_circuit<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, nodes: &[&'a T]) -> Result<Vec<&'a T>, EulerianError> {
+    hierholzer(graph, nodes, true)
+}
+
+/// Build a short tour that visits every one of `nodes` and returns to the
+/// first, returning the nodes in the order they're visited and the total
+/// cost of the round trip.
+///
+/// This is the travelling salesman problem, which is too hard to solve
+/// exactly for more than a handful of nodes, so the tour is only
+/// approximate: it's built by always heading to the nearest node not yet
+/// visited, then improved with 2-opt moves (reversing a stretch of the tour
+/// whenever that makes it shorter) until no more help. `distance` gives the
+/// cost of travelling between two nodes, which must be the same in both
+/// directions; it's called only once for each unordered pair, with the
+/// nodes in the order they appear in `nodes`, so it can be as expensive as a
+/// search:
+///
+/// ```ignore
+/// travelling_salesman(stops.as_slice(), |a, b| {
+///     match dijkstra_search(&g, a, b, None) {
+///         Ok(Some((_, cost))) => cost,
+///         _ => UNREACHABLE
+///     }
+/// });
+/// ```
+pub fn travelling_salesman<'a, T, W: Cost>(nodes: &[&'a T], distance: |&'a T, &'a T| -> W)
+                                           -> (Vec<&'a T>, W) {
+    let n = nodes.len();
+    if n < 2 {
+        return (nodes.to_vec(), Zero::zero());
+    }
+
+    // Distances are symmetric, so only work out each pair once.
+    let mut costs: Vec<Vec<W>> = Vec::from_fn(n, |_| Vec::from_elem(n, Zero::zero()));
+    for i in range(0, n) {
+        for j in range(i + 1, n) {
+            let d = distance(nodes[i], nodes[j]);
+            *costs.get_mut(i).get_mut(j) = d;
+            *costs.get_mut(j).get_mut(i) = d;
+        }
+    }
+    let cost = |i: uint, j: uint| *costs.get(i).get(j);
+
+    // Start with the nearest neighbour tour.
+    let mut tour = vec!(0u);
+    let mut visited = Vec::from_elem(n, false);
+    *visited.get_mut(0) = true;
+
+    while tour.len() < n {
+        let last = *tour.last().unwrap();
+        let mut nearest: Option<(W, uint)> = None;
+        for j in range(0, n) {
+            if !*visited.get(j) && nearest.map_or(true, |(c, _)| cost(last, j) < c) {
+                nearest = Some((cost(last, j), j));
+            }
+        }
+
+        let (_, next) = nearest.unwrap();
+        *visited.get_mut(next) = true;
+        tour.push(next);
+    }
+
+    // Then untangle it: replacing edges a-b and c-d with a-c and b-d means
+    // reversing everything from b to c.
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for i in range(0, n - 1) {
+            for j in range(i + 2, n) {
+                if i == 0 && j == n - 1 {
+                    continue;
+                }
+
+                let (a, b, c, d) = (*tour.get(i), *tour.get(i + 1), *tour.get(j), *tour.get((j + 1) % n));
+                if cost(a, c) + cost(b, d) < cost(a, b) + cost(c, d) {
+                    tour.mut_slice(i + 1, j + 1).reverse();
+                    improved = true;
+                }
+            }
+        }
+    }
+
+    let mut total = Zero::zero();
+    for i in range(0, n) {
+        total = total + cost(*tour.get(i), *tour.get((i + 1) % n));
+    }
+
+    (tour.iter().map(|&i| nodes[i]).collect(), total)
+}
+
+/// Which of a set of nodes can reach which, as computed by
+/// `transitive_closure`.
+pub struct TransitiveClosure<'a, T> {
+    index: HashMap<&'a T, uint>,
+    // One row of bits for each node, `words` words long, with a bit set for
+    // every node it can reach.
+    words: uint,
+    bits: Vec<u32>
+}
+
+impl<'a, T: Eq + Hash> TransitiveClosure<'a, T> {
+    /// Whether there is any path from `from` to `to`. Every node can reach
+    /// itself.
+    pub fn reachable(&self, from: &'a T, to: &'a T) -> bool {
+        match (self.index.find(&from), self.index.find(&to)) {
+            (Some(&i), Some(&j)) => *self.bits.get(i * self.words + j / 32) & (1 << (j % 32)) != 0,
+            _ => from == to
+        }
+    }
+}
+
+/// Work out which of `nodes` can reach which, so that later questions can be
+/// answered without searching.
+///
+/// This searches breadth-first from every node, so it takes time
+/// proportional to the number of nodes times the number of edges, and the
+/// result takes a bit for every pair of nodes. Edges that lead outside of
+/// `nodes` are ignored.
+pub fn transitive_closure<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, nodes: &[&'a T]) -> TransitiveClosure<'a, T> {
+
+    let mut index = HashMap::new();
+    for (i, &node) in nodes.iter().enumerate() {
+        index.insert(node, i);
+    }
+
+    let adjacent: Vec<Vec<uint>> = nodes.iter().map(|&node| {
+        graph.neighbours(node).filter_map(|(_, next)| index.find(&next).map(|&j| j)).collect()
+    }).collect();
+
+    let words = (nodes.len() + 31) / 32;
+    let mut bits = Vec::from_elem(nodes.len() * words, 0u32);
+
+    for source in range(0, nodes.len()) {
+        let row = source * words;
+        let mut frontier = DList::new();
+        *bits.get_mut(row + source / 32) |= 1 << (source % 32);
+        frontier.push_back(source);
+
+        while !frontier.is_empty() {
+            let current = frontier.pop_front().unwrap();
+            for &next in adjacent.get(current).iter() {
+                let (word, bit) = (row + next / 32, 1 << (next % 32));
+                if *bits.get(word) & bit == 0 {
+                    *bits.get_mut(word) |= bit;
+                    frontier.push_back(next);
+                }
+            }
+        }
+    }
+
+    TransitiveClosure { index: index, words: words, bits: bits }
+}
+
+/// The dominator tree of a directed graph, as computed by `dominators`.
+///
+/// A node `a` dominates `b` if every path from the root to `b` passes
+/// through `a`. Each reachable node other than the root has an immediate
+/// dominator, the closest of its dominators, and these form a tree.
+pub struct DominatorTree<'a, T> {
+    root: &'a T,
+    idom: HashMap<&'a T, &'a T>
+}
+
+impl<'a, T: Eq + Hash> DominatorTree<'a, T> {
+    pub fn root(&self) -> &'a T {
+        self.root
+    }
+
+    /// The immediate dominator of `node`, or `None` for the root and for
+    /// nodes the root can't reach.
+    pub fn immediate_dominator(&self, node: &'a T) -> Option<&'a T> {
+        if node == self.root {
+            return None;
+        }
+        self.idom.find(&node).map(|&n| n)
+    }
+
+    /// Whether `a` dominates `b`. Every reachable node dominates itself.
+    pub fn dominates(&self, a: &'a T, b: &'a T) -> bool {
+        if !self.idom.contains_key(&b) {
+            return false;
+        }
+
+        let mut current = b;
+        loop {
+            if current == a {
+                return true;
+            }
+            if current == self.root {
+                return false;
+            }
+            current = *self.idom.get(&current);
+        }
+    }
+}
+
+/// Find the dominator tree of the nodes reachable from `root`, with the
+/// iterative algorithm of Cooper, Harvey, and Kennedy.
+///
+/// The nodes are numbered in reverse postorder, and each node's immediate
+/// dominator is repeatedly worked out as the nearest common ancestor of its
+/// predecessors' in the tree so far, until nothing changes. This is simpler
+/// than Lengauer-Tarjan and just as fast in practice.
+pub fn dominators<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, root: &'a T) -> DominatorTree<'a, T> {
+
+    let mut order = Vec::new();
+    let mut visited = HashSet::new();
+    depth_first_visit(graph, root, &mut visited, &mut None, &mut Some(|node| order.push(node)));
+    order.reverse();
+
+    let mut index = HashMap::new();
+    for (i, &node) in order.iter().enumerate() {
+        index.insert(node, i);
+    }
+
+    let mut predecessors = Vec::from_fn(order.len(), |_| Vec::new());
+    for (i, &node) in order.iter().enumerate() {
+        for (_, next) in graph.neighbours(node) {
+            predecessors.get_mut(*index.get(&next)).push(i);
+        }
+    }
+
+    // Nodes are numbered so that a node always comes after its dominators,
+    // so walking two nodes up the tree until they meet finds the nearest
+    // common dominator.
+    let mut idom: Vec<Option<uint>> = Vec::from_elem(order.len(), None);
+    *idom.get_mut(0) = Some(0);
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for node in range(1, order.len()) {
+            let mut new_idom = None;
+            for &p in predecessors.get(node).iter() {
+                if idom.get(p).is_none() {
+                    continue;
+                }
+
+                new_idom = Some(match new_idom {
+                    None => p,
+                    Some(other) => {
+                        let (mut a, mut b) = (p, other);
+                        while a != b {
+                            while a > b { a = idom.get(a).unwrap(); }
+                            while b > a { b = idom.get(b).unwrap(); }
+                        }
+                        a
+                    }
+                });
+            }
+
+            if new_idom != *idom.get(node) {
+                *idom.get_mut(node) = new_idom;
+                changed = true;
+            }
+        }
+    }
+
+    let mut tree = HashMap::new();
+    for (i, d) in idom.iter().enumerate() {
+        match *d {
+            Some(d) => { tree.insert(*order.get(i), *order.get(d)); },
+            None => ()
+        }
+    }
+
+    DominatorTree { root: root, idom: tree }
+}
+
+/// The starting points for measuring a graph: all of `nodes`, or if
+/// `samples` is given, that many of them chosen at random.
+fn sample_nodes<'a, T>(nodes: &[&'a T], samples: Option<uint>) -> Vec<&'a T> {
+    match samples {
+        Some(count) if count < nodes.len() => rand::sample(&mut task_rng(), nodes.iter().map(|&n| n), count),
+        _ => nodes.to_vec()
+    }
+}
+
+/// Find the eccentricity of each of `nodes`: the cost of the cheapest path
+/// to the furthest node it can reach.
+///
+/// This takes a full `dijkstra_all` from each node, which is too slow for
+/// large graphs, so if `samples` is given only that many nodes, chosen at
+/// random, are measured. Passing the result to `diameter` or `radius` then
+/// gives a lower bound on the diameter and an upper bound on the radius,
+/// which are usually close on graphs of any size.
+pub fn eccentricities<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, nodes: &[&'a T], samples: Option<uint>)
+    -> Result<HashMap<&'a T, W>, SearchError> {
+
+    let mut eccentricity = HashMap::new();
+    for &node in sample_nodes(nodes, samples).iter() {
+        let distances = try!(dijkstra_all(graph, node));
+        eccentricity.insert(node, distances.values().fold(Zero::zero(), |a: W, &b| max(a, b)));
+    }
+    Ok(eccentricity)
+}
+
+/// The diameter of a graph, which is its largest eccentricity.
+pub fn diameter<'a, T: Eq + Hash, W: Cost>(eccentricities: &HashMap<&'a T, W>) -> Option<W> {
+    eccentricities.values().map(|&e| e).max()
+}
+
+/// The radius of a graph, which is its smallest eccentricity.
+pub fn radius<'a, T: Eq + Hash, W: Cost>(eccentricities: &HashMap<&'a T, W>) -> Option<W> {
+    eccentricities.values().map(|&e| e).min()
+}
+
+/// Compute the betweenness centrality of each of `nodes` with Brandes'
+/// algorithm: how many cheapest paths between other pairs of nodes pass
+/// through it, where pairs with several equally cheap paths share the
+/// credit between them.
+///
+/// Each node is used as a source in turn, counting its cheapest paths with a
+/// single Dijkstra search and then adding up each node's share of them on
+/// the way back. If `samples` is given, only that many sources are used,
+/// chosen at random, and the result is scaled up to estimate the full
+/// count. In an undirected graph every pair is counted from both ends, so
+/// halve the scores to get the usual figures. Edge weights must not be
+/// negative.
+pub fn betweenness_centrality<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, nodes: &[&'a T], samples: Option<uint>)
+    -> Result<HashMap<&'a T, f64>, SearchError> {
+
+    let mut centrality: HashMap<&'a T, f64> = nodes.iter().map(|&n| (n, 0.0)).collect();
+    let sources = sample_nodes(nodes, samples);
+
+    for &source in sources.iter() {
+        let mut settled = Vec::new();
+        let mut predecessors: HashMap<&'a T, Vec<&'a T>> = HashMap::new();
+        let mut paths: HashMap<&'a T, f64> = HashMap::new();
+        let mut cost_so_far = HashMap::new();
+        let mut done = HashSet::new();
+        let mut frontier = PriorityQueue::new();
+
+        cost_so_far.insert(source, Zero::zero());
+        paths.insert(source, 1.0);
+        predecessors.insert(source, Vec::new());
+        frontier.push(MinPriorityNode { node: source, cost: Zero::zero() });
+
+        while !frontier.is_empty() {
+            let MinPriorityNode { node: current, cost: current_cost } = frontier.pop().unwrap();
+            if current_cost > *cost_so_far.get(&current) || !done.insert(current) {
+                continue;
+            }
+            settled.push(current);
+
+            for (cost, next) in graph.neighbours(current) {
+                if cost < Zero::zero() {
+                    return Err(NegativeWeight);
+                }
+
+                // A settled node's paths are final, even if a zero-weight
+                // edge leads back to it at the same cost.
+                if done.contains(&next) {
+                    continue;
+                }
+
+                let new_cost = current_cost + cost;
+                if cost_so_far.find(&next).map_or(true, |&c| new_cost < c) {
+                    cost_so_far.insert(next, new_cost);
+                    paths.insert(next, 0.0);
+                    predecessors.insert(next, Vec::new());
+                    frontier.push(MinPriorityNode { node: next, cost: new_cost });
+                }
+
+                // Every cheapest path to `current` extends to one to `next`.
+                if new_cost == *cost_so_far.get(&next) {
+                    let through = *paths.get(&current);
+                    *paths.get_mut(&next) += through;
+                    predecessors.get_mut(&next).push(current);
+                }
+            }
+        }
+
+        // Hand each node's share back to its predecessors, furthest first.
+        let mut dependency: HashMap<&'a T, f64> = HashMap::new();
+        for &node in settled.iter().rev() {
+            let share = *dependency.find(&node).unwrap_or(&0.0);
+            for &p in predecessors.get(&node).iter() {
+                let credit = *paths.get(&p) / *paths.get(&node) * (1.0 + share);
+                *dependency.find_or_insert(p, 0.0) += credit;
+            }
+
+            if node != source {
+                match centrality.find_mut(&node) {
+                    Some(c) => *c += share,
+                    None => ()
+                }
+            }
+        }
+    }
+
+    if sources.len() < nodes.len() && sources.len() > 0 {
+        let scale = nodes.len() as f64 / sources.len() as f64;
+        for (_, c) in centrality.mut_iter() {
+            *c *= scale;
+        }
+    }
+
+    Ok(centrality)
+}
+
+/// Compute the closeness centrality of each of `nodes`: the number of other
+/// nodes it can reach divided by the total cost of reaching them, which is
+/// the reciprocal of its average distance.
+///
+/// Nodes are scored by a `dijkstra_all` from each, so on large graphs pass
+/// `samples` to score only that many nodes, chosen at random. Distances are
+/// measured outwards along the edges. As with `harmonic_centrality`, nodes
+/// reached at zero cost are skipped rather than counted as infinitely close,
+/// so a node that reaches nothing else at a positive cost scores zero. Since
+/// only reachable nodes are counted, a node in a small component can
+/// outscore one in a large component; `harmonic_centrality` avoids this.
+pub fn closeness_centrality<'a, T: Eq + Hash, W: Cost + ToPrimitive, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, nodes: &[&'a T], samples: Option<uint>)
+    -> Result<HashMap<&'a T, f64>, SearchError> {
+
+    let mut closeness = HashMap::new();
+    for &node in sample_nodes(nodes, samples).iter() {
+        let distances = try!(dijkstra_all(graph, node));
+        let costs: Vec<f64> = distances.values().map(|d| d.to_f64().unwrap()).filter(|&d| d > 0.0).collect();
+        let total = costs.iter().fold(0.0, |a, &d| a + d);
+        closeness.insert(node, if total == 0.0 { 0.0 } else { costs.len() as f64 / total });
+    }
+    Ok(closeness)
+}
+
+/// Compute the harmonic centrality of each of `nodes`: the sum of the
+/// reciprocals of its distances to every other node it can reach.
+///
+/// Unreachable nodes simply add nothing, so unlike `closeness_centrality`
+/// this is meaningful on disconnected graphs. As there, `samples` limits the
+/// number of nodes scored. Nodes reached at zero cost are skipped rather
+/// than counted as infinitely close.
+pub fn harmonic_centrality<'a, T: Eq + Hash, W: Cost + ToPrimitive, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, nodes: &[&'a T], samples: Option<uint>)
+    -> Result<HashMap<&'a T, f64>, SearchError> {
+
+    let mut harmonic = HashMap::new();
+    for &node in sample_nodes(nodes, samples).iter() {
+        let distances = try!(dijkstra_all(graph, node));
+        let score = distances.values()
+            .map(|d| d.to_f64().unwrap())
+            .filter(|&d| d > 0.0)
+            .fold(0.0, |a, d| a + 1.0 / d);
+        harmonic.insert(node, score);
+    }
+    Ok(harmonic)
+}
+
+/// The most rounds of power iteration `pagerank` will run before returning
+/// its current scores, in case the tolerance is never met.
+static PAGERANK_MAX_ITERATIONS: uint = 1000;
+
+/// Rank `nodes` by importance with PageRank: the share of its time a random
+/// walker would spend at each node if it followed a random edge out of its
+/// current node with probability `damping`, and otherwise jumped to a node
+/// chosen uniformly at random.
+///
+/// Every edge out of a node is equally likely to be followed, whatever its
+/// weight, since weights here are costs rather than strengths. Walkers at a
+/// node with no edges out jump at random, and edges that leave `nodes` are
+/// ignored. Rounds of power iteration are run until the scores change by
+/// less than `tolerance` in total; they always sum to one.
+///
+/// ```ignore
+/// let ranks = pagerank(&graph, nodes.as_slice(), 0.85, 1e-6);
+/// ```
+pub fn pagerank<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, nodes: &[&'a T], damping: f64, tolerance: f64)
+    -> HashMap<&'a T, f64> {
+
+    let count = nodes.len();
+    if count == 0 {
+        return HashMap::new();
+    }
+
+    let index: HashMap<&'a T, uint> = nodes.iter().enumerate().map(|(i, &n)| (n, i)).collect();
+    let links: Vec<Vec<uint>> = nodes.iter().map(|&node| {
+        graph.neighbours(node).filter_map(|(_, next)| index.find(&next).map(|&i| i)).collect()
+    }).collect();
+
+    let mut rank = Vec::from_elem(count, 1.0 / count as f64);
+    for _ in range(0, PAGERANK_MAX_ITERATIONS) {
+        // Rank held by dead ends is spread over every node, like the jumps.
+        let stranded = range(0, count).filter(|&i| links.get(i).is_empty())
+            .fold(0.0, |a, i| a + *rank.get(i));
+        let base = (1.0 - damping + damping * stranded) / count as f64;
+
+        let mut next = Vec::from_elem(count, base);
+        for (i, out) in links.iter().enumerate() {
+            if out.is_empty() {
+                continue;
+            }
+            let share = damping * *rank.get(i) / out.len() as f64;
+            for &j in out.iter() {
+                *next.get_mut(j) += share;
+            }
+        }
+
+        let change = rank.iter().zip(next.iter()).fold(0.0, |a, (&x, &y)| a + (x - y).abs());
+        rank = next;
+        if change < tolerance {
+            break;
+        }
+    }
+
+    nodes.iter().map(|&n| n).zip(rank.move_iter()).collect()
+}
+
+/// An endless walk through a graph that moves to a random neighbour at each
+/// step, drawing from `rng`. The start node is yielded first, and the walk
+/// ends early only if it reaches a node with no edges out.
+///
+/// A walk made with `new` picks each neighbour with equal probability, while
+/// one made with `biased` picks edges in proportion to their weights, which
+/// must then not be negative. Either way, pass a seeded generator to make
+/// the walk repeatable:
+///
+/// ```ignore
+/// let rng: StdRng = SeedableRng::from_seed(&[1, 2, 3, 4]);
+/// let walk: Vec<&(uint, uint)> = RandomWalk::new(&grid, start, rng).take(80).collect();
+/// ```
+pub struct RandomWalk<'a, T, W, I, R> {
+    graph: &'a graph::WeightedGraph<'a, T, W, I>,
+    current: Option<&'a T>,
+    rng: R,
+    biased: bool,
+    started: bool
+}
+
+impl<'a, T, W: Cost + ToPrimitive, I: Iterator<(W, &'a T)>, R: rand::Rng> RandomWalk<'a, T, W, I, R> {
+
+    pub fn new(graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, rng: R)
+        -> RandomWalk<'a, T, W, I, R> {
+        RandomWalk { graph: graph, current: Some(start), rng: rng, biased: false, started: false }
+    }
+
+    pub fn biased(graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, rng: R)
+        -> RandomWalk<'a, T, W, I, R> {
+        RandomWalk { graph: graph, current: Some(start), rng: rng, biased: true, started: false }
+    }
+
+    fn step(&mut self, node: &'a T) -> Option<&'a T> {
+        let neighbours: Vec<(W, &'a T)> = self.graph.neighbours(node).collect();
+        if neighbours.is_empty() {
+            return None;
+        }
+
+        if !self.biased {
+            let i = self.rng.gen_range(0, neighbours.len());
+            let &(_, next) = neighbours.get(i);
+            return Some(next);
+        }
+
+        let total = neighbours.iter().fold(0.0, |a, &(w, _)| a + w.to_f64().unwrap());
+        if total <= 0.0 {
+            return None;
+        }
+
+        let mut target = self.rng.gen::<f64>() * total;
+        for &(w, next) in neighbours.iter() {
+            target -= w.to_f64().unwrap();
+            if target < 0.0 {
+                return Some(next);
+            }
+        }
+        // Rounding can leave a sliver over; it belongs to the last edge that
+        // can actually be taken.
+        neighbours.iter().rev().find(|&&(w, _)| w > Zero::zero()).map(|&(_, next)| next)
+    }
+}
+
+impl<'a, T, W: Cost + ToPrimitive, I: Iterator<(W, &'a T)>, R: rand::Rng>
+    Iterator<&'a T> for RandomWalk<'a, T, W, I, R> {
+
+    fn next(&mut self) -> Option<&'a T> {
+        if !self.started {
+            self.started = true;
+            return self.current;
+        }
+
+        self.current = match self.current {
+            Some(node) => self.step(node),
+            None => None
+        };
+        self.current
+    }
+}
+
+/// Lowest common ancestor and tree distance queries on a rooted tree, by
+/// binary lifting.
+///
+/// Each node keeps its ancestors 1, 2, 4, ... levels up, so both kinds of
+/// query climb the tree in O(log n) jumps instead of searching it. If the
+/// graph is not itself a tree, the one used is the breadth-first spanning
+/// tree found from the root, and distances are measured along it.
+pub struct TreeAncestors<'a, T, W> {
+    nodes: Vec<&'a T>,
+    index: HashMap<&'a T, uint>,
+    depth: Vec<uint>,
+    // The cost of the tree path from the root to each node.
+    cost: Vec<W>,
+    // up[k][i] is the ancestor 2^k levels above node i, or the root if that
+    // would be past it.
+    up: Vec<Vec<uint>>
+}
+
+impl<'a, T: Eq + Hash, W: Cost + Sub<W, W>> TreeAncestors<'a, T, W> {
+
+    pub fn new<I: Iterator<(W, &'a T)>>(graph: &'a graph::WeightedGraph<'a, T, W, I>, root: &'a T)
+        -> TreeAncestors<'a, T, W> {
+
+        let mut nodes = vec![root];
+        let mut index = HashMap::new();
+        let mut depth = vec![0u];
+        let mut cost: Vec<W> = vec![Zero::zero()];
+        let mut parent = vec![0u];
+        index.insert(root, 0u);
+
+        let mut i = 0;
+        while i < nodes.len() {
+            let node = *nodes.get(i);
+            for (weight, next) in graph.neighbours(node) {
+                if index.contains_key(&next) {
+                    continue;
+                }
+                index.insert(next, nodes.len());
+                nodes.push(next);
+                depth.push(*depth.get(i) + 1);
+                cost.push(*cost.get(i) + weight);
+                parent.push(i);
+            }
+            i += 1;
+        }
+
+        let deepest = depth.iter().fold(0, |a, &d| max(a, d));
+        let mut up = vec![parent];
+        while 1u << up.len() <= deepest {
+            let next = {
+                let last = up.last().unwrap();
+                last.iter().map(|&p| *last.get(p)).collect()
+            };
+            up.push(next);
+        }
+
+        TreeAncestors { nodes: nodes, index: index, depth: depth, cost: cost, up: up }
+    }
+
+    /// How many edges lie between `node` and the root, or `None` if `node`
+    /// is not in the tree.
+    pub fn depth(&self, node: &'a T) -> Option<uint> {
+        self.index.find(&node).map(|&i| *self.depth.get(i))
+    }
+
+    /// The ancestor `levels` edges above `node`, or `None` if that would be
+    /// above the root.
+    pub fn ancestor(&self, node: &'a T, levels: uint) -> Option<&'a T> {
+        let i = match self.index.find(&node) {
+            Some(&i) => i,
+            None => return None
+        };
+        if levels > *self.depth.get(i) {
+            return None;
+        }
+        Some(*self.nodes.get(self.climb(i, levels)))
+    }
+
+    /// The deepest node that is an ancestor of both `a` and `b`, counting
+    /// each node as its own ancestor.
+    pub fn lowest_common_ancestor(&self, a: &'a T, b: &'a T) -> Option<&'a T> {
+        match (self.index.find(&a), self.index.find(&b)) {
+            (Some(&i), Some(&j)) => Some(*self.nodes.get(self.meet(i, j))),
+            _ => None
+        }
+    }
+
+    /// The cost of the tree path between `a` and `b`.
+    pub fn distance(&self, a: &'a T, b: &'a T) -> Option<W> {
+        match (self.index.find(&a), self.index.find(&b)) {
+            (Some(&i), Some(&j)) => {
+                let above = *self.cost.get(self.meet(i, j));
+                Some((*self.cost.get(i) - above) + (*self.cost.get(j) - above))
+            },
+            _ => None
+        }
+    }
+
+    fn climb(&self, mut node: uint, levels: uint) -> uint {
+        for (k, jumps) in self.up.iter().enumerate() {
+            if levels & (1 << k) != 0 {
+                node = *jumps.get(node);
+            }
+        }
+        node
+    }
+
+    fn meet(&self, a: uint, b: uint) -> uint {
+        let (da, db) = (*self.depth.get(a), *self.depth.get(b));
+        let (mut a, mut b) = if da > db {
+            (self.climb(a, da - db), b)
+        } else {
+            (a, self.climb(b, db - da))
+        };
+        if a == b {
+            return a;
+        }
+
+        // Jump both up as far as possible without meeting; the answer is
+        // then one level above.
+        for jumps in self.up.iter().rev() {
+            if *jumps.get(a) != *jumps.get(b) {
+                a = *jumps.get(a);
+                b = *jumps.get(b);
+            }
+        }
+        *self.up.get(0).get(a)
+    }
+}
+
+/// The outcome of a depth-limited search.
+#[deriving(PartialEq, Show)]
+pub enum DepthLimitedResult<'a, T> {
+    /// The goal was reached by the enclosed path.
+    Found(Vec<&'a T>),
+    /// The goal was not found, but there were paths that could not be
+    /// followed any further without exceeding the depth limit.
+    Cutoff,
+    /// The goal cannot be reached from the start at any depth.
+    Unreachable
+}
+
+/// Search for `goal` along every path extending the one in `path` with no
+/// more than `limit` edges, without revisiting any node already on it.
+fn depth_limited_visit<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, goal: &'a T, limit: uint,
+     path: &mut Vec<&'a T>) -> DepthLimitedResult<'a, T> {
+
+    let current = *path.last().unwrap();
+
+    if current == goal {
+        return Found(path.clone());
+    }
+
+    if path.len() > limit {
+        // Report a cutoff only if there was actually somewhere left to go.
+        let mut neighbours = graph.neighbours(current);
+        return if neighbours.any(|(_, next)| !path.contains(&next)) { Cutoff } else { Unreachable };
+    }
+
+    let mut cutoff = false;
+
+    for (_, next) in graph.neighbours(current) {
+        if path.contains(&next) {
+            continue;
+        }
+
+        path.push(next);
+        match depth_limited_visit(graph, goal, limit, path) {
+            Found(found) => return Found(found),
+            Cutoff => cutoff = true,
+            Unreachable => ()
+        }
+        path.pop();
+    }
+
+    if cutoff { Cutoff } else { Unreachable }
+}
+
+/// Find a path from `start` to `goal` with the fewest edges using repeated
+/// depth-limited searches of increasing depth.
+///
+/// Only the current path is kept in memory, which makes this suitable for
+/// graphs where the frontier of `breadth_first_search` would be too large,
+/// at the cost of re-exploring shallow nodes on every iteration. If
+/// `max_depth` is given, no path with more edges than that is considered and
+/// `Cutoff` is returned if searching deeper might have found the goal. Fails
+/// with `StartNotFound` or `GoalNotFound` if either endpoint isn't in the
+/// graph.
+pub fn iterative_deepening_search<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     max_depth: Option<uint>) -> Result<DepthLimitedResult<'a, T>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+    let mut limit = 0u;
+
+    loop {
+        let mut path = vec!(start);
+
+        match depth_limited_visit(graph, goal, limit, &mut path) {
+            Cutoff if max_depth.map_or(true, |max| limit < max) => limit += 1,
+            result => return Ok(result)
+        }
+    }
+}
+
+mod priority {
+
+    /// This is a simple struct to modify the PriortyQueue's behaviour so that
+    /// it uses the minimum instead of the maximum element.
+    ///
+    /// Taken almost straight from the `std::collections::priority_queue` docs.
+    #[deriving(Eq, PartialEq)]
+    pub struct MinPriorityNode<'a, T, W> {
+        pub node: T,
+        pub cost: W
+    }
+
+    impl<'a, T: Eq, W: Ord> Ord for MinPriorityNode<'a, T, W> {
+        fn cmp(&self, other: &MinPriorityNode<'a, T, W>) -> Ordering {
+            other.cost.cmp(&self.cost)
+        }
+    }
+
+    impl<'a, T: PartialEq + Eq, W: Ord> PartialOrd for MinPriorityNode<'a, T, W> {
+        fn partial_cmp(&self, other: &MinPriorityNode<'a, T, W>) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    /// The counterpart of `MinPriorityNode` for searches that want the
+    /// largest element first, which is the PriorityQueue's natural order.
+    #[deriving(Eq, PartialEq)]
+    pub struct MaxPriorityNode<'a, T, W> {
+        pub node: T,
+        pub cost: W
+    }
+
+    impl<'a, T: Eq, W: Ord> Ord for MaxPriorityNode<'a, T, W> {
+        fn cmp(&self, other: &MaxPriorityNode<'a, T, W>) -> Ordering {
+            self.cost.cmp(&other.cost)
+        }
+    }
+
+    impl<'a, T: PartialEq + Eq, W: Ord> PartialOrd for MaxPriorityNode<'a, T, W> {
+        fn partial_cmp(&self, other: &MaxPriorityNode<'a, T, W>) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+}
+
+/// Walk the `came_from` chain backwards from `goal` to `start`, returning the
+/// nodes along the way in start-to-goal order.
+fn reconstruct_path<'a, T: Eq + Hash>(came_from: &HashMap<&'a T, &'a T>,
+                                       start: &'a T, goal: &'a T) -> Vec<&'a T> {
+    let mut path = vec!(goal);
+    let mut current = goal;
+
+    while current != start {
+        current = *came_from.get(&current);
+        path.push(current);
+    }
+
+    path.reverse();
+    path
+}
+
+/// The machinery behind `dijkstra_search` and the algorithms built on it.
+///
+/// Runs until a node for which `is_goal` returns true is expanded, or until
+/// every reachable node has been, and returns the predecessor and cost
+/// tables. Costs in the table are final for every expanded node, and so for
+/// the goal that stopped the search. Edges for which `allowed` returns false
+/// are ignored.
+fn dijkstra_tables<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, is_goal: |&'a T| -> bool,
+     visit: &mut Option<|&'a T, W|>, allowed: |&'a T, &'a T| -> bool)
+    -> Result<(HashMap<&'a T, &'a T>, HashMap<&'a T, W>), SearchError> {
+    counted_dijkstra_tables(graph, start, is_goal, visit, allowed, &mut SearchCounters::new())
+}
+
+/// As `dijkstra_tables`, keeping count of the work done in `counters`.
+fn counted_dijkstra_tables<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, is_goal: |&'a T| -> bool,
+     visit: &mut Option<|&'a T, W|>, allowed: |&'a T, &'a T| -> bool, counters: &mut SearchCounters)
+    -> Result<(HashMap<&'a T, &'a T>, HashMap<&'a T, W>), SearchError> {
+
+    try!(check_endpoints(graph, start, None));
+
+    let mut frontier = PriorityQueue::new();
+    let mut came_from = HashMap::new();
+    let mut cost_so_far = HashMap::new();
+
+    frontier.push(MinPriorityNode { node: start, cost: Zero::zero() });
+    came_from.insert(start, start);
+    cost_so_far.insert(start, Zero::zero());
+
+    while !frontier.is_empty() {
+        let MinPriorityNode { node: current, cost: current_cost } = frontier.pop().unwrap();
+
+        // Skip entries that were superseded by a cheaper route.
+        if current_cost > *cost_so_far.get(&current) {
+            continue;
+        }
+
+        match *visit {
+            Some(ref mut f) => (*f)(current, current_cost),
+            None => ()
+        }
+
+        // Check if we've reached the goal.
+        if is_goal(current) {
+            break;
+        }
+        counters.expanded += 1;
+
+        for (cost, next) in graph.neighbours(current) {
+            if cost < Zero::zero() {
+                return Err(NegativeWeight);
+            }
+
+            if !allowed(current, next) {
+                continue;
+            }
+
+            let new_cost = current_cost + cost;
+            
+            if cost_so_far.contains_key(&next) && new_cost >= *cost_so_far.get(&next) {
+                continue;
+            } else {
+                cost_so_far.insert_or_update_with(next, new_cost, |_, v| *v = new_cost);
+                came_from.insert_or_update_with(next, current, |_, v| *v = current);
+                frontier.push(MinPriorityNode { node: next, cost: new_cost });
+                counters.generated(frontier.len());
+            }
+        }
+    }
+
+    // When stopping early, nodes that were reached but not expanded may not
+    // have their final costs yet, but the goal always does.
+    Ok((came_from, cost_so_far))
+}
+
+/// Find the cheapest path from `start` to `goal`.
+///
+/// Returns the path (including both endpoints) and its total cost, or `None`
+/// if the goal cannot be reached. If `visit` is specified, it is called with
+/// each node as it is expanded, along with the cost of reaching it.
+///
+/// Edge weights must not be negative; if one is encountered the search is
+/// abandoned with a `NegativeWeight` error, since the result could otherwise
+/// silently be wrong. Likewise, a start or goal that isn't in the graph
+/// fails with `StartNotFound` or `GoalNotFound`.
+pub fn dijkstra_search<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     mut visit: Option<|&'a T, W|>) -> Result<Option<(Vec<&'a T>, W)>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+    let (came_from, cost_so_far) = try!(dijkstra_tables(graph, start, |n| n == goal, &mut visit, |_, _| true));
+
+    Ok(cost_so_far.find(&goal).map(|&cost| (reconstruct_path(&came_from, start, goal), cost)))
+}
+
+/// As `dijkstra_search`, but also report how much work the search did and
+/// how long it took, even if no path was found.
+pub fn dijkstra_search_with_stats<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T)
+    -> Result<SearchResult<'a, T, W>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+    let started = time::precise_time_ns();
+    let mut counters = SearchCounters::new();
+    let (came_from, cost_so_far) = try!(counted_dijkstra_tables(graph, start, |n| n == goal, &mut None,
+                                                                |_, _| true, &mut counters));
+
+    Ok(SearchResult {
+        path: cost_so_far.find(&goal).map(|&cost| (reconstruct_path(&came_from, start, goal), cost)),
+        stats: counters.stats(started)
+    })
+}
+
+/// As `dijkstra_search`, but give up with `OverCostLimit` once every route
+/// left to try would cost more than `limit`, for questions such as whether
+/// the goal is within a unit's movement range.
+pub fn dijkstra_search_within<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T, limit: W)
+    -> Result<LimitedSearchResult<'a, T, W>, SearchError> {
+
+    dijkstra_search_limited(graph, start, goal, &SearchLimits::none().with_cost(limit))
+}
+
+/// As `dijkstra_search`, but give up as soon as any of `limits` is reached.
+/// A partial path from giving up early leads to the node expanded last.
+pub fn dijkstra_search_limited<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     limits: &SearchLimits<W>) -> Result<LimitedSearchResult<'a, T, W>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+    limited_a_star(graph, start, |n| n == goal, |_| Zero::zero(), &mut None, &mut SearchCounters::new(),
+                   limits, true)
+}
+
+/// As `dijkstra_search_limited`, but also report how much work the search
+/// did and how long it took, however it ended.
+pub fn dijkstra_search_limited_with_stats<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     limits: &SearchLimits<W>) -> Result<(LimitedSearchResult<'a, T, W>, SearchStats), SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+    let started = time::precise_time_ns();
+    let mut counters = SearchCounters::new();
+    let result = try!(limited_a_star(graph, start, |n| n == goal, |_| Zero::zero(), &mut None, &mut counters,
+                                     limits, true));
+    Ok((result, counters.stats(started)))
+}
+
+/// As `dijkstra_search`, but for an `IndexGraph`, keeping the search's
+/// tables in arrays indexed by `NodeId` instead of in hash maps.
+pub fn indexed_dijkstra_search<T: Eq + Hash + Clone, W: Cost + Clone>
+    (graph: &graph::IndexGraph<T, W>, start: graph::NodeId, goal: graph::NodeId)
+    -> Result<Option<(Vec<graph::NodeId>, W)>, SearchError> {
+
+    let n = graph.len();
+    if start.index() >= n {
+        return Err(StartNotFound);
+    } else if goal.index() >= n {
+        return Err(GoalNotFound);
+    }
+
+    let mut came_from: Vec<Option<graph::NodeId>> = Vec::from_fn(n, |_| None);
+    let mut cost_so_far: Vec<Option<W>> = Vec::from_fn(n, |_| None);
+    let mut frontier = PriorityQueue::new();
+
+    *cost_so_far.get_mut(start.index()) = Some(Zero::zero());
+    frontier.push(MinPriorityNode { node: start, cost: Zero::zero() });
+
+    while !frontier.is_empty() {
+        let MinPriorityNode { node: current, cost: current_cost } = frontier.pop().unwrap();
+        if cost_so_far.get(current.index()).map_or(false, |c| current_cost > c) {
+            continue;
+        }
+
+        if current == goal {
+            let mut path = vec!(goal);
+            let mut node = goal;
+            while node != start {
+                node = came_from.get(node.index()).unwrap();
+                path.push(node);
+            }
+            path.reverse();
+            return Ok(Some((path, current_cost)));
+        }
+
+        for &(cost, next) in graph.edges(current).iter() {
+            if cost < Zero::zero() {
+                return Err(NegativeWeight);
+            }
+
+            let new_cost = current_cost + cost;
+            if cost_so_far.get(next.index()).map_or(true, |c| new_cost < c) {
+                *cost_so_far.get_mut(next.index()) = Some(new_cost);
+                *came_from.get_mut(next.index()) = Some(current);
+                frontier.push(MinPriorityNode { node: next, cost: new_cost });
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Run Dijkstra's algorithm until every node reachable from `start` has been
+/// expanded, returning the cost of the cheapest path to each of them.
+///
+/// This is useful for precomputing distances, or for range queries such as
+/// finding everything within a given cost of the start. As with
+/// `dijkstra_search`, edge weights must not be negative.
+pub fn dijkstra_all<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T)
+    -> Result<HashMap<&'a T, W>, SearchError> {
+
+    let (_, cost_so_far) = try!(dijkstra_tables(graph, start, |_| false, &mut None, |_, _| true));
+    Ok(cost_so_far)
+}
+
+/// The cheapest paths from one node to every node it can reach, as found by
+/// `dijkstra_predecessors`, stored as the previous node on each path.
+pub struct PredecessorMap<'a, T, W> {
+    start: &'a T,
+    came_from: HashMap<&'a T, &'a T>,
+    cost_so_far: HashMap<&'a T, W>
+}
+
+impl<'a, T: Eq + Hash, W: Cost> PredecessorMap<'a, T, W> {
+    pub fn start(&self) -> &'a T {
+        self.start
+    }
+
+    /// The node before `node` on the cheapest path to it, or `None` for the
+    /// start and for nodes that can't be reached.
+    pub fn predecessor(&self, node: &'a T) -> Option<&'a T> {
+        if node == self.start { None } else { self.came_from.find(&node).map(|&n| n) }
+    }
+
+    /// The cost of the cheapest path to `node`.
+    pub fn cost_to(&self, node: &'a T) -> Option<W> {
+        self.cost_so_far.find(&node).map(|&c| c)
+    }
+
+    /// The cheapest path to `node`, including both endpoints.
+    pub fn path_to(&self, node: &'a T) -> Option<Vec<&'a T>> {
+        if self.came_from.contains_key(&node) {
+            Some(reconstruct_path(&self.came_from, self.start, node))
+        } else {
+            None
+        }
+    }
+}
+
+/// Run Dijkstra's algorithm until every node reachable from `start` has been
+/// expanded, returning the cheapest path to each of them. This is cheaper
+/// than a `dijkstra_search` per target when paths to many targets are
+/// wanted. As there, edge weights must not be negative.
+pub fn dijkstra_predecessors<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T)
+    -> Result<PredecessorMap<'a, T, W>, SearchError> {
+
+    let (came_from, cost_so_far) = try!(dijkstra_tables(graph, start, |_| false, &mut None, |_, _| true));
+    Ok(PredecessorMap { start: start, came_from: came_from, cost_so_far: cost_so_far })
+}
+
+/// As `dijkstra_search`, but stop at the nearest node for which `is_goal`
+/// returns true, returning it along with the path to it and the path's
+/// cost. This saves listing every acceptable goal up front.
+pub fn dijkstra_search_where<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, is_goal: |&'a T| -> bool,
+     mut visit: Option<|&'a T, W|>) -> Result<Option<(&'a T, Vec<&'a T>, W)>, SearchError> {
+
+    let mut found = None;
+    let (came_from, cost_so_far) = try!(dijkstra_tables(graph, start, |n| {
+        if is_goal(n) { found = Some(n); true } else { false }
+    }, &mut visit, |_, _| true));
+
+    Ok(found.map(|goal| (goal, reconstruct_path(&came_from, start, goal), *cost_so_far.get(&goal))))
+}
+
+/// As `dijkstra_search`, but stop at the nearest of several `goals`,
+/// returning it along with the path to it and the path's cost.
+pub fn dijkstra_search_any<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goals: &[&'a T],
+     visit: Option<|&'a T, W|>) -> Result<Option<(&'a T, Vec<&'a T>, W)>, SearchError> {
+
+    for &goal in goals.iter() {
+        try!(check_endpoints(graph, start, Some(goal)));
+    }
+
+    let goals: HashSet<&'a T> = goals.iter().map(|&g| g).collect();
+    dijkstra_search_where(graph, start, |n| goals.contains(&n), visit)
+}
+
+/// A Dijkstra search that can be paused and resumed, keeping its frontier
+/// and tables in between, so that an expensive search can be spread over
+/// several frames of a game:
+///
+/// ```ignore
+/// let mut search = Dijkstra::new(&graph, start);
+/// loop {
+///     match try!(search.run_until(goal, 1000)) {
+///         Some((path, cost)) => { follow(path); break; },
+///         None if search.is_finished() => break,
+///         None => wait_for_next_frame()
+///     }
+/// }
+/// ```
+///
+/// The cost of reaching a node is final once it has been settled, and the
+/// search can carry on past any goal to answer questions about others. A
+/// start or goal that isn't in the graph is reported by the first call that
+/// runs the search.
+pub struct Dijkstra<'a, T, W, I> {
+    graph: &'a graph::WeightedGraph<'a, T, W, I>,
+    start: &'a T,
+    frontier: PriorityQueue<MinPriorityNode<'a, &'a T, W>>,
+    came_from: HashMap<&'a T, &'a T>,
+    cost_so_far: HashMap<&'a T, W>,
+    settled: HashSet<&'a T>
+}
+
+impl<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>> Dijkstra<'a, T, W, I> {
+
+    pub fn new(graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T) -> Dijkstra<'a, T, W, I> {
+        let mut search = Dijkstra {
+            graph: graph,
+            start: start,
+            frontier: PriorityQueue::new(),
+            came_from: HashMap::new(),
+            cost_so_far: HashMap::new(),
+            settled: HashSet::new()
+        };
+        search.frontier.push(MinPriorityNode { node: start, cost: Zero::zero() });
+        search.came_from.insert(start, start);
+        search.cost_so_far.insert(start, Zero::zero());
+        search
+    }
+
+    /// Settle up to `expansions` more nodes, returning whether the search
+    /// is now finished because every reachable node has been settled.
+    pub fn run_for(&mut self, expansions: uint) -> Result<bool, SearchError> {
+        try!(check_endpoints(self.graph, self.start, None));
+        for _ in range(0, expansions) {
+            if try!(self.step()).is_none() {
+                break;
+            }
+        }
+        Ok(self.is_finished())
+    }
+
+    /// Carry on until `goal` is settled, or until `expansions` more nodes
+    /// have been, returning the path to the goal and its cost in the first
+    /// case and `None` in the second. If the goal was settled earlier, this
+    /// returns its path straight away.
+    pub fn run_until(&mut self, goal: &'a T, expansions: uint)
+        -> Result<Option<(Vec<&'a T>, W)>, SearchError> {
+
+        try!(check_endpoints(self.graph, self.start, Some(goal)));
+        let mut remaining = expansions;
+        while !self.settled.contains(&goal) && remaining > 0 {
+            if try!(self.step()).is_none() {
+                break;
+            }
+            remaining -= 1;
+        }
+        Ok(self.path_to(goal))
+    }
+
+    /// Whether every node reachable from the start has been settled.
+    pub fn is_finished(&self) -> bool {
+        self.frontier.is_empty()
+    }
+
+    pub fn is_settled(&self, node: &'a T) -> bool {
+        self.settled.contains(&node)
+    }
+
+    /// The cost of the cheapest path to `node`, if it has been settled.
+    pub fn distance(&self, node: &'a T) -> Option<W> {
+        if self.settled.contains(&node) { self.cost_so_far.find(&node).map(|&c| c) } else { None }
+    }
+
+    /// The cheapest path to `node` and its cost, if it has been settled.
+    pub fn path_to(&self, node: &'a T) -> Option<(Vec<&'a T>, W)> {
+        self.distance(node).map(|cost| (reconstruct_path(&self.came_from, self.start, node), cost))
+    }
+
+    /// Settle one node, returning it, or `None` if there are none left.
+    fn step(&mut self) -> Result<Option<&'a T>, SearchError> {
+        while !self.frontier.is_empty() {
+            let MinPriorityNode { node: current, cost: current_cost } = self.frontier.pop().unwrap();
+
+            // Skip entries that were superseded by a cheaper route.
+            if self.settled.contains(&current) {
+                continue;
+            }
+
+            // Check every edge before settling anything, so that a failed
+            // step leaves the search as it was and fails the same way again.
+            let edges: Vec<(W, &'a T)> = self.graph.neighbours(current).collect();
+            if edges.iter().any(|&(cost, _)| cost < Zero::zero()) {
+                self.frontier.push(MinPriorityNode { node: current, cost: current_cost });
+                return Err(NegativeWeight);
+            }
+
+            self.settled.insert(current);
+            for &(cost, next) in edges.iter() {
+                let new_cost = current_cost + cost;
+                if self.cost_so_far.find(&next).map_or(true, |&c| new_cost < c) {
+                    self.cost_so_far.insert(next, new_cost);
+                    self.came_from.insert(next, current);
+                    self.frontier.push(MinPriorityNode { node: next, cost: new_cost });
+                }
+            }
+
+            self.discard_settled();
+            return Ok(Some(current));
+        }
+        Ok(None)
+    }
+
+    /// Drop superseded entries from the top of the frontier, so that it's
+    /// only empty once every reachable node has been settled.
+    fn discard_settled(&mut self) {
+        while self.frontier.top().map_or(false, |entry| self.settled.contains(&entry.node)) {
+            self.frontier.pop();
+        }
+    }
+}
+
+/// One step of a search, as reported by `SearchSteps`.
+#[deriving(Clone, PartialEq, Show)]
+pub enum SearchEvent<'a, T, W> {
+    /// A node was taken off the frontier to have its neighbours looked at,
+    /// at the enclosed cost from the start.
+    NodeExpanded(&'a T, W),
+    /// A cheaper route to the second node was found through the first, at
+    /// the enclosed cost, and it was put on the frontier.
+    EdgeRelaxed(&'a T, &'a T, W),
+    /// The goal was reached by the enclosed path, at the enclosed cost. This
+    /// is always the last event.
+    GoalFound(Vec<&'a T>, W),
+    /// The search had to be abandoned, as with the errors returned by
+    /// `a_star_search`. This is also always the last event.
+    SearchFailed(SearchError)
+}
+
+/// An A* search that is driven from outside, one event per call to
+/// `next()`, for visualising how it explores the graph. A heuristic that
+/// always returns zero makes it a Dijkstra search.
+///
+/// ```ignore
+/// for event in SearchSteps::new(&grid, start, goal, |cell| manhattan(cell, goal)) {
+///     draw(event);
+/// }
+/// ```
+pub struct SearchSteps<'a, 'h, T, W, I> {
+    graph: &'a graph::WeightedGraph<'a, T, W, I>,
+    start: &'a T,
+    goal: &'a T,
+    heuristic: |&T|: 'h -> W,
+    frontier: PriorityQueue<MinPriorityNode<'a, &'a T, W>>,
+    came_from: HashMap<&'a T, &'a T>,
+    cost_so_far: HashMap<&'a T, W>,
+    closed: HashSet<&'a T>,
+    // Events produced by the last expansion that haven't been handed out.
+    pending: DList<SearchEvent<'a, T, W>>,
+    finished: bool
+}
+
+impl<'a, 'h, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>> SearchSteps<'a, 'h, T, W, I> {
+
+    pub fn new(graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+               heuristic: |&T|: 'h -> W) -> SearchSteps<'a, 'h, T, W, I> {
+        let mut steps = SearchSteps {
+            graph: graph,
+            start: start,
+            goal: goal,
+            heuristic: heuristic,
+            frontier: PriorityQueue::new(),
+            came_from: HashMap::new(),
+            cost_so_far: HashMap::new(),
+            closed: HashSet::new(),
+            pending: DList::new(),
+            finished: false
+        };
+
+        match check_endpoints(graph, start, Some(goal)) {
+            Ok(()) => (),
+            Err(error) => {
+                steps.pending.push_back(SearchFailed(error));
+                steps.finished = true;
+                return steps;
+            }
+        }
+
+        let estimate = (steps.heuristic)(start);
+        steps.frontier.push(MinPriorityNode { node: start, cost: estimate });
+        steps.came_from.insert(start, start);
+        steps.cost_so_far.insert(start, Zero::zero());
+        steps
+    }
+}
+
+impl<'a, 'h, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    Iterator<SearchEvent<'a, T, W>> for SearchSteps<'a, 'h, T, W, I> {
+
+    fn next(&mut self) -> Option<SearchEvent<'a, T, W>> {
+        if !self.pending.is_empty() {
+            return self.pending.pop_front();
+        }
+
+        while !self.finished && !self.frontier.is_empty() {
+            let MinPriorityNode { node: current, cost: _ } = self.frontier.pop().unwrap();
+            if !self.closed.insert(current) {
+                continue;
+            }
+
+            let current_cost = *self.cost_so_far.get(&current);
+            if current == self.goal {
+                self.finished = true;
+                return Some(GoalFound(reconstruct_path(&self.came_from, self.start, current), current_cost));
+            }
+
+            // Look over every edge before relaxing any, so that a negative
+            // one doesn't leave half an expansion behind.
+            let edges: Vec<(W, &'a T)> = self.graph.neighbours(current).collect();
+            if edges.iter().any(|&(cost, _)| cost < Zero::zero()) {
+                self.finished = true;
+                return Some(SearchFailed(NegativeWeight));
+            }
+
+            for &(cost, next) in edges.iter() {
+                let new_cost = current_cost + cost;
+                if self.cost_so_far.find(&next).map_or(true, |&c| new_cost < c) {
+                    self.cost_so_far.insert(next, new_cost);
+                    self.came_from.insert(next, current);
+                    let estimate = new_cost + (self.heuristic)(next);
+                    self.frontier.push(MinPriorityNode { node: next, cost: estimate });
+                    self.pending.push_back(EdgeRelaxed(current, next, new_cost));
+                }
+            }
+
+            return Some(NodeExpanded(current, current_cost));
+        }
+
+        None
+    }
+}
+
+/// Find the earliest time a traveller setting off from `start` at `time`
+/// can reach `goal` in a `TimeExpandedGraph`, returning the states passed
+/// through on the way and the arrival time.
+pub fn earliest_arrival<'a, T: Eq + Hash + Clone>(graph: &'a graph::TimeExpandedGraph<T>, start: T,
+                                                  time: uint, goal: &T)
+    -> Option<(Vec<&'a (T, uint)>, uint)> {
+
+    let first = graph.state(start, time);
+    let mut found = None;
+
+    // Times only move forwards, so no weight is ever negative.
+    let (came_from, _) = dijkstra_tables(graph, first, |state| {
+        let &(ref node, _) = state;
+        if node == goal { found = Some(state); true } else { false }
+    }, &mut None, |_, _| true).unwrap();
+
+    found.map(|state| {
+        let &(_, arrival) = state;
+        (reconstruct_path(&came_from, first, state), arrival)
+    })
+}
+
+/// Run Dijkstra's algorithm from several sources at once, finding for every
+/// node that can be reached the source nearest to it and the cost of getting
+/// there.
+///
+/// Each source is given with an initial cost, which is added to every path
+/// from it; pass zero to treat all sources equally. This answers questions
+/// like "which exit is closest to each room?" with a single search, and
+/// divides the graph into regions much like a Voronoi diagram. Edge weights
+/// must not be negative.
+pub fn multi_source_dijkstra<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, sources: &[(&'a T, W)])
+    -> Result<HashMap<&'a T, (&'a T, W)>, SearchError> {
+
+    let mut frontier = PriorityQueue::new();
+    let mut nearest: HashMap<&'a T, (&'a T, W)> = HashMap::new();
+
+    for &(source, initial) in sources.iter() {
+        try!(check_endpoints(graph, source, None));
+        if nearest.find(&source).map_or(true, |&(_, c)| initial < c) {
+            nearest.insert(source, (source, initial));
+            frontier.push(MinPriorityNode { node: source, cost: initial });
+        }
+    }
+
+    while !frontier.is_empty() {
+        let MinPriorityNode { node: current, cost: current_cost } = frontier.pop().unwrap();
+        let (source, best) = *nearest.get(&current);
+
+        if current_cost > best {
+            continue;
+        }
+
+        for (cost, next) in graph.neighbours(current) {
+            if cost < Zero::zero() {
+                return Err(NegativeWeight);
+            }
+
+            let new_cost = current_cost + cost;
+            if nearest.find(&next).map_or(true, |&(_, c)| new_cost < c) {
+                nearest.insert(next, (source, new_cost));
+                frontier.push(MinPriorityNode { node: next, cost: new_cost });
+            }
+        }
+    }
+
+    Ok(nearest)
+}
+
+/// Find the widest path from `start` to `goal`: the one whose narrowest
+/// edge is as wide as possible, where each edge's weight is its width (a
+/// bandwidth or a load limit, say).
+///
+/// Returns the path and that bottleneck width, or `None` if the goal cannot
+/// be reached. This is Dijkstra's algorithm with the largest width expanded
+/// first and a path's width taken as the minimum of its edges' rather than
+/// the sum. A path from the start to itself has no edges to limit it, so its
+/// width is `W`'s maximum value. If `visit` is specified, it is called with
+/// each node as it is expanded, along with the width of the path to it.
+pub fn widest_path_search<'a, T: Eq + Hash, W: Cost + Bounded, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     mut visit: Option<|&'a T, W|>) -> Result<Option<(Vec<&'a T>, W)>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+
+    let mut frontier = PriorityQueue::new();
+    let mut came_from = HashMap::new();
+    let mut width_so_far = HashMap::new();
+    let widest: W = Bounded::max_value();
+
+    width_so_far.insert(start, widest);
+    frontier.push(MaxPriorityNode { node: start, cost: widest });
+
+    while !frontier.is_empty() {
+        let MaxPriorityNode { node: current, cost: current_width } = frontier.pop().unwrap();
+        if current_width < *width_so_far.get(&current) {
+            continue;
+        }
+
+        match visit {
+            Some(ref mut f) => (*f)(current, current_width),
+            None => ()
+        }
+
+        if current == goal {
+            return Ok(Some((reconstruct_path(&came_from, start, goal), current_width)));
+        }
+
+        for (width, next) in graph.neighbours(current) {
+            let new_width = min(current_width, width);
+            if width_so_far.find(&next).map_or(true, |&w| new_width > w) {
+                width_so_far.insert(next, new_width);
+                came_from.insert(next, current);
+                frontier.push(MaxPriorityNode { node: next, cost: new_width });
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Find the minimax path from `start` to `goal`: the one whose most
+/// expensive single edge is as cheap as possible, for routing that would
+/// rather take a long way round than one steep climb.
+///
+/// This is the dual of `widest_path_search`, and is called the same way as
+/// `dijkstra_search`, but a path's cost is the largest of its edges' rather
+/// than their sum. Since that can never go down along a path, negative
+/// weights are fine. A path from the start to itself costs `W`'s minimum
+/// value.
+pub fn minimax_path_search<'a, T: Eq + Hash, W: Cost + Bounded, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     mut visit: Option<|&'a T, W|>) -> Result<Option<(Vec<&'a T>, W)>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+
+    let mut frontier = PriorityQueue::new();
+    let mut came_from = HashMap::new();
+    let mut cost_so_far = HashMap::new();
+    let lowest: W = Bounded::min_value();
+
+    cost_so_far.insert(start, lowest);
+    frontier.push(MinPriorityNode { node: start, cost: lowest });
+
+    while !frontier.is_empty() {
+        let MinPriorityNode { node: current, cost: current_cost } = frontier.pop().unwrap();
+        if current_cost > *cost_so_far.get(&current) {
+            continue;
+        }
+
+        match visit {
+            Some(ref mut f) => (*f)(current, current_cost),
+            None => ()
+        }
+
+        if current == goal {
+            return Ok(Some((reconstruct_path(&came_from, start, goal), current_cost)));
+        }
+
+        for (cost, next) in graph.neighbours(current) {
+            let new_cost = max(current_cost, cost);
+            if cost_so_far.find(&next).map_or(true, |&c| new_cost < c) {
+                cost_so_far.insert(next, new_cost);
+                came_from.insert(next, current);
+                frontier.push(MinPriorityNode { node: next, cost: new_cost });
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// The weight of the cheapest edge from `from` to `to`, if there is one.
+fn edge_cost<'a, T: Eq, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, from: &'a T, to: &'a T) -> Option<W> {
+
+    let mut best = None;
+    for (cost, next) in graph.neighbours(from) {
+        if next == to {
+            best = infinite_min(best, Some(cost));
+        }
+    }
+    best
+}
+
+/// Find the `k` cheapest paths from `start` to `goal` that don't visit any
+/// node more than once, using Yen's algorithm.
+///
+/// The paths are returned in order of increasing cost, and there may be
+/// fewer than `k` of them if the graph doesn't have that many. Each path
+/// after the first is found by running `dijkstra_search` from every node of
+/// the previous path with some edges removed, so edge weights must not be
+/// negative.
+pub fn k_shortest_paths<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T, k: uint)
+    -> Result<Vec<(Vec<&'a T>, W)>, SearchError> {
+
+    let mut found: Vec<(Vec<&'a T>, W)> = Vec::new();
+    let mut candidates: Vec<(Vec<&'a T>, W)> = Vec::new();
+
+    if k == 0 {
+        return Ok(found);
+    }
+
+    match try!(dijkstra_search(graph, start, goal, None)) {
+        Some(best) => found.push(best),
+        None => return Ok(found)
+    }
+
+    while found.len() < k {
+        let previous = found.last().unwrap().ref0().clone();
+        let mut root_cost: W = Zero::zero();
+
+        // Branch off the previous path at each of its nodes in turn.
+        for i in range(0, previous.len() - 1) {
+            let spur = *previous.get(i);
+            let root = previous.slice_to(i + 1);
+
+            // Forbid the next edge of every path found so far that shares
+            // this root, so the new path must differ from all of them, and
+            // forbid revisiting the root itself so that it stays loopless.
+            let mut removed_edges = HashSet::new();
+            for &(ref path, _) in found.iter() {
+                if path.len() > i + 1 && path.slice_to(i + 1) == root {
+                    removed_edges.insert((*path.get(i), *path.get(i + 1)));
+                }
+            }
+
+            let removed_nodes: HashSet<&'a T> = root.slice_to(i).iter().map(|&n| n).collect();
+
+            let (came_from, cost_so_far) = try!(dijkstra_tables(graph, spur, |n| n == goal, &mut None,
+                |from, to| !removed_nodes.contains(&to) && !removed_edges.contains(&(from, to))));
+
+            match cost_so_far.find(&goal) {
+                Some(&spur_cost) => {
+                    let mut path = root.slice_to(i).to_vec();
+                    path.push_all(reconstruct_path(&came_from, spur, goal).as_slice());
+
+                    if !candidates.iter().any(|&(ref p, _)| *p == path) {
+                        candidates.push((path, root_cost + spur_cost));
+                    }
+                },
+                None => ()
+            }
+
+            root_cost = root_cost + edge_cost(graph, spur, *previous.get(i + 1)).unwrap();
+        }
+
+        if candidates.is_empty() {
+            break;
+        }
+
+        // Promote the cheapest candidate.
+        let mut best = 0;
+        for (j, &(_, cost)) in candidates.iter().enumerate() {
+            if cost < *candidates.get(best).ref1() {
+                best = j;
+            }
+        }
+
+        found.push(candidates.swap_remove(best).unwrap());
+    }
+
+    Ok(found)
+}
+
+/// A step away from the shortest path tree in `KShortestWalks`: an edge
+/// `from -> to` along with how much more it costs to take it than to keep
+/// following the tree.
+type Sidetrack<'a, T, W> = (W, &'a T, &'a T);
+
+/// A lazy enumeration of the walks from `start` to `goal` in order of
+/// increasing cost, using Eppstein's sidetrack formulation.
+///
+/// Unlike `k_shortest_paths`, the walks may visit nodes (including the goal)
+/// more than once. Construction runs a single `dijkstra_search` backwards
+/// from the goal over `reverse`, which must hold the same edges as `graph`
+/// with their directions flipped, and fails as that search does or if either
+/// endpoint isn't in `graph`. Every walk is then that shortest path
+/// tree plus a sequence of detours off it, and each call to `next()` only
+/// needs to pop the cheapest candidate sequence and push at most two more,
+/// so callers can keep pulling walks for as long as they like:
+///
+/// ```ignore
+/// let walks = try!(KShortestWalks::new(&g, &r, start, goal));
+/// for (walk, cost) in walks.take(1000) {
+///     if acceptable(walk.as_slice()) { break; }
+/// }
+/// ```
+///
+/// This is the lazy variant: instead of Eppstein's persistent heaps, the
+/// sorted detours available along each tree path are built the first time
+/// they are needed and kept around afterwards.
+pub struct KShortestWalks<'a, T, W, I> {
+    graph: &'a graph::WeightedGraph<'a, T, W, I>,
+    start: &'a T,
+    goal: &'a T,
+    // The cost of the cheapest path from each node to the goal, and the next
+    // node along it.
+    distance: HashMap<&'a T, W>,
+    next_hop: HashMap<&'a T, &'a T>,
+    // For each node, every detour that leaves the tree path from that node to
+    // the goal, cheapest first.
+    sidetracks: HashMap<&'a T, Vec<Sidetrack<'a, T, W>>>,
+    // The walks handed out so far, as their last detour and the index of the
+    // walk it was added to. The first is the shortest path, with no detours.
+    walks: Vec<(Option<uint>, Option<(&'a T, &'a T)>)>,
+    // Candidates are the walk they extend, the node whose detours they pick
+    // from, and which of those detours they take.
+    queue: PriorityQueue<MinPriorityNode<'a, (uint, &'a T, uint), W>>,
+    started: bool
+}
+
+impl<'a, T: Eq + Hash, W: Cost + Sub<W, W>, I: Iterator<(W, &'a T)>> KShortestWalks<'a, T, W, I> {
+
+    pub fn new<J: Iterator<(W, &'a T)>>(graph: &'a graph::WeightedGraph<'a, T, W, I>,
+                                        reverse: &'a graph::WeightedGraph<'a, T, W, J>,
+                                        start: &'a T, goal: &'a T)
+        -> Result<KShortestWalks<'a, T, W, I>, SearchError> {
+
+        try!(check_endpoints(graph, start, Some(goal)));
+        let (next_hop, distance) = try!(dijkstra_tables(reverse, goal, |_| false, &mut None, |_, _| true));
+
+        Ok(KShortestWalks {
+            graph: graph,
+            start: start,
+            goal: goal,
+            distance: distance,
+            next_hop: next_hop,
+            sidetracks: HashMap::new(),
+            walks: Vec::new(),
+            queue: PriorityQueue::new(),
+            started: false
+        })
+    }
+
+    /// The `index`th cheapest detour along the tree path from `node`, making
+    /// sure the detours for that path have been worked out first.
+    fn sidetrack(&mut self, node: &'a T, index: uint) -> Option<Sidetrack<'a, T, W>> {
+        // Find the part of the tree path that hasn't been seen yet.
+        let mut missing = Vec::new();
+        let mut current = node;
+        while !self.sidetracks.contains_key(&current) {
+            missing.push(current);
+            if current == self.goal {
+                break;
+            }
+            current = *self.next_hop.get(&current);
+        }
+
+        // Then fill it in from the goal end, since each node's detours are
+        // its own plus those of the next node along.
+        for &current in missing.iter().rev() {
+            let mut own = Vec::new();
+            let mut skipped_tree_edge = false;
+            let current_distance = *self.distance.get(&current);
+
+            for (cost, next) in self.graph.neighbours(current) {
+                let next_distance = match self.distance.find(&next) {
+                    Some(&d) => d,
+                    None => continue
+                };
+
+                // The one edge that is part of the tree isn't a detour.
+                if current != self.goal && !skipped_tree_edge && next == *self.next_hop.get(&current)
+                    && cost + next_distance == current_distance {
+                    skipped_tree_edge = true;
+                    continue;
+                }
+
+                own.push((cost + next_distance - current_distance, current, next));
+            }
+
+            if current != self.goal {
+                own.push_all(self.sidetracks.get(self.next_hop.get(&current)).as_slice());
+            }
+
+            own.sort_by(|&(a, _, _), &(b, _, _)| a.cmp(&b));
+            self.sidetracks.insert(current, own);
+        }
+
+        self.sidetracks.get(&node).as_slice().get(index).map(|&s| s)
+    }
+
+    /// Spell out a walk by following the tree between its detours.
+    fn walk(&self, index: uint) -> Vec<&'a T> {
+        let mut detours = Vec::new();
+        let mut current = Some(index);
+        while current.is_some() {
+            let &(parent, detour) = self.walks.get(current.unwrap());
+            match detour {
+                Some(edge) => detours.push(edge),
+                None => ()
+            }
+            current = parent;
+        }
+
+        let mut walk = vec!(self.start);
+        let mut current = self.start;
+        for &(from, to) in detours.iter().rev() {
+            while current != from {
+                current = *self.next_hop.get(&current);
+                walk.push(current);
+            }
+            walk.push(to);
+            current = to;
+        }
+
+        while current != self.goal {
+            current = *self.next_hop.get(&current);
+            walk.push(current);
+        }
+
+        walk
+    }
+
+    /// Queue up the walk that takes the `index`th detour from `node` in
+    /// addition to those of walk `parent`, if there is such a detour.
+    fn push(&mut self, parent: uint, node: &'a T, index: uint, base: W) {
+        match self.sidetrack(node, index) {
+            Some((extra, _, _)) => {
+                self.queue.push(MinPriorityNode { node: (parent, node, index), cost: base + extra });
+            },
+            None => ()
+        }
+    }
+}
+
+impl<'a, T: Eq + Hash, W: Cost + Sub<W, W>, I: Iterator<(W, &'a T)>>
+    Iterator<(Vec<&'a T>, W)> for KShortestWalks<'a, T, W, I> {
+
+    fn next(&mut self) -> Option<(Vec<&'a T>, W)> {
+        if !self.started {
+            self.started = true;
+
+            let cost = match self.distance.find(&self.start) {
+                Some(&cost) => cost,
+                None => return None
+            };
+
+            self.walks.push((None, None));
+            let start = self.start;
+            self.push(0, start, 0, cost);
+            return Some((self.walk(0), cost));
+        }
+
+        let MinPriorityNode { node: (parent, node, index), cost } = match self.queue.pop() {
+            Some(candidate) => candidate,
+            None => return None
+        };
+
+        let (extra, from, to) = self.sidetrack(node, index).unwrap();
+        self.walks.push((Some(parent), Some((from, to))));
+        let current = self.walks.len() - 1;
+
+        // The next walk either swaps this detour for the next cheapest one
+        // from the same path, or keeps it and adds another further along.
+        self.push(parent, node, index + 1, cost - extra);
+        self.push(current, to, 0, cost);
+
+        Some((self.walk(current), cost))
+    }
+}
+
+/// What two paths must avoid sharing to count as disjoint.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum Disjointness {
+    /// The paths may pass through the same nodes, but not along the same
+    /// edges.
+    EdgeDisjoint,
+    /// The paths may not share any nodes besides their endpoints.
+    VertexDisjoint
+}
+
+/// A pair of disjoint paths found by `suurballe_search`.
+#[deriving(Clone, Show)]
+pub struct DisjointPaths<'a, T, W> {
+    pub first: Vec<&'a T>,
+    pub second: Vec<&'a T>,
+    /// The combined cost of both paths.
+    pub cost: W
+}
+
+/// Find the shortest path from `source` to `sink` in the residual network
+/// left by the flow so far, using reduced costs so that Dijkstra's algorithm
+/// still applies, and push one more unit of flow along it. Returns false if
+/// the sink can no longer be reached.
+fn augment_disjoint<W: Cost + Sub<W, W>>(arcs: &Vec<(uint, uint, W, Option<uint>)>,
+                                         outgoing: &Vec<Vec<uint>>, incoming: &Vec<Vec<uint>>,
+                                         flow: &mut Vec<bool>, potential: &mut Vec<W>,
+                                         source: uint, sink: uint) -> bool {
+    let mut distance: Vec<Option<W>> = Vec::from_elem(outgoing.len(), None);
+    let mut via: Vec<Option<uint>> = Vec::from_elem(outgoing.len(), None);
+    let mut frontier = PriorityQueue::new();
+
+    *distance.get_mut(source) = Some(Zero::zero());
+    frontier.push(MinPriorityNode { node: source, cost: Zero::zero() });
+
+    while !frontier.is_empty() {
+        let MinPriorityNode { node: current, cost: current_cost } = frontier.pop().unwrap();
+
+        if infinite_lt(*distance.get(current), Some(current_cost)) {
+            continue;
+        }
+
+        // Arcs without flow can be followed forwards, and arcs with flow can
+        // be undone by following them backwards. The potentials keep both
+        // kinds from looking negative.
+        let mut steps = Vec::new();
+        for &arc in outgoing.get(current).iter() {
+            let &(_, head, weight, _) = arcs.get(arc);
+            if !*flow.get(arc) {
+                steps.push((arc, head, weight + *potential.get(current) - *potential.get(head)));
+            }
+        }
+        for &arc in incoming.get(current).iter() {
+            let &(tail, _, weight, _) = arcs.get(arc);
+            if *flow.get(arc) {
+                steps.push((arc, tail, *potential.get(current) - (weight + *potential.get(tail))));
+            }
+        }
+
+        for &(arc, next, cost) in steps.iter() {
+            let new_cost = current_cost + cost;
+            if infinite_lt(Some(new_cost), *distance.get(next)) {
+                *distance.get_mut(next) = Some(new_cost);
+                *via.get_mut(next) = Some(arc);
+                frontier.push(MinPriorityNode { node: next, cost: new_cost });
+            }
+        }
+    }
+
+    if distance.get(sink).is_none() {
+        return false;
+    }
+
+    for (node, d) in distance.iter().enumerate() {
+        match *d {
+            Some(d) => *potential.get_mut(node) = *potential.get(node) + d,
+            None => ()
+        }
+    }
+
+    // Walk back from the sink, flipping the flow on every arc along the way.
+    let mut current = sink;
+    while current != source {
+        let arc = via.get(current).unwrap();
+        let &(tail, head, _, _) = arcs.get(arc);
+        let used = *flow.get(arc);
+        *flow.get_mut(arc) = !used;
+        current = if used { head } else { tail };
+    }
+
+    true
+}
+
+/// Find two disjoint paths from `start` to `goal` with the smallest combined
+/// cost, using Suurballe's algorithm.
+///
+/// Simply taking the shortest path and then the shortest path that avoids it
+/// can fail to find a pair even when one exists, so the second search is
+/// allowed to undo parts of the first. When `disjointness` is
+/// `VertexDisjoint`, each node is split into an entry and an exit joined by
+/// a single edge, so that only one of the paths can pass through it.
+///
+/// Edge weights must not be negative. If there is no such pair of paths, the
+/// search fails with a `NoDisjointPaths` error, and with `StartNotFound` or
+/// `GoalNotFound` if either endpoint isn't in the graph.
+pub fn suurballe_search<'a, T: Eq + Hash, W: Cost + Sub<W, W>, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     disjointness: Disjointness) -> Result<DisjointPaths<'a, T, W>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+    if start == goal {
+        return Ok(DisjointPaths { first: vec!(start), second: vec!(start), cost: Zero::zero() });
+    }
+
+    // Number the reachable nodes, and collect the edges between them.
+    let mut nodes = vec!(start);
+    let mut index = HashMap::new();
+    let mut edges = Vec::new();
+    index.insert(start, 0u);
+
+    let mut i = 0;
+    while i < nodes.len() {
+        let current = *nodes.get(i);
+        for (cost, next) in graph.neighbours(current) {
+            if cost < Zero::zero() {
+                return Err(NegativeWeight);
+            }
+
+            if !index.contains_key(&next) {
+                index.insert(next, nodes.len());
+                nodes.push(next);
+            }
+
+            let j = *index.get(&next);
+            if j != i {
+                edges.push((i, j, cost));
+            }
+        }
+        i += 1;
+    }
+
+    let target = match index.find(&goal) {
+        Some(&target) => target,
+        None => return Err(NoDisjointPaths)
+    };
+
+    // Each arc is its tail, head, weight, and the original node it leads to
+    // (if it isn't one of the internal edges of a split node).
+    let mut arcs = Vec::new();
+    let (size, source, sink) = match disjointness {
+        EdgeDisjoint => {
+            for &(from, to, cost) in edges.iter() {
+                arcs.push((from, to, cost, Some(to)));
+            }
+            (nodes.len(), 0, target)
+        },
+        VertexDisjoint => {
+            for n in range(0, nodes.len()) {
+                arcs.push((2 * n, 2 * n + 1, Zero::zero(), None));
+            }
+            for &(from, to, cost) in edges.iter() {
+                arcs.push((2 * from + 1, 2 * to, cost, Some(to)));
+            }
+            (2 * nodes.len(), 1, 2 * target)
+        }
+    };
+
+    let mut outgoing = Vec::from_fn(size, |_| Vec::new());
+    let mut incoming = Vec::from_fn(size, |_| Vec::new());
+    for (arc, &(tail, head, _, _)) in arcs.iter().enumerate() {
+        outgoing.get_mut(tail).push(arc);
+        incoming.get_mut(head).push(arc);
+    }
+
+    let mut flow = Vec::from_elem(arcs.len(), false);
+    let mut potential = Vec::from_elem(size, Zero::zero());
+
+    for _ in range(0u, 2) {
+        if !augment_disjoint(&arcs, &outgoing, &incoming, &mut flow, &mut potential, source, sink) {
+            return Err(NoDisjointPaths);
+        }
+    }
+
+    // Split the flow back up into two paths.
+    let mut paths = Vec::new();
+    let mut total = Zero::zero();
+    for _ in range(0u, 2) {
+        let mut path = vec!(start);
+        let mut current = source;
+
+        while current != sink {
+            let arc = *outgoing.get(current).iter().find(|&&arc| *flow.get(arc)).unwrap();
+            *flow.get_mut(arc) = false;
+
+            let &(_, head, cost, node) = arcs.get(arc);
+            match node {
+                Some(node) => path.push(*nodes.get(node)),
+                None => ()
+            }
+            total = total + cost;
+            current = head;
+        }
+
+        paths.push(path);
+    }
+
+    let second = paths.pop().unwrap();
+    let first = paths.pop().unwrap();
+    Ok(DisjointPaths { first: first, second: second, cost: total })
+}
+
+/// Find the cheapest path from `start` to `goal` in a graph with small
+/// integer weights, using Dial's algorithm.
+///
+/// This is `dijkstra_search` with the priority queue replaced by a circular
+/// array of `max_weight + 1` buckets, one for each possible distance ahead of
+/// the current one, which makes each step constant time. No edge may weigh
+/// more than `max_weight`; if one does, the search is abandoned with a
+/// `WeightTooLarge` error.
+pub fn dial_search<'a, T: Eq + Hash, I: Iterator<(uint, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, uint, I>, start: &'a T, goal: &'a T,
+     max_weight: uint) -> Result<Option<(Vec<&'a T>, uint)>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+
+    let mut buckets: Vec<DList<&'a T>> = Vec::from_fn(max_weight + 1, |_| DList::new());
+    let mut came_from = HashMap::new();
+    let mut cost_so_far = HashMap::new();
+
+    buckets.get_mut(0).push_back(start);
+    came_from.insert(start, start);
+    cost_so_far.insert(start, 0u);
+
+    let mut queued = 1u;
+    let mut current_cost = 0u;
+
+    while queued > 0 {
+        // Every queued node is no more than `max_weight` past the current
+        // cost, so each bucket only ever holds nodes at a single distance.
+        while buckets.get(current_cost % (max_weight + 1)).is_empty() {
+            current_cost += 1;
+        }
+
+        let current = buckets.get_mut(current_cost % (max_weight + 1)).pop_front().unwrap();
+        queued -= 1;
+
+        // Skip entries that were superseded by a cheaper route.
+        if *cost_so_far.get(&current) < current_cost {
+            continue;
+        }
+
+        if current == goal {
+            return Ok(Some((reconstruct_path(&came_from, start, goal), current_cost)));
+        }
+
+        for (cost, next) in graph.neighbours(current) {
+            if cost > max_weight {
+                return Err(WeightTooLarge);
+            }
+
+            let new_cost = current_cost + cost;
+
+            if cost_so_far.find(&next).map_or(false, |&c| new_cost >= c) {
+                continue;
+            }
+
+            cost_so_far.insert(next, new_cost);
+            came_from.insert(next, current);
+            buckets.get_mut(new_cost % (max_weight + 1)).push_back(next);
+            queued += 1;
+        }
+    }
+
+    Ok(None)
+}
+
+/// Find the cheapest path from `start` to `goal` in a graph whose edges all
+/// weigh either zero or one, using 0-1 breadth-first search.
+///
+/// Nodes reached by free edges go to the front of a double-ended queue and
+/// the rest go to the back, which keeps the queue in order of cost without
+/// needing a heap, so the search runs in linear time. If any edge weighs more
+/// than one, the search is abandoned with a `WeightTooLarge` error.
+pub fn zero_one_breadth_first_search<'a, T: Eq + Hash, I: Iterator<(uint, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, uint, I>, start: &'a T, goal: &'a T)
+    -> Result<Option<(Vec<&'a T>, uint)>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+
+    let mut frontier = DList::new();
+    let mut came_from = HashMap::new();
+    let mut cost_so_far = HashMap::new();
+
+    frontier.push_back((start, 0u));
+    came_from.insert(start, start);
+    cost_so_far.insert(start, 0u);
+
+    loop {
+        let (current, current_cost) = match frontier.pop_front() {
+            Some(entry) => entry,
+            None => break
+        };
+
+        // Skip entries that were superseded by a cheaper route.
+        if *cost_so_far.get(&current) < current_cost {
+            continue;
+        }
+
+        if current == goal {
+            return Ok(Some((reconstruct_path(&came_from, start, goal), current_cost)));
+        }
+
+        for (cost, next) in graph.neighbours(current) {
+            if cost > 1 {
+                return Err(WeightTooLarge);
+            }
+
+            let new_cost = current_cost + cost;
+
+            if cost_so_far.find(&next).map_or(false, |&c| new_cost >= c) {
+                continue;
+            }
+
+            cost_so_far.insert(next, new_cost);
+            came_from.insert(next, current);
+
+            if cost == 0 {
+                frontier.push_front((next, new_cost));
+            } else {
+                frontier.push_back((next, new_cost));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Find the cheapest path from `start` to `goal`, guided by a heuristic.
+///
+/// The `heuristic` estimates the remaining cost from a node to the goal. So
+/// long as it never overestimates that cost, the path found is optimal; a
+/// heuristic that always returns zero makes this equivalent to
+/// `dijkstra_search`. The `visit` callback behaves as it does there.
+///
+/// Fails with `StartNotFound` or `GoalNotFound` if either endpoint isn't in
+/// the graph, rather than reporting that there is no path between them.
+pub fn a_star_search<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     heuristic: |&T| -> W, mut visit: Option<|&'a T, W|>)
+    -> Result<Option<(Vec<&'a T>, W)>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+    Ok(counted_a_star(graph, start, |n| n == goal, heuristic, &mut visit, &mut SearchCounters::new())
+        .map(|(_, path, cost)| (path, cost)))
+}
+
+/// As `a_star_search`, but stop at the first node reached for which
+/// `is_goal` returns true, returning it along with the path to it and the
+/// path's cost.
+///
+/// The heuristic must then estimate the cost to the nearest such node, and
+/// as before the path found is only optimal if it never overestimates.
+pub fn a_star_search_where<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, is_goal: |&'a T| -> bool,
+     heuristic: |&T| -> W, mut visit: Option<|&'a T, W|>)
+    -> Result<Option<(&'a T, Vec<&'a T>, W)>, SearchError> {
+
+    try!(check_endpoints(graph, start, None));
+    Ok(counted_a_star(graph, start, is_goal, heuristic, &mut visit, &mut SearchCounters::new()))
+}
+
+/// As `a_star_search`, but also report how much work the search did and how
+/// long it took, even if no path was found.
+pub fn a_star_search_with_stats<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     heuristic: |&T| -> W) -> Result<SearchResult<'a, T, W>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+    let started = time::precise_time_ns();
+    let mut counters = SearchCounters::new();
+    let path = counted_a_star(graph, start, |n| n == goal, heuristic, &mut None, &mut counters)
+        .map(|(_, path, cost)| (path, cost));
+    Ok(SearchResult { path: path, stats: counters.stats(started) })
+}
+
+/// The machinery behind `a_star_search` and its variants, which stops at the
+/// first node for which `is_goal` returns true and keeps count of the work
+/// done in `counters`.
+fn counted_a_star<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, is_goal: |&'a T| -> bool,
+     heuristic: |&T| -> W, visit: &mut Option<|&'a T, W|>, counters: &mut SearchCounters)
+    -> Option<(&'a T, Vec<&'a T>, W)> {
+
+    match limited_a_star(graph, start, is_goal, heuristic, visit, counters, &SearchLimits::none(), false) {
+        Ok(Reached(path, cost)) => Some((*path.last().unwrap(), path, cost)),
+        _ => None
+    }
+}
+
+/// As `counted_a_star`, but giving up as soon as one of `limits` is broken,
+/// and failing on negative weights if `reject_negative` is set.
+fn limited_a_star<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, is_goal: |&'a T| -> bool,
+     heuristic: |&T| -> W, visit: &mut Option<|&'a T, W|>, counters: &mut SearchCounters,
+     limits: &SearchLimits<W>, reject_negative: bool)
+    -> Result<LimitedSearchResult<'a, T, W>, SearchError> {
+
+    let mut frontier = PriorityQueue::new();
+    let mut came_from = HashMap::new();
+    let mut cost_so_far = HashMap::new();
+
+    let start_estimate = heuristic(start);
+    frontier.push(MinPriorityNode { node: (start, Zero::zero()), cost: start_estimate });
+    came_from.insert(start, start);
+    cost_so_far.insert(start, Zero::zero());
+
+    // The expanded node with the lowest heuristic, for partial results.
+    let (mut closest, mut closest_estimate) = (start, start_estimate);
+    let started = time::precise_time_ns();
+
+    while !frontier.is_empty() {
+        let MinPriorityNode { node: (current, current_cost), cost: estimate } = frontier.pop().unwrap();
+
+        // Skip entries that were superseded by a cheaper route, so that each
+        // node is only visited, counted, and expanded once.
+        if current_cost > *cost_so_far.get(&current) {
+            continue;
+        }
+
+        // The frontier is ordered by estimate, so if this one is over the
+        // limit then so is everything left.
+        if limits.cost.map_or(false, |limit| estimate > limit) {
+            return Ok(OverCostLimit);
+        }
+
+        match *visit {
+            Some(ref mut f) => (*f)(current, *cost_so_far.get(&current)),
+            None => ()
+        }
+
+        if is_goal(current) {
+            let path = reconstruct_path(&came_from, start, current);
+            return Ok(Reached(path, *cost_so_far.get(&current)));
+        }
+
+        if limits.cancellation.as_ref().map_or(false, |token| token.is_cancelled()) {
+            return Ok(Cancelled);
+        }
+
+        if limits.expansions.map_or(false, |limit| counters.expanded >= limit) {
+            let path = reconstruct_path(&came_from, start, closest);
+            return Ok(OverExpansionLimit(path, *cost_so_far.get(&closest)));
+        }
+
+        if counters.expanded % DEADLINE_CHECK_INTERVAL == 0 && limits.time.map_or(false, |limit| {
+            Duration::nanoseconds((time::precise_time_ns() - started) as i64) > limit
+        }) {
+            let path = reconstruct_path(&came_from, start, closest);
+            return Ok(TimedOut(path, *cost_so_far.get(&closest)));
+        }
+        counters.expanded += 1;
+
+        let remaining = heuristic(current);
+        if remaining <= closest_estimate {
+            closest = current;
+            closest_estimate = remaining;
+        }
+
+        for (cost, next) in graph.neighbours(current) {
+            if reject_negative && cost < Zero::zero() {
+                return Err(NegativeWeight);
+            }
+
+            let new_cost = *cost_so_far.get(&current) + cost;
+
+            if cost_so_far.contains_key(&next) && new_cost >= *cost_so_far.get(&next) {
+                continue;
+            } else {
+                cost_so_far.insert_or_update_with(next, new_cost, |_, v| *v = new_cost);
+                came_from.insert_or_update_with(next, current, |_, v| *v = current);
+
+                // Unlike Dijkstra, the frontier is ordered by the estimated
+                // total cost of a path through `next`.
+                frontier.push(MinPriorityNode { node: (next, new_cost), cost: new_cost + heuristic(next) });
+                counters.generated(frontier.len());
+            }
+        }
+    }
+
+    let path = reconstruct_path(&came_from, start, closest);
+    Ok(NoRoute(path, *cost_so_far.get(&closest)))
+}
+
+/// As `a_star_search`, but give up with `OverCostLimit` once it is clear
+/// that the goal can't be reached for `limit` or less, rather than
+/// exploring everything reachable from the start.
+///
+/// The heuristic must not overestimate, or the search may give up on a
+/// path that is in fact within the limit.
+pub fn a_star_search_within<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     heuristic: |&T| -> W, limit: W) -> Result<LimitedSearchResult<'a, T, W>, SearchError> {
+
+    a_star_search_limited(graph, start, goal, heuristic, &SearchLimits::none().with_cost(limit))
+}
+
+/// As `a_star_search`, but if the goal can't be reached, return the path to
+/// the reachable node that the heuristic puts closest to it instead, along
+/// with whether the goal itself was reached. Every node reachable from the
+/// start is expanded before giving up, so bound this with
+/// `a_star_search_limited` on large graphs.
+pub fn a_star_search_closest<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     heuristic: |&T| -> W) -> Result<(Vec<&'a T>, W, bool), SearchError> {
+
+    match try!(a_star_search_limited(graph, start, goal, heuristic, &SearchLimits::none())) {
+        Reached(path, cost) => Ok((path, cost, true)),
+        NoRoute(path, cost) => Ok((path, cost, false)),
+        // Nothing else can happen without limits.
+        _ => unreachable!()
+    }
+}
+
+/// As `a_star_search`, but give up as soon as any of `limits` is reached, so
+/// that searches run once a frame take a bounded amount of time.
+pub fn a_star_search_limited<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     heuristic: |&T| -> W, limits: &SearchLimits<W>) -> Result<LimitedSearchResult<'a, T, W>, SearchError> {
+
+    // Negative weights aren't checked for, so only missing endpoints fail.
+    try!(check_endpoints(graph, start, Some(goal)));
+    limited_a_star(graph, start, |n| n == goal, heuristic, &mut None, &mut SearchCounters::new(),
+                   limits, false)
+}
+
+/// As `a_star_search_limited`, but also report how much work the search did
+/// and how long it took, however it ended.
+pub fn a_star_search_limited_with_stats<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     heuristic: |&T| -> W, limits: &SearchLimits<W>)
+    -> Result<(LimitedSearchResult<'a, T, W>, SearchStats), SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+    let started = time::precise_time_ns();
+    let mut counters = SearchCounters::new();
+    let result = try!(limited_a_star(graph, start, |n| n == goal, heuristic, &mut None, &mut counters,
+                                     limits, false));
+    Ok((result, counters.stats(started)))
+}
+
+/// Precomputed distances to and from a handful of landmark nodes, for use as
+/// an A* heuristic (the ALT technique).
+///
+/// By the triangle inequality, the cost of getting from one node to another
+/// is at least the difference between their distances to any landmark (and
+/// likewise for distances from it), so taking the largest such difference
+/// gives a consistent estimate that needs no knowledge of the graph's
+/// geometry. It works best with landmarks on the edges of the graph, behind
+/// the nodes being searched between:
+///
+/// ```ignore
+/// let landmarks = try!(Landmarks::new(&g, &r, nodes.as_slice(), 16));
+/// a_star_search(&g, start, goal, |n| landmarks.estimate(n, goal), None);
+/// ```
+pub struct Landmarks<'a, T, W> {
+    landmarks: Vec<&'a T>,
+    // The cost of the cheapest path from each landmark to every node, and
+    // from every node to each landmark.
+    from_landmark: Vec<HashMap<T, W>>,
+    to_landmark: Vec<HashMap<T, W>>
+}
+
+impl<'a, T: Eq + Hash + Clone, W: Cost + Sub<W, W>> Landmarks<'a, T, W> {
+
+    /// Choose `count` landmarks from among `nodes` and work out their
+    /// distances. The `reverse` graph is as for `bidirectional_dijkstra`.
+    ///
+    /// Landmarks are picked greedily, each being the node furthest from all
+    /// of the ones picked before it, starting from the first of `nodes`.
+    pub fn new<I: Iterator<(W, &'a T)>, J: Iterator<(W, &'a T)>>
+        (graph: &'a graph::WeightedGraph<'a, T, W, I>,
+         reverse: &'a graph::WeightedGraph<'a, T, W, J>,
+         nodes: &[&'a T], count: uint) -> Result<Landmarks<'a, T, W>, SearchError> {
+
+        let mut landmarks = Landmarks {
+            landmarks: Vec::new(),
+            from_landmark: Vec::new(),
+            to_landmark: Vec::new()
+        };
+
+        // The distance from the nearest landmark to each node, where a node
+        // that isn't in the table can't be reached from any of them.
+        let mut nearest: HashMap<&'a T, W> = HashMap::new();
+
+        while landmarks.landmarks.len() < min(count, nodes.len()) {
+            let mut next: Option<(Option<W>, &'a T)> = None;
+            for &node in nodes.iter() {
+                let distance = nearest.find(&node).map(|&d| d);
+                if next.map_or(true, |(best, _)| infinite_lt(best, distance)) {
+                    next = Some((distance, node));
+                }
+            }
+
+            let (_, landmark) = next.unwrap();
+            if landmarks.landmarks.contains(&landmark) {
+                break;
+            }
+
+            let (_, from) = try!(dijkstra_tables(graph, landmark, |_| false, &mut None, |_, _| true));
+            let (_, to) = try!(dijkstra_tables(reverse, landmark, |_| false, &mut None, |_, _| true));
+
+            for (&node, &distance) in from.iter() {
+                nearest.insert_or_update_with(node, distance, |_, d| *d = min(*d, distance));
+            }
+
+            landmarks.landmarks.push(landmark);
+            landmarks.from_landmark.push(from.iter().map(|(&n, &d)| (n.clone(), d)).collect());
+            landmarks.to_landmark.push(to.iter().map(|(&n, &d)| (n.clone(), d)).collect());
+        }
+
+        Ok(landmarks)
+    }
+
+    /// The landmarks that were chosen.
+    pub fn landmarks<'b>(&'b self) -> &'b [&'a T] {
+        self.landmarks.as_slice()
+    }
+
+    /// A lower bound on the cost of the cheapest path from `node` to `goal`.
+    pub fn estimate(&self, node: &T, goal: &T) -> W {
+        let mut best: W = Zero::zero();
+
+        for (from, to) in self.from_landmark.iter().zip(self.to_landmark.iter()) {
+            match (to.find(node), to.find(goal)) {
+                (Some(&a), Some(&b)) if a > b => best = max(best, a - b),
+                _ => ()
+            }
+
+            match (from.find(goal), from.find(node)) {
+                (Some(&a), Some(&b)) if a > b => best = max(best, a - b),
+                _ => ()
+            }
+        }
+
+        best
+    }
+}
+
+impl<'a, T: Eq + Hash + Clone, W: Cost + Sub<W, W>> heuristic::Heuristic<T, W> for Landmarks<'a, T, W> {
+    fn estimate(&self, node: &T, goal: &T) -> W {
+        // The inherent method, which takes priority over this one.
+        self.estimate(node, goal)
+    }
+}
+
+/// A path found by a search that may trade optimality for speed.
+#[deriving(Clone, Show)]
+pub struct BoundedPath<'a, T, W> {
+    pub path: Vec<&'a T>,
+    pub cost: W,
+    /// A lower bound on the cost of the optimal path, so that dividing `cost`
+    /// by it gives the suboptimality actually achieved. This is often much
+    /// tighter than the bound the search guarantees.
+    pub lower_bound: W
+}
+
+/// Find a path from `start` to `goal` whose cost is no more than `epsilon`
+/// times that of the cheapest path, using weighted A*.
+///
+/// This is `a_star_search` with the heuristic inflated by `epsilon`, which
+/// must be at least one, making the search greedier and usually much faster.
+/// An `epsilon` of one finds the optimal path. Integer costs can only be
+/// inflated by whole numbers, so for finer control either scale the costs up
+/// or use `OrderedFloat`.
+pub fn weighted_a_star_search<'a, T: Eq + Hash, W: Cost + Mul<W, W>, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     heuristic: |&T| -> W, epsilon: W) -> Result<Option<BoundedPath<'a, T, W>>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+    Ok(counted_weighted_a_star(graph, start, goal, heuristic, epsilon, &mut SearchCounters::new()))
+}
+
+/// As `weighted_a_star_search`, but also report how much work the search did
+/// and how long it took, even if no path was found.
+pub fn weighted_a_star_search_with_stats<'a, T: Eq + Hash, W: Cost + Mul<W, W>, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     heuristic: |&T| -> W, epsilon: W) -> Result<SearchResult<'a, T, W>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+    let started = time::precise_time_ns();
+    let mut counters = SearchCounters::new();
+    let found = counted_weighted_a_star(graph, start, goal, heuristic, epsilon, &mut counters);
+    Ok(SearchResult { path: found.map(|b| (b.path, b.cost)), stats: counters.stats(started) })
+}
+
+/// The machinery behind `weighted_a_star_search`, keeping count of the work
+/// done in `counters`.
+fn counted_weighted_a_star<'a, T: Eq + Hash, W: Cost + Mul<W, W>, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     heuristic: |&T| -> W, epsilon: W, counters: &mut SearchCounters) -> Option<BoundedPath<'a, T, W>> {
+
+    let mut frontier = PriorityQueue::new();
+    let mut came_from = HashMap::new();
+    let mut cost_so_far = HashMap::new();
+    let mut closed = HashSet::new();
+
+    frontier.push(MinPriorityNode { node: start, cost: epsilon * heuristic(start) });
+    came_from.insert(start, start);
+    cost_so_far.insert(start, Zero::zero());
+
+    while !frontier.is_empty() {
+        let MinPriorityNode { node: current, cost: _ } = frontier.pop().unwrap();
+
+        if closed.contains(&current) {
+            continue;
+        }
+
+        if current == goal {
+            let cost = *cost_so_far.get(&goal);
+
+            // Every path we haven't finished exploring passes through a node
+            // that is still open, so the cheapest uninflated estimate among
+            // those bounds the optimal cost from below.
+            let mut lower_bound = cost;
+            for entry in frontier.iter() {
+                if closed.contains(&entry.node) {
+                    continue;
+                }
+
+                let estimate = *cost_so_far.get(&entry.node) + heuristic(entry.node);
+                if estimate < lower_bound {
+                    lower_bound = estimate;
+                }
+            }
+
+            return Some(BoundedPath {
+                path: reconstruct_path(&came_from, start, goal),
+                cost: cost,
+                lower_bound: lower_bound
+            });
+        }
+
+        closed.insert(current);
+        counters.expanded += 1;
+
+        for (cost, next) in graph.neighbours(current) {
+            let new_cost = *cost_so_far.get(&current) + cost;
+
+            if cost_so_far.find(&next).map_or(false, |&c| new_cost >= c) {
+                continue;
+            }
+
+            cost_so_far.insert(next, new_cost);
+            came_from.insert(next, current);
+
+            // With an inflated heuristic a cheaper route to an expanded node
+            // can still turn up; reopening it keeps the path cost honest.
+            closed.remove(&next);
+            frontier.push(MinPriorityNode { node: next, cost: new_cost + epsilon * heuristic(next) });
+            counters.generated(frontier.len());
+        }
+    }
+
+    None
+}
+
+/// An anytime planner using the Anytime Repairing A* (ARA*) algorithm.
+///
+/// ARA* runs `weighted_a_star_search` with a large `epsilon` to find a first
+/// path quickly, then repeatedly lowers `epsilon` by `step` and repairs the
+/// previous search to find better paths, until `epsilon` reaches one and the
+/// path is optimal. If `step` is not positive, only the first path is
+/// yielded. Construction fails if either endpoint isn't in the graph. Each
+/// call to `next()` yields the next solution along with the `epsilon` it is
+/// guaranteed to be within, so callers can simply stop iterating when they
+/// run out of time:
+///
+/// ```ignore
+/// let mut best = None;
+/// for (solution, epsilon) in try!(AnytimeRepairingAStar::new(&g, start, goal, h, 5, 1)) {
+///     best = Some(solution);
+///     if out_of_time() { break; }
+/// }
+/// ```
+pub struct AnytimeRepairingAStar<'a, T, W, I> {
+    graph: &'a graph::WeightedGraph<'a, T, W, I>,
+    start: &'a T,
+    goal: &'a T,
+    heuristic: |&T|: 'a -> W,
+    epsilon: W,
+    step: W,
+    came_from: HashMap<&'a T, &'a T>,
+    cost_so_far: HashMap<&'a T, W>,
+    // Entries in `queue` are only live if their node is in `open` and their
+    // key is still current.
+    open: HashSet<&'a T>,
+    queue: PriorityQueue<MinPriorityNode<'a, &'a T, W>>,
+    closed: HashSet<&'a T>,
+    // Nodes whose cost improved after they were expanded in the current
+    // iteration, which must be reconsidered in the next.
+    inconsistent: HashSet<&'a T>,
+    started: bool,
+    finished: bool
+}
+
+impl<'a, T: Eq + Hash, W: Cost + Mul<W, W> + Sub<W, W> + One, I: Iterator<(W, &'a T)>>
+    AnytimeRepairingAStar<'a, T, W, I> {
+
+    pub fn new(graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+               heuristic: |&T|: 'a -> W, epsilon: W, step: W)
+        -> Result<AnytimeRepairingAStar<'a, T, W, I>, SearchError> {
+
+        try!(check_endpoints(graph, start, Some(goal)));
+        let mut planner = AnytimeRepairingAStar {
+            graph: graph,
+            start: start,
+            goal: goal,
+            heuristic: heuristic,
+            epsilon: epsilon,
+            step: step,
+            came_from: HashMap::new(),
+            cost_so_far: HashMap::new(),
+            open: HashSet::new(),
+            queue: PriorityQueue::new(),
+            closed: HashSet::new(),
+            inconsistent: HashSet::new(),
+            started: false,
+            finished: false
+        };
+
+        planner.came_from.insert(start, start);
+        planner.cost_so_far.insert(start, Zero::zero());
+        planner.push(start);
+        Ok(planner)
+    }
+
+    /// The inflated estimate used to order the open list.
+    fn key(&mut self, node: &'a T) -> W {
+        *self.cost_so_far.get(&node) + self.epsilon * (self.heuristic)(node)
+    }
+
+    fn push(&mut self, node: &'a T) {
+        let key = self.key(node);
+        self.open.insert(node);
+        self.queue.push(MinPriorityNode { node: node, cost: key });
+    }
+
+    /// Discard any stale entries at the top of the queue, and return the
+    /// smallest live key.
+    fn top_key(&mut self) -> Option<W> {
+        loop {
+            let (node, key) = match self.queue.top() {
+                Some(&MinPriorityNode { node, cost }) => (node, cost),
+                None => return None
+            };
+
+            if self.open.contains(&node) && key == self.key(node) {
+                return Some(key);
+            }
+
+            self.queue.pop();
+        }
+    }
+
+    /// Expand nodes until no open node could lead to a path to the goal
+    /// that is cheaper than the current one, given the current `epsilon`.
+    fn improve_path(&mut self) {
+        loop {
+            let top = match self.top_key() {
+                Some(key) => key,
+                None => break
+            };
+
+            let goal = self.goal;
+            if self.cost_so_far.contains_key(&goal) && self.key(goal) <= top {
+                break;
+            }
+
+            let MinPriorityNode { node: current, cost: _ } = self.queue.pop().unwrap();
+            self.open.remove(&current);
+            self.closed.insert(current);
+
+            let current_cost = *self.cost_so_far.get(&current);
+            for (cost, next) in self.graph.neighbours(current) {
+                let new_cost = current_cost + cost;
+
+                if self.cost_so_far.find(&next).map_or(false, |&c| new_cost >= c) {
+                    continue;
+                }
+
+                self.cost_so_far.insert(next, new_cost);
+                self.came_from.insert(next, current);
+
+                if self.closed.contains(&next) {
+                    self.inconsistent.insert(next);
+                } else {
+                    self.push(next);
+                }
+            }
+        }
+    }
+
+    /// Package up the current path to the goal, if there is one.
+    fn solution(&mut self) -> Option<BoundedPath<'a, T, W>> {
+        let cost = match self.cost_so_far.find(&self.goal) {
+            Some(&cost) => cost,
+            None => return None
+        };
+
+        // The optimal path must pass through some node that is either open
+        // or inconsistent, so the smallest uninflated estimate among them
+        // bounds its cost from below.
+        let pending: Vec<&'a T> = self.open.iter().chain(self.inconsistent.iter()).map(|&n| n).collect();
+        let mut lower_bound = cost;
+        for &node in pending.iter() {
+            let estimate = *self.cost_so_far.get(&node) + (self.heuristic)(node);
+            if estimate < lower_bound {
+                lower_bound = estimate;
+            }
+        }
+
+        Some(BoundedPath {
+            path: reconstruct_path(&self.came_from, self.start, self.goal),
+            cost: cost,
+            lower_bound: lower_bound
+        })
+    }
+}
+
+impl<'a, T: Eq + Hash, W: Cost + Mul<W, W> + Sub<W, W> + One, I: Iterator<(W, &'a T)>>
+    Iterator<(BoundedPath<'a, T, W>, W)> for AnytimeRepairingAStar<'a, T, W, I> {
+
+    fn next(&mut self) -> Option<(BoundedPath<'a, T, W>, W)> {
+        if self.finished {
+            return None;
+        }
+
+        if self.started {
+            let one: W = One::one();
+            if self.epsilon <= one {
+                self.finished = true;
+                return None;
+            }
+
+            // A non-positive `step` would never lower epsilon, and simply
+            // yield the same solution forever.
+            let epsilon = if self.epsilon < self.step + one { one } else { self.epsilon - self.step };
+            if epsilon >= self.epsilon {
+                self.finished = true;
+                return None;
+            }
+            self.epsilon = epsilon;
+
+            // Reconsider everything that was left open or became
+            // inconsistent, keyed by the new epsilon.
+            let pending: Vec<&'a T> = self.open.iter().chain(self.inconsistent.iter()).map(|&n| n).collect();
+            self.open.clear();
+            self.queue.clear();
+            self.closed.clear();
+            self.inconsistent.clear();
+
+            for &node in pending.iter() {
+                self.push(node);
+            }
+        }
+
+        self.started = true;
+        self.improve_path();
+
+        match self.solution() {
+            Some(solution) => {
+                // There's no point in continuing once the path is known to
+                // be optimal.
+                if solution.lower_bound == solution.cost {
+                    self.finished = true;
+                }
+
+                Some((solution, self.epsilon))
+            },
+            None => {
+                self.finished = true;
+                None
+            }
+        }
+    }
+}
+
+/// A path found by `MemoryBoundedAStar`.
+#[deriving(Clone, Show)]
+pub struct MemoryBoundedPath<'a, T, W> {
+    pub path: Vec<&'a T>,
+    pub cost: W,
+    /// Whether the path is known to be optimal. This is `false` when some
+    /// paths were too long to fit in memory and had to be abandoned, in which
+    /// case the path is only the cheapest of those that did fit.
+    pub optimal: bool
+}
+
+/// A node held in memory by `MemoryBoundedAStar`.
+struct BoundedNode<'a, T, W> {
+    state: &'a T,
+    parent: Option<uint>,
+    depth: uint,
+    cost: W,
+    // The backed-up estimate of the cheapest path through this node, where
+    // `None` is infinite.
+    estimate: Option<W>,
+    // Every neighbour that isn't already on the path from the start, each
+    // with the cheapest edge to it.
+    successors: Vec<(W, &'a T)>,
+    children: Vec<uint>,
+    // The smallest estimate among children that have been dropped from
+    // memory, and whether every successor has been generated at least once.
+    forgotten: Option<W>,
+    expanded: bool,
+    alive: bool
+}
+
+/// A planner using simplified memory-bounded A* (SMA*).
+///
+/// SMA* behaves like `a_star_search` until it has `max_nodes` nodes in
+/// memory. From then on, to make room for each new node it forgets the leaf
+/// with the worst estimate, remembering that estimate in the leaf's parent
+/// so that the branch can be regenerated later if everything else turns out
+/// to be worse. This lets it search state spaces far too large for A* with
+/// a fixed amount of memory.
+///
+/// A path can only be found if it fits in memory, that is if it has fewer
+/// than `max_nodes` nodes. The `optimal` flag of the result says whether any
+/// longer paths had to be abandoned.
+pub struct MemoryBoundedAStar<'a, T, W, I> {
+    graph: &'a graph::WeightedGraph<'a, T, W, I>,
+    heuristic: |&T|: 'a -> W,
+    max_nodes: uint
+}
+
+impl<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>> MemoryBoundedAStar<'a, T, W, I> {
+    /// Create a planner that keeps at most `max_nodes` nodes in memory. SMA*
+    /// needs room for a node and one child, so smaller limits are raised to
+    /// two.
+    pub fn new(graph: &'a graph::WeightedGraph<'a, T, W, I>, max_nodes: uint,
+               heuristic: |&T|: 'a -> W) -> MemoryBoundedAStar<'a, T, W, I> {
+        MemoryBoundedAStar { graph: graph, heuristic: heuristic, max_nodes: max(max_nodes, 2) }
+    }
+
+    /// Find a path from `start` to `goal`, or `None` if there is none that
+    /// fits in memory.
+    pub fn search(&mut self, start: &'a T, goal: &'a T)
+        -> Result<Option<MemoryBoundedPath<'a, T, W>>, SearchError> {
+
+        try!(check_endpoints(self.graph, start, Some(goal)));
+        let mut nodes = Vec::new();
+        let mut free = Vec::new();
+        let mut live = 1u;
+        let mut truncated = false;
+
+        let estimate = (self.heuristic)(start);
+        let root = self.make_node(&nodes, start, None, 0, Zero::zero(), Some(estimate), goal);
+        nodes.push(root);
+
+        loop {
+            // Pick the open node with the lowest estimate, preferring the
+            // deepest in the event of a tie.
+            let mut best: Option<uint> = None;
+            for (i, node) in nodes.iter().enumerate() {
+                let open = node.alive && node.estimate.is_some()
+                    && (node.state == goal || node.children.len() < node.successors.len());
+                if !open {
+                    continue;
+                }
+
+                best = match best {
+                    Some(b) if infinite_lt(nodes.get(b).estimate, node.estimate) => Some(b),
+                    Some(b) if nodes.get(b).estimate == node.estimate
+                        && nodes.get(b).depth >= node.depth => Some(b),
+                    _ => Some(i)
+                };
+            }
+
+            let current = match best {
+                Some(i) => i,
+                None => return Ok(None)
+            };
+
+            if nodes.get(current).state == goal {
+                let mut path = Vec::new();
+                let mut i = Some(current);
+                while i.is_some() {
+                    let node = nodes.get(i.unwrap());
+                    path.push(node.state);
+                    i = node.parent;
+                }
+                path.reverse();
+
+                return Ok(Some(MemoryBoundedPath {
+                    path: path,
+                    cost: nodes.get(current).cost,
+                    optimal: !truncated
+                }));
+            }
+
+            // Generate the first successor that isn't currently in memory.
+            let (edge_cost, state) = {
+                let node = nodes.get(current);
+                *node.successors.iter().find(|&&(_, s)| {
+                    !node.children.iter().any(|&c| nodes.get(c).state == s)
+                }).unwrap()
+            };
+
+            let depth = nodes.get(current).depth + 1;
+            let cost = nodes.get(current).cost + edge_cost;
+
+            // A node at the maximum depth can't have children of its own, so
+            // unless it's the goal there's no point in keeping it around.
+            let estimate = if state != goal && depth + 1 >= self.max_nodes {
+                truncated = true;
+                None
+            } else {
+                let parent_estimate = nodes.get(current).estimate.unwrap();
+                Some(max(parent_estimate, cost + (self.heuristic)(state)))
+            };
+
+            if live >= self.max_nodes {
+                let leaf = worst_bounded_leaf(&nodes, current);
+                forget_bounded_leaf(&mut nodes, leaf);
+                free.push(leaf);
+                live -= 1;
+            }
+
+            let child = self.make_node(&nodes, state, Some(current), depth, cost, estimate, goal);
+            let index = match free.pop() {
+                Some(slot) => { *nodes.get_mut(slot) = child; slot },
+                None => { nodes.push(child); nodes.len() - 1 }
+            };
+            live += 1;
+
+            {
+                let node = nodes.get_mut(current);
+                node.children.push(index);
+
+                if node.children.len() == node.successors.len() {
+                    node.expanded = true;
+                    node.forgotten = None;
+                }
+            }
+
+            back_up_bounded_estimates(&mut nodes, current);
+        }
+    }
+
+    fn make_node(&self, nodes: &Vec<BoundedNode<'a, T, W>>, state: &'a T, parent: Option<uint>,
+                 depth: uint, cost: W, estimate: Option<W>, goal: &'a T) -> BoundedNode<'a, T, W> {
+
+        // Only consider neighbours that aren't already on the path here, so
+        // that the search never goes around in circles.
+        let mut successors = Vec::new();
+        for (edge_cost, next) in self.graph.neighbours(state) {
+            let mut ancestor = parent;
+            let mut on_path = next == state;
+
+            while !on_path && ancestor.is_some() {
+                let node = nodes.get(ancestor.unwrap());
+                on_path = node.state == next;
+                ancestor = node.parent;
+            }
+
+            if on_path {
+                continue;
+            }
+
+            // Only the cheapest of any parallel edges is worth following,
+            // and a successor is counted as generated by its state.
+            match successors.iter().position(|&(_, s)| s == next) {
+                Some(i) => {
+                    let &(cheapest, _) = successors.get(i);
+                    if edge_cost < cheapest {
+                        *successors.get_mut(i) = (edge_cost, next);
+                    }
+                },
+                None => successors.push((edge_cost, next))
+            }
+        }
+
+        // A dead end that isn't the goal can never lead anywhere.
+        let estimate = if successors.is_empty() && state != goal { None } else { estimate };
+
+        BoundedNode {
+            state: state,
+            parent: parent,
+            depth: depth,
+            cost: cost,
+            estimate: estimate,
+            successors: successors,
+            children: Vec::new(),
+            forgotten: None,
+            expanded: false,
+            alive: true
+        }
+    }
+}
+
+/// Find the leaf with the worst estimate, preferring the shallowest in
+/// the event of a tie, other than the root and `keep`.
+fn worst_bounded_leaf<'a, T, W: Cost>(nodes: &Vec<BoundedNode<'a, T, W>>, keep: uint) -> uint {
+    let mut worst: Option<uint> = None;
+
+    for (i, node) in nodes.iter().enumerate() {
+        if !node.alive || !node.children.is_empty() || node.parent.is_none() || i == keep {
+            continue;
+        }
+
+        worst = match worst {
+            Some(w) if infinite_lt(node.estimate, nodes.get(w).estimate) => Some(w),
+            Some(w) if nodes.get(w).estimate == node.estimate
+                && nodes.get(w).depth <= node.depth => Some(w),
+            _ => Some(i)
+        };
+    }
+
+    // So long as there are at least two nodes' worth of memory, the only
+    // way to run out is with more than one branch, so there's always a
+    // leaf to spare.
+    worst.unwrap()
+}
+
+/// Drop a leaf from memory, remembering its estimate in its parent.
+fn forget_bounded_leaf<'a, T, W: Cost>(nodes: &mut Vec<BoundedNode<'a, T, W>>, leaf: uint) {
+    let (parent, estimate) = {
+        let node = nodes.get_mut(leaf);
+        node.alive = false;
+        (node.parent.unwrap(), node.estimate)
+    };
+
+    let node = nodes.get_mut(parent);
+    node.children.retain(|&c| c != leaf);
+    node.forgotten = infinite_min(node.forgotten, estimate);
+}
+
+/// Once all of a node's successors have been generated, its estimate can
+/// be tightened to the best of theirs, and so on up the tree.
+fn back_up_bounded_estimates<'a, T, W: Cost>(nodes: &mut Vec<BoundedNode<'a, T, W>>, from: uint) {
+    let mut i = from;
+
+    loop {
+        if !nodes.get(i).expanded {
+            break;
+        }
+
+        let mut estimate = nodes.get(i).forgotten;
+        for &c in nodes.get(i).children.iter() {
+            estimate = infinite_min(estimate, nodes.get(c).estimate);
+        }
+
+        if estimate == nodes.get(i).estimate {
+            break;
+        }
+
+        nodes.get_mut(i).estimate = estimate;
+
+        match nodes.get(i).parent {
+            Some(p) => i = p,
+            None => break
+        }
+    }
+}
+
+/// Extend `path` depth-first, without revisiting nodes already on it, until
+/// its estimated total cost exceeds `threshold`. Returns the cost of the path
+/// to the goal if it is found, and otherwise the smallest estimate that
+/// exceeded the threshold (if there was one) to use as the next threshold.
+/// The path is the whole frontier, so its longest length is counted in
+/// `counters` as the largest frontier.
+fn ida_star_visit<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, goal: &'a T,
+     heuristic: &mut |&T| -> W, path: &mut Vec<&'a T>, cost: W, threshold: W,
+     counters: &mut SearchCounters) -> Result<W, Option<W>> {
+
+    let current = *path.last().unwrap();
+    let estimate = cost + (*heuristic)(current);
+
+    if estimate > threshold {
+        return Err(Some(estimate));
+    }
+
+    if current == goal {
+        return Ok(cost);
+    }
+
+    let mut next_threshold = None;
+    counters.expanded += 1;
+
+    for (edge_cost, next) in graph.neighbours(current) {
+        if path.contains(&next) {
+            continue;
+        }
+
+        path.push(next);
+        counters.generated(path.len());
+        match ida_star_visit(graph, goal, heuristic, path, cost + edge_cost, threshold, counters) {
+            // Leave the path in place so that the caller can return it.
+            Ok(total) => return Ok(total),
+            Err(Some(t)) => if next_threshold.map_or(true, |best| t < best) {
+                next_threshold = Some(t);
+            },
+            Err(None) => ()
+        }
+        path.pop();
+    }
+
+    Err(next_threshold)
+}
+
+/// Find the cheapest path from `start` to `goal` using iterative deepening
+/// A* (IDA*).
+///
+/// This takes the same arguments as `a_star_search`, but rather than keeping
+/// a frontier and a table of costs it performs a series of depth-first
+/// searches bounded by the estimated total cost, so memory use is
+/// proportional to the length of the path. Nodes are re-expanded on every
+/// iteration, which makes it best suited to implicit graphs that are too
+/// large to store, such as puzzle state spaces.
+pub fn ida_star_search<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     heuristic: |&T| -> W) -> Result<Option<(Vec<&'a T>, W)>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+    Ok(counted_ida_star(graph, start, goal, heuristic, &mut SearchCounters::new()))
+}
+
+/// As `ida_star_search`, but also report how much work the search did and
+/// how long it took, even if no path was found. Nodes re-expanded on later
+/// iterations are counted again each time.
+pub fn ida_star_search_with_stats<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     heuristic: |&T| -> W) -> Result<SearchResult<'a, T, W>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+    let started = time::precise_time_ns();
+    let mut counters = SearchCounters::new();
+    let path = counted_ida_star(graph, start, goal, heuristic, &mut counters);
+    Ok(SearchResult { path: path, stats: counters.stats(started) })
+}
+
+/// The machinery behind `ida_star_search`, keeping count of the work done in
+/// `counters`.
+fn counted_ida_star<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     mut heuristic: |&T| -> W, counters: &mut SearchCounters) -> Option<(Vec<&'a T>, W)> {
+
+    let mut threshold = heuristic(start);
+    let mut path = vec!(start);
+
+    loop {
+        match ida_star_visit(graph, goal, &mut heuristic, &mut path, Zero::zero(), threshold, counters) {
+            Ok(cost) => return Some((path, cost)),
+            Err(Some(next)) => threshold = next,
+            Err(None) => return None
+        }
+    }
+}
+
+/// Explore the best successor of the last node in `path` for as long as its
+/// estimate stays within `limit` (where `None` is unlimited) and no worse
+/// than the best alternative. Returns the cost of the path to the goal if it
+/// is found, and otherwise the backed-up estimate of the last node.
+fn recursive_best_first_visit<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, goal: &'a T,
+     heuristic: &mut |&T| -> W, path: &mut Vec<&'a T>, cost: W, estimate: W,
+     limit: Option<W>) -> Result<W, Option<W>> {
+
+    let current = *path.last().unwrap();
+
+    if current == goal {
+        return Ok(cost);
+    }
+
+    // Each successor inherits its parent's estimate if that is higher, so
+    // that estimates backed up from earlier explorations aren't lost.
+    let mut successors = Vec::new();
+    for (edge_cost, next) in graph.neighbours(current) {
+        if path.contains(&next) {
+            continue;
+        }
+
+        let next_cost = cost + edge_cost;
+        let next_estimate = max(next_cost + (*heuristic)(next), estimate);
+        successors.push((Some(next_estimate), next_cost, next));
+    }
+
+    loop {
+        successors.sort_by(|&(a, _, _), &(b, _, _)| {
+            if infinite_lt(a, b) { Less } else if infinite_lt(b, a) { Greater } else { Equal }
+        });
+
+        let (best_estimate, next_cost, next) = match successors.as_slice().head() {
+            Some(&(Some(e), c, n)) => (e, c, n),
+            _ => return Err(None)
+        };
+
+        if infinite_lt(limit, Some(best_estimate)) {
+            return Err(Some(best_estimate));
+        }
+
+        let alternative = match successors.as_slice().get(1) {
+            Some(&(e, _, _)) => e,
+            None => None
+        };
+
+        path.push(next);
+        match recursive_best_first_visit(graph, goal, heuristic, path, next_cost, best_estimate,
+                                         infinite_min(limit, alternative)) {
+            // Leave the path in place so that the caller can return it.
+            Ok(total) => return Ok(total),
+            Err(backed_up) => *successors.get_mut(0) = (backed_up, next_cost, next)
+        }
+        path.pop();
+    }
+}
+
+/// Find the cheapest path from `start` to `goal` using recursive best-first
+/// search (RBFS).
+///
+/// This takes the same arguments as `a_star_search` and also finds optimal
+/// paths, but only keeps the current path and the siblings of the nodes on
+/// it in memory. It explores the most promising branch until some other
+/// branch looks better, then backs up, remembering the best estimate seen
+/// below the abandoned branch so that it can be resumed later.
+pub fn recursive_best_first_search<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     mut heuristic: |&T| -> W) -> Result<Option<(Vec<&'a T>, W)>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+
+    let mut path = vec!(start);
+    let estimate = heuristic(start);
+
+    match recursive_best_first_visit(graph, goal, &mut heuristic, &mut path, Zero::zero(), estimate, None) {
+        Ok(cost) => Ok(Some((path, cost))),
+        Err(_) => Ok(None)
+    }
+}
+
+/// The outcome of a `beam_search`.
+#[deriving(PartialEq, Show)]
+pub enum BeamSearchResult<'a, T, W> {
+    /// A path to the goal and its cost, which may be more than the cheapest.
+    BeamFound(Vec<&'a T>, W),
+    /// No path was found, but nodes were discarded to keep within the beam
+    /// width, so there may well be one.
+    BeamPruned,
+    /// No path was found and nothing was discarded, so there is none.
+    BeamExhausted
+}
+
+/// Search for a path from `start` to `goal` one layer at a time, keeping only
+/// the `width` most promising nodes of each layer.
+///
+/// Nodes are ranked by their cost so far plus the `heuristic`, as in
+/// `a_star_search`. Memory use is bounded by the width of the beam rather
+/// than the size of the graph, but because the rest of each layer is thrown
+/// away the path found may be far from optimal, and the goal may be missed
+/// entirely. Fails with `StartNotFound` or `GoalNotFound` if either endpoint
+/// isn't in the graph.
+pub fn beam_search<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     heuristic: |&T| -> W, width: uint) -> Result<BeamSearchResult<'a, T, W>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+    if start == goal {
+        return Ok(BeamFound(vec!(start), Zero::zero()));
+    }
+
+    let mut beam = vec!(start);
+    let mut came_from = HashMap::new();
+    let mut cost_so_far = HashMap::new();
+    let mut pruned = false;
+
+    came_from.insert(start, start);
+    cost_so_far.insert(start, Zero::zero());
+
+    while !beam.is_empty() {
+        let mut candidates = Vec::new();
+
+        for &current in beam.iter() {
+            let current_cost = *cost_so_far.get(&current);
+
+            for (cost, next) in graph.neighbours(current) {
+                // Every node is only ever considered once, even if it was
+                // pruned, which is what keeps the search moving forwards.
+                if cost_so_far.contains_key(&next) {
+                    continue;
+                }
+
+                let new_cost = current_cost + cost;
+                cost_so_far.insert(next, new_cost);
+                came_from.insert(next, current);
+
+                if next == goal {
+                    return Ok(BeamFound(reconstruct_path(&came_from, start, goal), new_cost));
+                }
+
+                candidates.push(MinPriorityNode { node: next, cost: new_cost + heuristic(next) });
+            }
+        }
+
+        // `MinPriorityNode` sorts in reverse, so the best candidates end up
+        // at the back.
+        candidates.sort();
+        if candidates.len() > width {
+            pruned = true;
+        }
+
+        beam = candidates.iter().rev().take(width).map(|c| c.node).collect();
+    }
+
+    Ok(if pruned { BeamPruned } else { BeamExhausted })
+}
+
+/// Find the cheapest path from `start` to `goal` using fringe search.
+///
+/// This takes the same arguments as `a_star_search` and finds the same
+/// paths, but replaces the priority queue with a pair of lists and a cost
+/// threshold, in the manner of `ida_star_search`. Nodes under the threshold
+/// are expanded depth-first, and the rest are set aside to be revisited once
+/// the threshold is raised. Avoiding the cost of keeping a heap ordered often
+/// makes this faster than A* on grid maps.
+pub fn fringe_search<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     heuristic: |&T| -> W) -> Result<Option<(Vec<&'a T>, W)>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+    Ok(counted_fringe_search(graph, start, goal, heuristic, &mut SearchCounters::new()))
+}
+
+/// As `fringe_search`, but also report how much work the search did and how
+/// long it took, even if no path was found.
+pub fn fringe_search_with_stats<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     heuristic: |&T| -> W) -> Result<SearchResult<'a, T, W>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+    let started = time::precise_time_ns();
+    let mut counters = SearchCounters::new();
+    let path = counted_fringe_search(graph, start, goal, heuristic, &mut counters);
+    Ok(SearchResult { path: path, stats: counters.stats(started) })
+}
+
+/// The machinery behind `fringe_search`, keeping count of the work done in
+/// `counters`. Both lists together make up the frontier.
+fn counted_fringe_search<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, start: &'a T, goal: &'a T,
+     heuristic: |&T| -> W, counters: &mut SearchCounters) -> Option<(Vec<&'a T>, W)> {
+
+    // Each entry records the cost it was added with, so that entries made
+    // obsolete by a cheaper route can be recognised and skipped.
+    let mut now = DList::new();
+    let mut later = DList::new();
+    let mut came_from = HashMap::new();
+    let mut cost_so_far = HashMap::new();
+
+    now.push_back((start, Zero::zero()));
+    came_from.insert(start, start);
+    cost_so_far.insert(start, Zero::zero());
+
+    let mut threshold = heuristic(start);
+
+    loop {
+        let mut next_threshold = None;
+
+        loop {
+            let (current, cost) = match now.pop_front() {
+                Some(entry) => entry,
+                None => break
+            };
+
+            if cost != *cost_so_far.get(&current) {
+                continue;
+            }
+
+            let estimate = cost + heuristic(current);
+            if estimate > threshold {
+                next_threshold = infinite_min(next_threshold, Some(estimate));
+                later.push_back((current, cost));
+                continue;
+            }
+
+            if current == goal {
+                return Some((reconstruct_path(&came_from, start, goal), cost));
+            }
+            counters.expanded += 1;
+
+            for (edge_cost, next) in graph.neighbours(current) {
+                let new_cost = cost + edge_cost;
+
+                if cost_so_far.find(&next).map_or(false, |&c| new_cost >= c) {
+                    continue;
+                }
+
+                cost_so_far.insert(next, new_cost);
+                came_from.insert(next, current);
+
+                // Pushing to the front means children are visited before
+                // the rest of the list, as in a depth-first search.
+                now.push_front((next, new_cost));
+                counters.generated(now.len() + later.len());
+            }
+        }
+
+        // Everything left was over the threshold, so raise it just enough to
+        // let the cheapest of those nodes through.
+        match next_threshold {
+            Some(t) => threshold = t,
+            None => return None
+        }
+
+        mem::swap(&mut now, &mut later);
+    }
+}
+
+/// The shortest paths between every pair of nodes in a graph, as computed by
+/// `floyd_warshall`.
+pub struct AllPairsShortestPaths<'a, T, W> {
+    nodes: Vec<&'a T>,
+    index: HashMap<&'a T, uint>,
+    // Both matrices are stored row-major, indexed by `from * n + to`.
+    distances: Vec<Option<W>>,
+    next_hops: Vec<Option<uint>>
+}
+
+impl<'a, T: Eq + Hash, W: Cost> AllPairsShortestPaths<'a, T, W> {
+    /// The nodes that paths were computed between.
+    pub fn nodes(&self) -> &[&'a T] {
+        self.nodes.as_slice()
+    }
+
+    /// The cost of the cheapest path between two nodes, or `None` if there is
+    /// no such path or either node was not included in the computation.
+    pub fn distance(&self, from: &'a T, to: &'a T) -> Option<W> {
+        match (self.index.find(&from), self.index.find(&to)) {
+            (Some(&i), Some(&j)) => *self.distances.get(i * self.nodes.len() + j),
+            _ => None
+        }
+    }
+
+    /// The cheapest path between two nodes, including both endpoints.
+    ///
+    /// Returns `None` if the route passes through a node on a negative
+    /// cycle, since there is then no cheapest path: going round the cycle
+    /// once more is always cheaper.
+    pub fn path(&self, from: &'a T, to: &'a T) -> Option<Vec<&'a T>> {
+        let n = self.nodes.len();
+        let (mut i, j) = match (self.index.find(&from), self.index.find(&to)) {
+            (Some(&i), Some(&j)) => (i, j),
+            _ => return None
+        };
+
+        if self.next_hops.get(i * n + j).is_none() {
+            return None;
+        }
+
+        let mut path = vec!(from);
+        loop {
+            if self.distances.get(i * n + i).map_or(false, |d| d < Zero::zero()) {
+                return None;
+            }
+            if i == j {
+                break;
+            }
+
+            // A path without cycles never needs more than `n` nodes, so
+            // this also guards against the hops ever looping.
+            if path.len() > n {
+                return None;
+            }
+
+            i = self.next_hops.get(i * n + j).unwrap();
+            path.push(*self.nodes.get(i));
+        }
+
+        Some(path)
+    }
+
+    /// Check whether any node lies on a cycle of negative total weight, in
+    /// which case the distances involving it are meaningless.
+    pub fn has_negative_cycle(&self) -> bool {
+        let n = self.nodes.len();
+        range(0, n).any(|i| self.distances.get(i * n + i).map_or(false, |d| d < Zero::zero()))
+    }
+}
+
+/// Compute the shortest paths between every pair of the given nodes using
+/// the Floyd-Warshall algorithm.
+///
+/// This runs in O(n^3) time and O(n^2) space for `n` nodes, regardless of
+/// the number of edges, which makes it a good fit for small, dense graphs.
+/// Edges leading to nodes outside of `nodes` are ignored, as are repeats
+/// of a node already in the list. Unlike `dijkstra_search`, negative edge
+/// weights are permitted.
+pub fn floyd_warshall<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, nodes: &[&'a T])
+    -> AllPairsShortestPaths<'a, T, W> {
+
+    let mut index = HashMap::with_capacity(nodes.len());
+    let mut unique = Vec::with_capacity(nodes.len());
+    for &node in nodes.iter() {
+        if !index.contains_key(&node) {
+            index.insert(node, unique.len());
+            unique.push(node);
+        }
+    }
+    let nodes = unique.as_slice();
+    let n = nodes.len();
+
+    let mut distances: Vec<Option<W>> = Vec::from_fn(n * n, |_| None);
+    let mut next_hops: Vec<Option<uint>> = Vec::from_fn(n * n, |_| None);
+
+    // Start with the direct edges, keeping only the cheapest between any two
+    // nodes.
+    for i in range(0, n) {
+        *distances.get_mut(i * n + i) = Some(Zero::zero());
+        *next_hops.get_mut(i * n + i) = Some(i);
+
+        for (cost, neighbour) in graph.neighbours(nodes[i]) {
+            let j = match index.find(&neighbour) {
+                Some(&j) => j,
+                None => continue
+            };
+
+            if distances.get(i * n + j).map_or(true, |d| cost < d) {
+                *distances.get_mut(i * n + j) = Some(cost);
+                *next_hops.get_mut(i * n + j) = Some(j);
+            }
+        }
+    }
+
+    // Then allow each node in turn to be used as an intermediate stop.
+    for k in range(0, n) {
+        for i in range(0, n) {
+            let to_k = match *distances.get(i * n + k) {
+                Some(d) => d,
+                None => continue
+            };
+
+            for j in range(0, n) {
+                let through_k = match *distances.get(k * n + j) {
+                    Some(d) => to_k + d,
+                    None => continue
+                };
+
+                if distances.get(i * n + j).map_or(true, |d| through_k < d) {
+                    *distances.get_mut(i * n + j) = Some(through_k);
+                    *next_hops.get_mut(i * n + j) = *next_hops.get(i * n + k);
+                }
+            }
+        }
+    }
+
+    AllPairsShortestPaths {
+        nodes: nodes.to_vec(),
+        index: index,
+        distances: distances,
+        next_hops: next_hops
+    }
+}
+
+/// Join the forward path from `start` to `meeting` together with the backward
+/// path from `meeting` to `goal`.
+fn stitch_path<'a, T: Eq + Hash>(came_from: &HashMap<&'a T, &'a T>, goes_to: &HashMap<&'a T, &'a T>,
+                                  start: &'a T, meeting: &'a T, goal: &'a T) -> Vec<&'a T> {
+    let mut path = reconstruct_path(came_from, start, meeting);
+    let mut current = meeting;
+
+    while current != goal {
+        current = *goes_to.get(&current);
+        path.push(current);
+    }
+
+    path
+}
+
+/// Expand the cheapest node on one side of a bidirectional search, updating
+/// `best` with the cheapest known path through any node that has also been
+/// reached from the other side. Nodes generated are counted in `counters`,
+/// but the largest frontier is left to the caller, which can see both sides.
+fn expand_bidirectional<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>,
+     frontier: &mut PriorityQueue<MinPriorityNode<'a, &'a T, W>>,
+     cost_so_far: &mut HashMap<&'a T, W>, came_from: &mut HashMap<&'a T, &'a T>,
+     other_cost_so_far: &HashMap<&'a T, W>, best: &mut Option<(W, &'a T)>,
+     counters: &mut SearchCounters) -> Result<(), SearchError> {
+
+    let MinPriorityNode { node: current, cost: current_cost } = frontier.pop().unwrap();
+
+    // Skip entries that were superseded by a cheaper route after they
+    // were pushed.
+    if current_cost > *cost_so_far.get(&current) {
+        return Ok(());
+    }
+    counters.expanded += 1;
+
+    for (cost, next) in graph.neighbours(current) {
+        if cost < Zero::zero() {
+            return Err(NegativeWeight);
+        }
+
+        let new_cost = current_cost + cost;
+
+        if cost_so_far.contains_key(&next) && new_cost >= *cost_so_far.get(&next) {
+            continue;
+        }
+
+        cost_so_far.insert(next, new_cost);
+        came_from.insert(next, current);
+        frontier.push(MinPriorityNode { node: next, cost: new_cost });
+        counters.generated += 1;
+
+        // Check whether the two searches have met at `next`.
+        match other_cost_so_far.find(&next) {
+            Some(&other_cost) => {
+                let total = new_cost + other_cost;
+                if best.map_or(true, |(best_cost, _)| total < best_cost) {
+                    *best = Some((total, next));
+                }
+            },
+            None => ()
+        }
+    }
+
+    Ok(())
+}
+
+/// Find the cheapest path from `start` to `goal` by searching forwards from
+/// the start and backwards from the goal at the same time.
+///
+/// The `reverse` graph must contain the same edges as `graph` with their
+/// directions flipped, such as a `graph::Reversed` view of it; for an
+/// undirected graph, simply pass the same graph twice. Returns the path and
+/// its total cost, as with `dijkstra_search`, or a `NegativeWeight` error if
+/// either search comes across a negative edge.
+pub fn bidirectional_dijkstra<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>,
+                              J: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>,
+     reverse: &'a graph::WeightedGraph<'a, T, W, J>,
+     start: &'a T, goal: &'a T) -> Result<Option<(Vec<&'a T>, W)>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+    counted_bidirectional_dijkstra(graph, reverse, start, goal, &mut SearchCounters::new())
+}
+
+/// As `bidirectional_dijkstra`, but also report how much work the search
+/// did and how long it took, even if no path was found.
+pub fn bidirectional_dijkstra_with_stats<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>,
+                                         J: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>,
+     reverse: &'a graph::WeightedGraph<'a, T, W, J>,
+     start: &'a T, goal: &'a T) -> Result<SearchResult<'a, T, W>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+    let started = time::precise_time_ns();
+    let mut counters = SearchCounters::new();
+    let path = try!(counted_bidirectional_dijkstra(graph, reverse, start, goal, &mut counters));
+    Ok(SearchResult { path: path, stats: counters.stats(started) })
+}
+
+/// The machinery behind `bidirectional_dijkstra`, keeping count of the work
+/// done in `counters`. Both frontiers together count as the frontier.
+fn counted_bidirectional_dijkstra<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>,
+                                  J: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>,
+     reverse: &'a graph::WeightedGraph<'a, T, W, J>,
+     start: &'a T, goal: &'a T, counters: &mut SearchCounters)
+    -> Result<Option<(Vec<&'a T>, W)>, SearchError> {
+
+    if start == goal {
+        return Ok(Some((vec!(start), Zero::zero())));
+    }
+
+    let mut forward = PriorityQueue::new();
+    let mut backward = PriorityQueue::new();
+    let mut forward_cost = HashMap::new();
+    let mut backward_cost = HashMap::new();
+    let mut came_from = HashMap::new();
+    let mut goes_to = HashMap::new();
+
+    forward.push(MinPriorityNode { node: start, cost: Zero::zero() });
+    backward.push(MinPriorityNode { node: goal, cost: Zero::zero() });
+    forward_cost.insert(start, Zero::zero());
+    backward_cost.insert(goal, Zero::zero());
+    came_from.insert(start, start);
+    goes_to.insert(goal, goal);
+
+    // The cheapest complete path found so far, and the node it passes
+    // through where the two searches met.
+    let mut best: Option<(W, &'a T)> = None;
+
+    while !forward.is_empty() && !backward.is_empty() {
+        let forward_min = forward.top().unwrap().cost;
+        let backward_min = backward.top().unwrap().cost;
+
+        // Any path that hasn't been found yet must cost at least as much as
+        // the two cheapest frontier nodes combined, so once that can't beat
+        // the best path we're done.
+        match best {
+            Some((best_cost, _)) if forward_min + backward_min >= best_cost => break,
+            _ => ()
+        }
+
+        // Always grow the side with the cheaper frontier.
+        if forward_min <= backward_min {
+            try!(expand_bidirectional(graph, &mut forward, &mut forward_cost, &mut came_from,
+                                      &backward_cost, &mut best, counters));
+        } else {
+            try!(expand_bidirectional(reverse, &mut backward, &mut backward_cost, &mut goes_to,
+                                      &forward_cost, &mut best, counters));
+        }
+        counters.max_frontier = max(counters.max_frontier, forward.len() + backward.len());
+    }
+
+    Ok(best.map(|(cost, meeting)| (stitch_path(&came_from, &goes_to, start, meeting, goal), cost)))
+}
+
+/// Expand the most promising node on one side of a bidirectional A* search,
+/// as with `expand_bidirectional`. Frontier entries are ordered by their
+/// estimated total cost, but also carry the cost they were pushed with so
+/// that stale ones can be told apart.
+fn expand_bidirectional_a_star<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, heuristic: &mut |&T| -> W,
+     frontier: &mut PriorityQueue<MinPriorityNode<'a, (&'a T, W), W>>,
+     cost_so_far: &mut HashMap<&'a T, W>, came_from: &mut HashMap<&'a T, &'a T>,
+     other_cost_so_far: &HashMap<&'a T, W>, best: &mut Option<(W, &'a T)>,
+     counters: &mut SearchCounters) -> Result<(), SearchError> {
+
+    let MinPriorityNode { node: (current, current_cost), cost: _ } = frontier.pop().unwrap();
+
+    if current_cost > *cost_so_far.get(&current) {
+        return Ok(());
+    }
+    counters.expanded += 1;
+
+    for (cost, next) in graph.neighbours(current) {
+        if cost < Zero::zero() {
+            return Err(NegativeWeight);
+        }
+
+        let new_cost = current_cost + cost;
+
+        if cost_so_far.contains_key(&next) && new_cost >= *cost_so_far.get(&next) {
+            continue;
+        }
+
+        cost_so_far.insert(next, new_cost);
+        came_from.insert(next, current);
+        frontier.push(MinPriorityNode { node: (next, new_cost), cost: new_cost + (*heuristic)(next) });
+        counters.generated += 1;
+
+        match other_cost_so_far.find(&next) {
+            Some(&other_cost) => {
+                let total = new_cost + other_cost;
+                if best.map_or(true, |(best_cost, _)| total < best_cost) {
+                    *best = Some((total, next));
+                }
+            },
+            None => ()
+        }
+    }
+
+    Ok(())
+}
+
+/// Find the cheapest path from `start` to `goal` with A*, searching forwards
+/// from the start and backwards from the goal at the same time.
+///
+/// `forward_heuristic` estimates the cost from a node to the goal, and
+/// `backward_heuristic` the cost from the start to a node; both must be
+/// consistent. The `reverse` graph and negative weights are treated as for
+/// `bidirectional_dijkstra`.
+///
+/// Stopping as soon as the two searches meet, as the naive version does, can
+/// return a path that isn't the cheapest. Instead, every path that hasn't
+/// been found yet must pass through an open node on each side, so it can't
+/// cost less than the smallest estimate on that side; the search stops once
+/// either side's smallest estimate is no better than the best path so far.
+pub fn bidirectional_a_star_search<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>,
+                                   J: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>,
+     reverse: &'a graph::WeightedGraph<'a, T, W, J>,
+     start: &'a T, goal: &'a T,
+     forward_heuristic: |&T| -> W, backward_heuristic: |&T| -> W)
+    -> Result<Option<(Vec<&'a T>, W)>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+    counted_bidirectional_a_star(graph, reverse, start, goal, forward_heuristic, backward_heuristic,
+                                 &mut SearchCounters::new())
+}
+
+/// As `bidirectional_a_star_search`, but also report how much work the
+/// search did and how long it took, even if no path was found.
+pub fn bidirectional_a_star_search_with_stats<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>,
+                                              J: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>,
+     reverse: &'a graph::WeightedGraph<'a, T, W, J>,
+     start: &'a T, goal: &'a T,
+     forward_heuristic: |&T| -> W, backward_heuristic: |&T| -> W)
+    -> Result<SearchResult<'a, T, W>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+    let started = time::precise_time_ns();
+    let mut counters = SearchCounters::new();
+    let path = try!(counted_bidirectional_a_star(graph, reverse, start, goal, forward_heuristic,
+                                                 backward_heuristic, &mut counters));
+    Ok(SearchResult { path: path, stats: counters.stats(started) })
+}
+
+/// The machinery behind `bidirectional_a_star_search`, keeping count of the
+/// work done in `counters` as for `counted_bidirectional_dijkstra`.
+fn counted_bidirectional_a_star<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>,
+                                J: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>,
+     reverse: &'a graph::WeightedGraph<'a, T, W, J>,
+     start: &'a T, goal: &'a T,
+     mut forward_heuristic: |&T| -> W, mut backward_heuristic: |&T| -> W,
+     counters: &mut SearchCounters) -> Result<Option<(Vec<&'a T>, W)>, SearchError> {
+
+    if start == goal {
+        return Ok(Some((vec!(start), Zero::zero())));
+    }
+
+    let mut forward = PriorityQueue::new();
+    let mut backward = PriorityQueue::new();
+    let mut forward_cost = HashMap::new();
+    let mut backward_cost = HashMap::new();
+    let mut came_from = HashMap::new();
+    let mut goes_to = HashMap::new();
+
+    forward.push(MinPriorityNode { node: (start, Zero::zero()), cost: forward_heuristic(start) });
+    backward.push(MinPriorityNode { node: (goal, Zero::zero()), cost: backward_heuristic(goal) });
+    forward_cost.insert(start, Zero::zero());
+    backward_cost.insert(goal, Zero::zero());
+    came_from.insert(start, start);
+    goes_to.insert(goal, goal);
+
+    let mut best: Option<(W, &'a T)> = None;
+
+    while !forward.is_empty() && !backward.is_empty() {
+        let forward_min = forward.top().unwrap().cost;
+        let backward_min = backward.top().unwrap().cost;
+
+        match best {
+            Some((best_cost, _)) if forward_min >= best_cost || backward_min >= best_cost => break,
+            _ => ()
+        }
+
+        if forward_min <= backward_min {
+            try!(expand_bidirectional_a_star(graph, &mut forward_heuristic, &mut forward, &mut forward_cost,
+                                             &mut came_from, &backward_cost, &mut best, counters));
+        } else {
+            try!(expand_bidirectional_a_star(reverse, &mut backward_heuristic, &mut backward, &mut backward_cost,
+                                             &mut goes_to, &forward_cost, &mut best, counters));
+        }
+        counters.max_frontier = max(counters.max_frontier, forward.len() + backward.len());
+    }
+
+    Ok(best.map(|(cost, meeting)| (stitch_path(&came_from, &goes_to, start, meeting, goal), cost)))
+}
+
+/// Expand every node in one layer of a bidirectional breadth-first search,
+/// replacing `frontier` with the next layer. Returns the first newly
+/// discovered node that has also been reached from the other side. As with
+/// `expand_bidirectional`, the caller keeps track of the largest frontier.
+fn expand_layer<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, frontier: &mut Vec<&'a T>,
+     came_from: &mut HashMap<&'a T, &'a T>, other_came_from: &HashMap<&'a T, &'a T>,
+     counters: &mut SearchCounters) -> Option<&'a T> {
+
+    let mut next_frontier = Vec::new();
+
+    for &current in frontier.iter() {
+        counters.expanded += 1;
+
+        for (_, next) in graph.neighbours(current) {
+            if came_from.contains_key(&next) {
+                continue;
+            }
+
+            came_from.insert(next, current);
+            counters.generated += 1;
+
+            if other_came_from.contains_key(&next) {
+                return Some(next);
+            }
+
+            next_frontier.push(next);
+        }
+    }
+
+    *frontier = next_frontier;
+    None
+}
+
+/// Find a path from `start` to `goal` with the fewest edges by searching
+/// forwards from the start and backwards from the goal at the same time.
+///
+/// Edge weights are ignored. Each step expands a whole layer of whichever
+/// side currently has the smaller frontier. As with `bidirectional_dijkstra`,
+/// `reverse` must contain the edges of `graph` with their directions flipped.
+pub fn bidirectional_breadth_first_search<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>,
+                                          J: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>,
+     reverse: &'a graph::WeightedGraph<'a, T, W, J>,
+     start: &'a T, goal: &'a T) -> Result<Option<Vec<&'a T>>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+    Ok(counted_bidirectional_breadth_first(graph, reverse, start, goal, &mut SearchCounters::new()))
+}
+
+/// As `bidirectional_breadth_first_search`, but also report how much work
+/// the search did and how long it took, even if no path was found. The cost
+/// of the path is its number of edges.
+pub fn bidirectional_breadth_first_search_with_stats<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>,
+                                                     J: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>,
+     reverse: &'a graph::WeightedGraph<'a, T, W, J>,
+     start: &'a T, goal: &'a T) -> Result<SearchResult<'a, T, uint>, SearchError> {
+
+    try!(check_endpoints(graph, start, Some(goal)));
+    let started = time::precise_time_ns();
+    let mut counters = SearchCounters::new();
+    let path = counted_bidirectional_breadth_first(graph, reverse, start, goal, &mut counters);
+    Ok(SearchResult {
+        path: path.map(|path| { let edges = path.len() - 1; (path, edges) }),
+        stats: counters.stats(started)
+    })
+}
+
+/// The machinery behind `bidirectional_breadth_first_search`, keeping count
+/// of the work done in `counters` as for `counted_bidirectional_dijkstra`.
+fn counted_bidirectional_breadth_first<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>,
+                                       J: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>,
+     reverse: &'a graph::WeightedGraph<'a, T, W, J>,
+     start: &'a T, goal: &'a T, counters: &mut SearchCounters) -> Option<Vec<&'a T>> {
+
+    if start == goal {
+        return Some(vec!(start));
+    }
+
+    let mut forward = vec!(start);
+    let mut backward = vec!(goal);
+    let mut came_from = HashMap::new();
+    let mut goes_to = HashMap::new();
+
+    came_from.insert(start, start);
+    goes_to.insert(goal, goal);
+
+    while !forward.is_empty() && !backward.is_empty() {
+        // Both sides are expanded a full layer at a time, so every meeting
+        // point discovered during a layer gives a path of the same length
+        // and the first one is as good as any.
+        let meeting = if forward.len() <= backward.len() {
+            expand_layer(graph, &mut forward, &mut came_from, &goes_to, counters)
+        } else {
+            expand_layer(reverse, &mut backward, &mut goes_to, &came_from, counters)
+        };
+        counters.max_frontier = max(counters.max_frontier, forward.len() + backward.len());
+
+        match meeting {
+            Some(node) => return Some(stitch_path(&came_from, &goes_to, start, node, goal)),
+            None => ()
+        }
+    }
+
+    None
+}
+
+/// How many nodes a witness search may settle while contracting a node
+/// before giving up and adding the shortcut anyway.
+static WITNESS_SEARCH_LIMIT: uint = 500;
+
+/// Search outwards from `source` for paths that avoid `avoid` and any node
+/// that has already been contracted, giving up once they cost more than
+/// `limit`. Every distance in the result belongs to a real path, even if the
+/// search was cut short before it could be shown to be the cheapest.
+fn witness_distances<W: Cost>(out: &Vec<HashMap<uint, W>>, contracted: &Vec<bool>,
+                              source: uint, avoid: uint, limit: W) -> HashMap<uint, W> {
+    let mut frontier = PriorityQueue::new();
+    let mut cost_so_far = HashMap::new();
+    let mut settled = 0u;
+
+    frontier.push(MinPriorityNode { node: source, cost: Zero::zero() });
+    cost_so_far.insert(source, Zero::zero());
+
+    while !frontier.is_empty() && settled < WITNESS_SEARCH_LIMIT {
+        let MinPriorityNode { node: current, cost: current_cost } = frontier.pop().unwrap();
+
+        if current_cost > *cost_so_far.get(&current) {
+            continue;
+        }
+        if current_cost > limit {
+            break;
+        }
+        settled += 1;
+
+        for (&next, &cost) in out.get(current).iter() {
+            if next == avoid || *contracted.get(next) {
+                continue;
+            }
+
+            let new_cost = current_cost + cost;
+            if cost_so_far.find(&next).map_or(true, |&c| new_cost < c) {
+                cost_so_far.insert(next, new_cost);
+                frontier.push(MinPriorityNode { node: next, cost: new_cost });
+            }
+        }
+    }
+
+    cost_so_far
+}
+
+/// The shortcuts that contracting `node` would need: one for each pair of
+/// its remaining neighbours for which the path through it is the only
+/// cheapest one that could be found.
+fn contraction_shortcuts<W: Cost>(out: &Vec<HashMap<uint, W>>, inc: &Vec<HashMap<uint, W>>,
+                                  contracted: &Vec<bool>, node: uint) -> Vec<(uint, uint, W)> {
+    let mut shortcuts = Vec::new();
+
+    for (&from, &first) in inc.get(node).iter() {
+        if *contracted.get(from) {
+            continue;
+        }
+
+        let mut limit = first;
+        for (_, &second) in out.get(node).iter() {
+            limit = max(limit, first + second);
+        }
+
+        let witnesses = witness_distances(out, contracted, from, node, limit);
+
+        for (&to, &second) in out.get(node).iter() {
+            if to == from || *contracted.get(to) {
+                continue;
+            }
+
+            let through = first + second;
+            if witnesses.find(&to).map_or(true, |&c| c > through) {
+                shortcuts.push((from, to, through));
+            }
+        }
+    }
+
+    shortcuts
+}
+
+/// Dijkstra's algorithm over one half of a contraction hierarchy, returning
+/// the distance to and predecessor of every node it reaches.
+fn upward_search<W: Cost>(edges: &Vec<Vec<(uint, W)>>, source: uint)
+                          -> (HashMap<uint, W>, HashMap<uint, uint>) {
+    let mut frontier = PriorityQueue::new();
+    let mut cost_so_far = HashMap::new();
+    let mut came_from = HashMap::new();
+
+    frontier.push(MinPriorityNode { node: source, cost: Zero::zero() });
+    cost_so_far.insert(source, Zero::zero());
+
+    while !frontier.is_empty() {
+        let MinPriorityNode { node: current, cost: current_cost } = frontier.pop().unwrap();
+
+        if current_cost > *cost_so_far.get(&current) {
+            continue;
+        }
+
+        for &(next, cost) in edges.get(current).iter() {
+            let new_cost = current_cost + cost;
+            if cost_so_far.find(&next).map_or(true, |&c| new_cost < c) {
+                cost_so_far.insert(next, new_cost);
+                came_from.insert(next, current);
+                frontier.push(MinPriorityNode { node: next, cost: new_cost });
+            }
+        }
+    }
+
+    (cost_so_far, came_from)
+}
+
+/// A contraction hierarchy, which answers shortest path queries on large,
+/// rarely changing graphs such as road networks far faster than a plain
+/// search.
+///
+/// Preprocessing ranks the nodes by importance and removes ("contracts")
+/// them one at a time from least to most important, adding shortcut edges
+/// between the neighbours of each so that the distances between the nodes
+/// that remain are unchanged. A query then only needs to search upwards in
+/// rank from both ends, which touches a tiny fraction of the graph, before
+/// the shortcuts on the path are expanded back into the edges they replace.
+///
+/// Nodes are ordered lazily by their edge difference (the shortcuts needed
+/// minus the edges removed) plus the number of their neighbours that have
+/// already been contracted, which keeps the hierarchy evenly spread.
+pub struct ContractionHierarchy<'a, T, W> {
+    nodes: Vec<&'a T>,
+    index: HashMap<&'a T, uint>,
+    // Edges to higher ranked nodes, and edges from higher ranked nodes
+    // stored against their heads, for the forward and backward searches.
+    upward: Vec<Vec<(uint, W)>>,
+    downward: Vec<Vec<(uint, W)>>,
+    // The node each shortcut bypasses.
+    middle: HashMap<(uint, uint), uint>
+}
+
+impl<'a, T: Eq + Hash, W: Cost> ContractionHierarchy<'a, T, W> {
+
+    /// Build the hierarchy for the graph made up of `nodes`. Edges that lead
+    /// outside of `nodes` are ignored, and weights must not be negative.
+    pub fn new<I: Iterator<(W, &'a T)>>(graph: &'a graph::WeightedGraph<'a, T, W, I>,
+                                       nodes: &[&'a T])
+        -> Result<ContractionHierarchy<'a, T, W>, SearchError> {
+
+        let mut index = HashMap::new();
+        for (i, &node) in nodes.iter().enumerate() {
+            index.insert(node, i);
+        }
+
+        // Keep only the cheapest of any parallel edges.
+        let mut out: Vec<HashMap<uint, W>> = Vec::from_fn(nodes.len(), |_| HashMap::new());
+        let mut inc: Vec<HashMap<uint, W>> = Vec::from_fn(nodes.len(), |_| HashMap::new());
+        for (i, &node) in nodes.iter().enumerate() {
+            for (cost, next) in graph.neighbours(node) {
+                if cost < Zero::zero() {
+                    return Err(NegativeWeight);
+                }
+
+                let j = match index.find(&next) {
+                    Some(&j) if j != i => j,
+                    _ => continue
+                };
+
+                out.get_mut(i).insert_or_update_with(j, cost, |_, c| *c = min(*c, cost));
+                inc.get_mut(j).insert_or_update_with(i, cost, |_, c| *c = min(*c, cost));
+            }
+        }
+
+        let mut contracted = Vec::from_elem(nodes.len(), false);
+        let mut neighbours_contracted = Vec::from_elem(nodes.len(), 0i);
+        let mut rank = Vec::from_elem(nodes.len(), 0u);
+        let mut middle = HashMap::new();
+
+        let priority = |out: &Vec<HashMap<uint, W>>, inc: &Vec<HashMap<uint, W>>,
+                        contracted: &Vec<bool>, neighbours_contracted: &Vec<int>, node: uint| {
+            let shortcuts = contraction_shortcuts(out, inc, contracted, node);
+            let removed = out.get(node).keys().chain(inc.get(node).keys())
+                             .filter(|&&n| !*contracted.get(n)).count();
+            (shortcuts.len() as int - removed as int + *neighbours_contracted.get(node), shortcuts)
+        };
+
+        let mut queue = PriorityQueue::new();
+        for node in range(0, nodes.len()) {
+            let (p, _) = priority(&out, &inc, &contracted, &neighbours_contracted, node);
+            queue.push(MinPriorityNode { node: node, cost: p });
+        }
+
+        let mut next_rank = 0u;
+        while !queue.is_empty() {
+            let MinPriorityNode { node, cost: _ } = queue.pop().unwrap();
+
+            // Priorities go stale as the graph around a node changes, so
+            // recompute this one and put it back if it's no longer the best.
+            let (p, shortcuts) = priority(&out, &inc, &contracted, &neighbours_contracted, node);
+            if queue.top().map_or(false, |top| p > top.cost) {
+                queue.push(MinPriorityNode { node: node, cost: p });
+                continue;
+            }
+
+            for &(from, to, cost) in shortcuts.iter() {
+                out.get_mut(from).insert_or_update_with(to, cost, |_, c| *c = cost);
+                inc.get_mut(to).insert_or_update_with(from, cost, |_, c| *c = cost);
+                middle.insert((from, to), node);
+            }
+
+            *contracted.get_mut(node) = true;
+            *rank.get_mut(node) = next_rank;
+            next_rank += 1;
+
+            let neighbours: Vec<uint> = out.get(node).keys().chain(inc.get(node).keys()).map(|&n| n).collect();
+            for &n in neighbours.iter() {
+                *neighbours_contracted.get_mut(n) += 1;
+            }
+        }
+
+        let mut upward = Vec::from_fn(nodes.len(), |_| Vec::new());
+        let mut downward = Vec::from_fn(nodes.len(), |_| Vec::new());
+        for (from, edges) in out.iter().enumerate() {
+            for (&to, &cost) in edges.iter() {
+                if *rank.get(from) < *rank.get(to) {
+                    upward.get_mut(from).push((to, cost));
+                } else {
+                    downward.get_mut(to).push((from, cost));
+                }
+            }
+        }
+
+        Ok(ContractionHierarchy {
+            nodes: nodes.to_vec(),
+            index: index,
+            upward: upward,
+            downward: downward,
+            middle: middle
+        })
+    }
+
+    /// Find the cheapest path from `start` to `goal`, returning it and its
+    /// total cost as with `dijkstra_search`.
+    pub fn search(&self, start: &'a T, goal: &'a T) -> Option<(Vec<&'a T>, W)> {
+        let (s, t) = match (self.index.find(&start), self.index.find(&goal)) {
+            (Some(&s), Some(&t)) => (s, t),
+            _ => return None
+        };
+
+        let (forward_cost, came_from) = upward_search(&self.upward, s);
+        let (backward_cost, goes_to) = upward_search(&self.downward, t);
+
+        // The cheapest path peaks at the highest ranked node on it, which
+        // both searches will have reached.
+        let mut best: Option<(W, uint)> = None;
+        for (&node, &cost) in forward_cost.iter() {
+            match backward_cost.find(&node) {
+                Some(&other) if best.map_or(true, |(b, _)| cost + other < b) => {
+                    best = Some((cost + other, node));
+                },
+                _ => ()
+            }
+        }
+
+        let (cost, meeting) = match best {
+            Some(best) => best,
+            None => return None
+        };
+
+        // List the hierarchy's edges along the path, from both sides of the
+        // meeting node.
+        let mut hops = vec!(meeting);
+        let mut current = meeting;
+        while current != s {
+            current = *came_from.get(&current);
+            hops.push(current);
+        }
+        hops.reverse();
+
+        current = meeting;
+        while current != t {
+            current = *goes_to.get(&current);
+            hops.push(current);
+        }
+
+        // Then replace each shortcut with the two edges it stands for, until
+        // only real edges are left.
+        let mut path = vec!(*self.nodes.get(s));
+        for pair in hops.as_slice().windows(2) {
+            let mut stack = vec!((pair[0], pair[1]));
+            while !stack.is_empty() {
+                let (from, to) = stack.pop().unwrap();
+                match self.middle.find(&(from, to)) {
+                    Some(&via) => {
+                        stack.push((via, to));
+                        stack.push((from, via));
+                    },
+                    None => path.push(*self.nodes.get(to))
+                }
+            }
+        }
+
+        Some((path, cost))
+    }
+}
+
+/// Assign the cells of a grid `width` cells wide to square regions
+/// `region_size` cells across, for use with `ArcFlags`.
+pub fn partition_grid(cells: &[&(uint, uint)], width: uint, region_size: uint) -> Vec<uint> {
+    let columns = (width + region_size - 1) / region_size;
+    cells.iter().map(|&&(x, y)| (y / region_size) * columns + x / region_size).collect()
+}
+
+/// Divide `nodes` into about `count` regions of connected nodes, for use
+/// with `ArcFlags`.
+///
+/// Each region is grown breadth-first from the first node that doesn't have
+/// one yet, until it holds its share of the nodes. Parts of the graph that
+/// can't be reached from the rest end up in regions of their own, so there
+/// may be more than `count` of them.
+pub fn partition_breadth_first<'a, T: Eq + Hash, W, I: Iterator<(W, &'a T)>>
+    (graph: &'a graph::WeightedGraph<'a, T, W, I>, nodes: &[&'a T], count: uint) -> Vec<uint> {
+
+    let share = max((nodes.len() + count - 1) / max(count, 1), 1);
+    let mut region_of: HashMap<&'a T, uint> = HashMap::new();
+    let mut regions = 0u;
+
+    for &seed in nodes.iter() {
+        if region_of.contains_key(&seed) {
+            continue;
+        }
+
+        let mut frontier = DList::new();
+        let mut size = 0u;
+        frontier.push_back(seed);
+        region_of.insert(seed, regions);
+
+        while !frontier.is_empty() && size < share {
+            let current = frontier.pop_front().unwrap();
+            size += 1;
+
+            for (_, next) in graph.neighbours(current) {
+                if size + frontier.len() < share && !region_of.contains_key(&next) {
+                    region_of.insert(next, regions);
+                    frontier.push_back(next);
+                }
+            }
+        }
+
+        regions += 1;
+    }
+
+    nodes.iter().map(|node| *region_of.get(node)).collect()
+}
+
+/// Precomputed arc flags for pruning searches on a static graph.
+///
+/// The nodes are divided into regions, and every edge is flagged with the
+/// regions that some cheapest path through it leads to. A search for a goal
+/// can then skip every edge not flagged with the goal's region, which on a
+/// well partitioned graph leaves little more than the edges near the
+/// cheapest path itself. Flags are found by searching backwards from each
+/// node where edges enter a region, so building them is expensive; they can
+/// be saved with `write_to()` and restored with `read_from()`.
+pub struct ArcFlags<'a, T, W> {
+    nodes: Vec<&'a T>,
+    index: HashMap<&'a T, uint>,
+    regions: Vec<uint>,
+    region_count: uint,
+    // The edges out of node `i` are `edges[offsets[i]..offsets[i + 1]]`.
+    offsets: Vec<uint>,
+    edges: Vec<(uint, W)>,
+    // One bit per region for each edge, packed into `words` words apiece.
+    words: uint,
+    flags: Vec<u32>
+}
+
+impl<'a, T: Eq + Hash, W: Cost> ArcFlags<'a, T, W> {
+
+    /// Compute the flags for the graph made up of `nodes`, where `regions`
+    /// gives the region of each node. The `reverse` graph is as for
+    /// `bidirectional_dijkstra`, and weights must not be negative.
+    pub fn new<I: Iterator<(W, &'a T)>, J: Iterator<(W, &'a T)>>
+        (graph: &'a graph::WeightedGraph<'a, T, W, I>,
+         reverse: &'a graph::WeightedGraph<'a, T, W, J>,
+         nodes: &[&'a T], regions: Vec<uint>) -> Result<ArcFlags<'a, T, W>, SearchError> {
+
+        let mut flags = try!(ArcFlags::unflagged(graph, nodes, regions));
+
+        // Edges within a region lead to it.
+        for from in range(0, flags.nodes.len()) {
+            for edge in range(*flags.offsets.get(from), *flags.offsets.get(from + 1)) {
+                let (to, _) = *flags.edges.get(edge);
+                if *flags.regions.get(from) == *flags.regions.get(to) {
+                    let region = *flags.regions.get(to);
+                    flags.set(edge, region);
+                }
+            }
+        }
+
+        // Any other cheapest path into a region must be a cheapest path to
+        // the node where it last enters, so search back from each of those.
+        let mut boundary = Vec::from_elem(flags.nodes.len(), false);
+        for from in range(0, flags.nodes.len()) {
+            for edge in range(*flags.offsets.get(from), *flags.offsets.get(from + 1)) {
+                let (to, _) = *flags.edges.get(edge);
+                if *flags.regions.get(from) != *flags.regions.get(to) {
+                    *boundary.get_mut(to) = true;
+                }
+            }
+        }
+
+        for target in range(0, flags.nodes.len()).filter(|&n| *boundary.get(n)) {
+            let region = *flags.regions.get(target);
+            let (_, distance) = try!(dijkstra_tables(reverse, *flags.nodes.get(target), |_| false, &mut None,
+                                                    |_, _| true));
+
+            for from in range(0, flags.nodes.len()) {
+                let from_distance = match distance.find(flags.nodes.get(from)) {
+                    Some(&d) => d,
+                    None => continue
+                };
+
+                for edge in range(*flags.offsets.get(from), *flags.offsets.get(from + 1)) {
+                    let (to, cost) = *flags.edges.get(edge);
+                    let to = *flags.nodes.get(to);
+                    match distance.find(&to) {
+                        Some(&d) if cost + d == from_distance => flags.set(edge, region),
+                        _ => ()
+                    }
+                }
+            }
+        }
+
+        Ok(flags)
+    }
+
+    /// Collect the edges of the graph, with no flags set yet.
+    fn unflagged<I: Iterator<(W, &'a T)>>(graph: &'a graph::WeightedGraph<'a, T, W, I>,
+                                         nodes: &[&'a T], regions: Vec<uint>)
+        -> Result<ArcFlags<'a, T, W>, SearchError> {
+
+        assert!(regions.len() == nodes.len(), "every node needs a region");
+
+        let mut index = HashMap::new();
+        for (i, &node) in nodes.iter().enumerate() {
+            index.insert(node, i);
+        }
+
+        let mut offsets = vec!(0u);
+        let mut edges = Vec::new();
+        for &node in nodes.iter() {
+            for (cost, next) in graph.neighbours(node) {
+                if cost < Zero::zero() {
+                    return Err(NegativeWeight);
+                }
+
+                match index.find(&next) {
+                    Some(&j) => edges.push((j, cost)),
+                    None => ()
+                }
+            }
+            offsets.push(edges.len());
+        }
+
+        let region_count = regions.iter().max().map_or(0, |&r| r + 1);
+        let words = (region_count + 31) / 32;
+
+        Ok(ArcFlags {
+            nodes: nodes.to_vec(),
+            index: index,
+            regions: regions,
+            region_count: region_count,
+            flags: Vec::from_elem(edges.len() * words, 0u32),
+            offsets: offsets,
+            edges: edges,
+            words: words
+        })
+    }
+
+    fn set(&mut self, edge: uint, region: uint) {
+        let word = edge * self.words + region / 32;
+        *self.flags.get_mut(word) |= 1 << (region % 32);
+    }
+
+    fn is_set(&self, edge: uint, region: uint) -> bool {
+        *self.flags.get(edge * self.words + region / 32) & (1 << (region % 32)) != 0
+    }
+
+    pub fn region_count(&self) -> uint {
+        self.region_count
+    }
+
+    /// Save the flags in a compact binary format. Only the flags themselves
+    /// are saved, not the graph they belong to.
+    pub fn write_to(&self, writer: &mut Writer) -> IoResult<()> {
+        try!(writer.write_le_u32(self.region_count as u32));
+        try!(writer.write_le_u32(self.edges.len() as u32));
+
+        for &word in self.flags.iter() {
+            try!(writer.write_le_u32(word));
+        }
+
+        Ok(())
+    }
+
+    /// Restore flags previously saved with `write_to()`, for the same graph,
+    /// nodes, and regions they were computed with.
+    pub fn read_from<I: Iterator<(W, &'a T)>>(graph: &'a graph::WeightedGraph<'a, T, W, I>,
+                                             nodes: &[&'a T], regions: Vec<uint>, reader: &mut Reader)
+        -> IoResult<ArcFlags<'a, T, W>> {
+
+        let mut flags = match ArcFlags::unflagged(graph, nodes, regions) {
+            Ok(flags) => flags,
+            Err(_) => return Err(IoError {
+                kind: InvalidInput,
+                desc: "graph has a negative edge weight",
+                detail: None
+            })
+        };
+
+        let region_count = try!(reader.read_le_u32()) as uint;
+        let edge_count = try!(reader.read_le_u32()) as uint;
+        if region_count != flags.region_count || edge_count != flags.edges.len() {
+            return Err(IoError {
+                kind: InvalidInput,
+                desc: "saved flags are for a different graph",
+                detail: None
+            });
+        }
+
+        for word in flags.flags.mut_iter() {
+            *word = try!(reader.read_le_u32());
+        }
+
+        Ok(flags)
+    }
+
+    /// Find the cheapest path from `start` to `goal`, following only edges
+    /// flagged with the goal's region. Returns the path and its total cost,
+    /// as with `dijkstra_search`.
+    pub fn search(&self, start: &'a T, goal: &'a T) -> Option<(Vec<&'a T>, W)> {
+        let (s, t) = match (self.index.find(&start), self.index.find(&goal)) {
+            (Some(&s), Some(&t)) => (s, t),
+            _ => return None
+        };
+        let region = *self.regions.get(t);
+
+        let mut frontier = PriorityQueue::new();
+        let mut came_from = HashMap::new();
+        let mut cost_so_far = HashMap::new();
+
+        frontier.push(MinPriorityNode { node: s, cost: Zero::zero() });
+        came_from.insert(s, s);
+        cost_so_far.insert(s, Zero::zero());
+
+        while !frontier.is_empty() {
+            let MinPriorityNode { node: current, cost: current_cost } = frontier.pop().unwrap();
+
+            if current_cost > *cost_so_far.get(&current) {
+                continue;
+            }
+
+            if current == t {
+                let mut path = vec!(*self.nodes.get(t));
+                let mut node = t;
+                while node != s {
+                    node = *came_from.get(&node);
+                    path.push(*self.nodes.get(node));
+                }
+                path.reverse();
+                return Some((path, current_cost));
+            }
+
+            for edge in range(*self.offsets.get(current), *self.offsets.get(current + 1)) {
+                if !self.is_set(edge, region) {
+                    continue;
+                }
+
+                let (next, cost) = *self.edges.get(edge);
+                let new_cost = current_cost + cost;
+                if cost_so_far.find(&next).map_or(true, |&c| new_cost < c) {
+                    cost_so_far.insert(next, new_cost);
+                    came_from.insert(next, current);
+                    frontier.push(MinPriorityNode { node: next, cost: new_cost });
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// An integration field and flow field over a grid, leading every cell
+/// towards the nearest of a set of goal cells.
+///
+/// Rather than searching for a path for each of many agents heading to the
+/// same place, the field is built once with `multi_source_dijkstra` and each
+/// agent simply follows `direction()` from wherever it stands. This is also
+/// known as a Dijkstra map.
+pub struct FlowField {
+    width: uint,
+    height: uint,
+    // The cost of reaching the nearest goal from each cell, and the move to
+    // make from it, if it can reach a goal at all.
+    costs: Vec<Option<uint>>,
+    directions: Vec<Option<(int, int)>>
+}
+
+impl FlowField {
+    pub fn new(grid: &graph::GridGraph, goals: &[(uint, uint)]) -> FlowField {
+        let (width, height) = (grid.width(), grid.height());
+
+        // Grids are undirected, so the cost of getting from a cell to a goal
+        // is the same as getting from the goal to the cell.
+        let sources: Vec<(&(uint, uint), uint)> = goals.iter().filter(|goal| grid.is_walkable(*goal))
+                                                       .map(|goal| (grid.find_cell(goal).unwrap(), 0u))
+                                                       .collect();
+        let nearest = multi_source_dijkstra(grid, sources.as_slice()).unwrap();
+
+        let mut costs = Vec::from_elem(width * height, None);
+        for (&&(x, y), &(_, cost)) in nearest.iter() {
+            *costs.get_mut(y * width + x) = Some(cost);
+        }
+
+        // Each cell points to whichever neighbour is on the cheapest route.
+        let mut directions = Vec::from_elem(width * height, None);
+        for (&&(x, y), &(_, cost)) in nearest.iter() {
+            if cost == 0 {
+                continue;
+            }
+
+            let mut best: Option<(uint, (int, int))> = None;
+            for (step, &(nx, ny)) in grid.neighbours(&(x, y)) {
+                match *costs.get(ny * width + nx) {
+                    Some(c) if best.map_or(true, |(b, _)| step + c < b) => {
+                        best = Some((step + c, (nx as int - x as int, ny as int - y as int)));
+                    },
+                    _ => ()
+                }
+            }
+
+            *directions.get_mut(y * width + x) = best.map(|(_, direction)| direction);
+        }
+
+        FlowField { width: width, height: height, costs: costs, directions: directions }
+    }
+
+    pub fn width(&self) -> uint {
+        self.width
+    }
+
+    pub fn height(&self) -> uint {
+        self.height
+    }
+
+    /// The cost of reaching the nearest goal from `cell`, or `None` if no
+    /// goal can be reached from it.
+    pub fn cost(&self, cell: &(uint, uint)) -> Option<uint> {
+        let &(x, y) = cell;
+        if x < self.width && y < self.height { *self.costs.get(y * self.width + x) } else { None }
+    }
+
+    /// The move to make from `cell` to head towards the nearest goal, as an
+    /// `(x, y)` offset. This is `None` at the goals themselves and wherever
+    /// no goal can be reached.
+    pub fn direction(&self, cell: &(uint, uint)) -> Option<(int, int)> {
+        let &(x, y) = cell;
+        if x < self.width && y < self.height { *self.directions.get(y * self.width + x) } else { None }
+    }
+}
+
+/// The straight-line distance between the centres of two grid cells.
+fn grid_distance(a: &(uint, uint), b: &(uint, uint)) -> OrderedFloat<f64> {
+    let (&(ax, ay), &(bx, by)) = (a, b);
+    let (dx, dy) = (ax as f64 - bx as f64, ay as f64 - by as f64);
+    OrderedFloat((dx * dx + dy * dy).sqrt())
+}
+
+/// The flavours of Theta* supported by `theta_star_search`.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum ThetaStarVariant {
+    /// Check line of sight whenever a neighbour is generated.
+    BasicThetaStar,
+    /// Assume line of sight when a neighbour is generated, and only check it
+    /// once that neighbour is expanded, falling back to the best expanded
+    /// neighbour as its parent if the assumption was wrong. This performs far
+    /// fewer line of sight checks on large open maps, at the cost of
+    /// occasionally slightly longer paths.
+    LazyThetaStar
+}
+
+/// Find a path across a grid from `start` to `goal` that is not restricted to
+/// the grid's edges, using the Theta* algorithm.
+///
+/// This works like `a_star_search`, except that whenever a cell's parent has
+/// line of sight to one of its neighbours, that neighbour is connected
+/// directly to the parent instead. The resulting path contains only the cells
+/// where it changes direction, and its cost is the total straight-line
+/// distance between them. When the line of sight checks happen is controlled
+/// by `variant`. Fails with `StartNotFound` or `GoalNotFound` if either cell
+/// is off the grid.
+pub fn theta_star_search<'a>(grid: &'a graph::GridGraph, start: &'a (uint, uint),
+                             goal: &'a (uint, uint), variant: ThetaStarVariant)
+                             -> Result<Option<(Vec<&'a (uint, uint)>, f64)>, SearchError> {
+
+    try!(check_endpoints(grid, start, Some(goal)));
+
+    let mut frontier = PriorityQueue::new();
+    let mut came_from = HashMap::new();
+    let mut cost_so_far = HashMap::new();
+    let mut closed = HashSet::new();
+
+    frontier.push(MinPriorityNode { node: start, cost: grid_distance(start, goal) });
+    came_from.insert(start, start);
+    cost_so_far.insert(start, OrderedFloat(0.0f64));
+
+    while !frontier.is_empty() {
+        let MinPriorityNode { node: current, cost: _ } = frontier.pop().unwrap();
+
+        if closed.contains(&current) {
+            continue;
+        }
+
+        // Lazy Theta* assumed that this cell could be seen from its parent.
+        // If it can't, attach it to whichever expanded neighbour gives the
+        // cheapest route instead.
+        if variant == LazyThetaStar && !grid.has_line_of_sight(*came_from.get(&current), current) {
+            let mut best = None;
+
+            for (_, prev) in grid.neighbours(current) {
+                if !closed.contains(&prev) {
+                    continue;
+                }
+
+                let cost = *cost_so_far.get(&prev) + grid_distance(prev, current);
+                if best.map_or(true, |(c, _)| cost < c) {
+                    best = Some((cost, prev));
+                }
+            }
+
+            match best {
+                Some((cost, prev)) => {
+                    cost_so_far.insert(current, cost);
+                    came_from.insert(current, prev);
+                },
+                None => ()
+            }
+        }
+
+        if current == goal {
+            let OrderedFloat(cost) = *cost_so_far.get(&goal);
+            return Ok(Some((reconstruct_path(&came_from, start, goal), cost)));
+        }
+
+        closed.insert(current);
+        let parent = *came_from.get(&current);
+
+        for (_, next) in grid.neighbours(current) {
+            if closed.contains(&next) {
+                continue;
+            }
+
+            // Skip over the current cell entirely if its parent can see
+            // `next`, since the straight line is never longer. The lazy
+            // variant defers that check until `next` is expanded.
+            let visible = variant == LazyThetaStar || grid.has_line_of_sight(parent, next);
+            let (from, new_cost) = if visible {
+                (parent, *cost_so_far.get(&parent) + grid_distance(parent, next))
+            } else {
+                (current, *cost_so_far.get(&current) + grid_distance(current, next))
+            };
+
+            if cost_so_far.find(&next).map_or(true, |&c| new_cost < c) {
+                cost_so_far.insert(next, new_cost);
+                came_from.insert(next, from);
+                frontier.push(MinPriorityNode { node: next, cost: new_cost + grid_distance(next, goal) });
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// The eight directions used by `JumpPointTable`, clockwise from north.
+/// Straight directions have even indices and diagonals odd ones, so turning
+/// by 45 degrees is a matter of adding or subtracting one (modulo eight).
+static JUMP_DIRECTIONS: [(int, int), ..8] = [(0, -1), (1, -1), (1, 0), (1, 1),
+                                             (0, 1), (-1, 1), (-1, 0), (-1, -1)];
+
+/// Precomputed jump distances for answering repeated queries on a static grid
+/// with the JPS+ algorithm.
+///
+/// For every walkable cell and each of the eight directions, the table
+/// records how far a search moving in that direction would travel before
+/// reaching either a jump point (a cell where the cheapest paths might turn)
+/// or an obstacle. Queries then leap straight from one jump point to the
+/// next, which is dramatically faster than expanding every cell in between.
+///
+/// Movement follows the rules of an `EightWayNoCorners` `GridGraph`,
+/// regardless of the connectivity of the grid the table was built from, and
+/// costs are `ORTHOGONAL_COST` and `DIAGONAL_COST` per step. Building the
+/// table is comparatively expensive, so it can be saved with `write_to()`
+/// when a level is baked and loaded again with `read_from()`.
+pub struct JumpPointTable {
+    width: uint,
+    height: uint,
+    walkable: Vec<bool>,
+    // Eight entries per cell. A positive entry is the number of steps to the
+    // next jump point, and any other entry is minus the number of steps that
+    // can be taken before hitting an obstacle.
+    distances: Vec<int>
+}
+
+impl JumpPointTable {
+    pub fn new(grid: &graph::GridGraph) -> JumpPointTable {
+        let (width, height) = (grid.width(), grid.height());
+
+        let mut walkable = Vec::with_capacity(width * height);
+        for y in range(0, height) {
+            for x in range(0, width) {
+                walkable.push(grid.is_walkable(&(x, y)));
+            }
+        }
+
+        let mut table = JumpPointTable {
+            width: width,
+            height: height,
+            walkable: walkable,
+            distances: Vec::from_elem(width * height * 8, 0i)
+        };
+
+        // Diagonal jump points are defined in terms of the straight ones, so
+        // the straight directions must be filled in first.
+        for dir in range(0u, 8).filter(|d| d % 2 == 0) {
+            table.sweep(dir);
+        }
+        for dir in range(0u, 8).filter(|d| d % 2 == 1) {
+            table.sweep(dir);
+        }
+
+        table
+    }
+
+    pub fn width(&self) -> uint {
+        self.width
+    }
+
+    pub fn height(&self) -> uint {
+        self.height
+    }
+
+    /// Save the table in a compact binary format.
+    pub fn write_to(&self, writer: &mut Writer) -> IoResult<()> {
+        try!(writer.write_le_u32(self.width as u32));
+        try!(writer.write_le_u32(self.height as u32));
+
+        for &walkable in self.walkable.iter() {
+            try!(writer.write_u8(walkable as u8));
+        }
+
+        for &distance in self.distances.iter() {
+            try!(writer.write_le_i32(distance as i32));
+        }
+
+        Ok(())
+    }
+
+    /// Load a table previously saved with `write_to()`.
+    pub fn read_from(reader: &mut Reader) -> IoResult<JumpPointTable> {
+        let width = try!(reader.read_le_u32()) as uint;
+        let height = try!(reader.read_le_u32()) as uint;
+
+        // Cells are addressed with `int` coordinates, and a corrupt header
+        // mustn't be allowed to overflow the table size.
+        let max_side = i32::MAX as uint;
+        let cells = match width.checked_mul(&height).and_then(|cells| cells.checked_mul(&8).map(|_| cells)) {
+            Some(cells) if width <= max_side && height <= max_side => cells,
+            _ => return Err(IoError {
+                kind: InvalidInput,
+                desc: "saved table has an invalid size",
+                detail: None
+            })
+        };
+
+        // Don't trust the header with the allocation either; a truncated
+        // table fails on the first missing byte.
+        let mut walkable = Vec::new();
+        for _ in range(0, cells) {
+            walkable.push(try!(reader.read_u8()) != 0);
+        }
+
+        let mut distances = Vec::new();
+        for _ in range(0, cells * 8) {
+            distances.push(try!(reader.read_le_i32()) as int);
+        }
+
+        let table = JumpPointTable { width: width, height: height, walkable: walkable, distances: distances };
+
+        // Nor with the jumps, since one that leaves the grid would send
+        // `search` off the end of the table.
+        for y in range(0, height as int) {
+            for x in range(0, width as int) {
+                for dir in range(0u, 8) {
+                    let (dx, dy) = JUMP_DIRECTIONS[dir];
+                    let reach = table.distance((x, y), dir).abs();
+                    let (ex, ey) = (x + dx * reach, y + dy * reach);
+
+                    if ex < 0 || ey < 0 || ex >= width as int || ey >= height as int {
+                        return Err(IoError {
+                            kind: InvalidInput,
+                            desc: "saved table has a jump off the grid",
+                            detail: None
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(table)
+    }
+
+    /// Find the cheapest path from `start` to `goal`. The path contains only
+    /// the jump points along it, where it may change direction.
+    pub fn search(&self, start: (uint, uint), goal: (uint, uint)) -> Option<(Vec<(uint, uint)>, uint)> {
+        let ((sx, sy), (tx, ty)) = (start, goal);
+        let (start, goal) = ((sx as int, sy as int), (tx as int, ty as int));
+
+        if !self.walkable(start) || !self.walkable(goal) {
+            return None;
+        }
+
+        let mut frontier = PriorityQueue::new();
+        let mut came_from = HashMap::new();
+        let mut cost_so_far = HashMap::new();
+        let mut travelling = HashMap::new();
+        let mut closed = HashSet::new();
+
+        frontier.push(MinPriorityNode { node: start, cost: octile_distance(start, goal) });
+        came_from.insert(start, start);
+        cost_so_far.insert(start, 0u);
+
+        while !frontier.is_empty() {
+            let MinPriorityNode { node: current, cost: _ } = frontier.pop().unwrap();
+
+            if closed.contains(&current) {
+                continue;
+            }
+
+            if current == goal {
+                let mut path = vec!(current);
+                let mut node = current;
+                while node != start {
+                    node = *came_from.get(&node);
+                    path.push(node);
+                }
+                path.reverse();
+
+                let path = path.iter().map(|&(x, y)| (x as uint, y as uint)).collect();
+                return Some((path, *cost_so_far.get(&goal)));
+            }
+
+            closed.insert(current);
+
+            // Only the directions in which the cheapest paths could continue
+            // need to be explored, given the direction we arrived from.
+            let directions = match travelling.find(&current) {
+                None => vec!(0u, 1, 2, 3, 4, 5, 6, 7),
+                Some(&d) if d % 2 == 0 => vec!(d, (d + 1) % 8, (d + 7) % 8, (d + 2) % 8, (d + 6) % 8),
+                Some(&d) => vec!(d, (d + 1) % 8, (d + 7) % 8)
+            };
+
+            let (x, y) = current;
+            let (gx, gy) = (tx as int - x, ty as int - y);
+
+            for &dir in directions.iter() {
+                let (dx, dy) = JUMP_DIRECTIONS[dir];
+                let distance = self.distance(current, dir);
+                let reach = distance.abs();
+
+                // Stop short of the next jump point if the goal lies on the
+                // way to it, or where the goal is lined up on one axis.
+                let steps = if dir % 2 == 0 {
+                    let ahead = gx * dx + gy * dy;
+                    let aligned = if dx == 0 { gx == 0 } else { gy == 0 };
+
+                    if aligned && ahead > 0 && ahead <= reach { Some(ahead) }
+                    else if distance > 0 { Some(distance) }
+                    else { None }
+                } else {
+                    let (ahead_x, ahead_y) = (gx * dx, gy * dy);
+                    let ahead = min(ahead_x, ahead_y);
+
+                    if ahead_x > 0 && ahead_y > 0 && ahead <= reach { Some(ahead) }
+                    else if distance > 0 { Some(distance) }
+                    else { None }
+                };
+
+                let steps = match steps {
+                    Some(steps) => steps,
+                    None => continue
+                };
+
+                let next = (x + dx * steps, y + dy * steps);
+                let step_cost = if dir % 2 == 0 { graph::ORTHOGONAL_COST } else { graph::DIAGONAL_COST };
+                let new_cost = *cost_so_far.get(&current) + steps as uint * step_cost;
+
+                if cost_so_far.find(&next).map_or(true, |&c| new_cost < c) {
+                    cost_so_far.insert(next, new_cost);
+                    came_from.insert(next, current);
+                    travelling.insert(next, dir);
+                    frontier.push(MinPriorityNode { node: next, cost: new_cost + octile_distance(next, goal) });
+                }
+            }
+        }
+
+        None
+    }
+
+    fn walkable(&self, cell: (int, int)) -> bool {
+        let (x, y) = cell;
+        x >= 0 && y >= 0 && (x as uint) < self.width && (y as uint) < self.height
+            && *self.walkable.get(y as uint * self.width + x as uint)
+    }
+
+    fn index(&self, cell: (int, int), dir: uint) -> uint {
+        let (x, y) = cell;
+        (y as uint * self.width + x as uint) * 8 + dir
+    }
+
+    fn distance(&self, cell: (int, int), dir: uint) -> int {
+        *self.distances.get(self.index(cell, dir))
+    }
+
+    /// Check whether a single step from `cell` in direction `dir` is allowed.
+    fn can_step(&self, cell: (int, int), dir: uint) -> bool {
+        let ((x, y), (dx, dy)) = (cell, JUMP_DIRECTIONS[dir]);
+
+        self.walkable((x + dx, y + dy))
+            && (dir % 2 == 0 || (self.walkable((x + dx, y)) && self.walkable((x, y + dy))))
+    }
+
+    /// Check whether a search moving in straight direction `dir` must stop at
+    /// `cell`, because one of the cells beside it can't be reached any more
+    /// cheaply from anywhere but here.
+    fn is_jump_point(&self, cell: (int, int), dir: uint) -> bool {
+        let ((x, y), (dx, dy)) = (cell, JUMP_DIRECTIONS[dir]);
+        let (px, py) = (x - dx, y - dy);
+
+        [(dy, dx), (-dy, -dx)].iter().any(|&(ox, oy)| {
+            !self.walkable((px + ox, py + oy)) && self.walkable((x + ox, y + oy))
+        })
+    }
+
+    /// Fill in the distances for one direction across the whole grid.
+    fn sweep(&mut self, dir: uint) {
+        let (dx, dy) = JUMP_DIRECTIONS[dir];
+        let (width, height) = (self.width as int, self.height as int);
+
+        // Visit the cells in an order that guarantees the neighbour in
+        // direction `dir` is always filled in first.
+        let xs: Vec<int> = if dx > 0 { range(0, width).rev().collect() } else { range(0, width).collect() };
+        let ys: Vec<int> = if dy > 0 { range(0, height).rev().collect() } else { range(0, height).collect() };
+
+        for &y in ys.iter() {
+            for &x in xs.iter() {
+                let cell = (x, y);
+                if !self.walkable(cell) {
+                    continue;
+                }
+
+                let distance = if !self.can_step(cell, dir) {
+                    0
+                } else {
+                    let next = (x + dx, y + dy);
+                    let jump = if dir % 2 == 0 {
+                        self.is_jump_point(next, dir)
+                    } else {
+                        // A diagonal move stops wherever a straight jump
+                        // along either of its components would find
+                        // something.
+                        self.distance(next, (dir + 7) % 8) > 0 || self.distance(next, (dir + 1) % 8) > 0
+                    };
+
+                    if jump {
+                        1
+                    } else {
+                        let onward = self.distance(next, dir);
+                        if onward > 0 { onward + 1 } else { onward - 1 }
+                    }
+                };
+
+                let index = self.index(cell, dir);
+                *self.distances.get_mut(index) = distance;
+            }
+        }
+    }
+}
+
+/// The cost of the cheapest eight-way path between two cells if there were no
+/// obstacles in the way.
+fn octile_distance(a: (int, int), b: (int, int)) -> uint {
+    let ((ax, ay), (bx, by)) = (a, b);
+    let (dx, dy) = ((ax - bx).abs() as uint, (ay - by).abs() as uint);
+    graph::ORTHOGONAL_COST * max(dx, dy) + (graph::DIAGONAL_COST - graph::ORTHOGONAL_COST) * min(dx, dy)
+}
+
+/// Borders between clusters with at least this many consecutive open cells
+/// get an entrance at each end of the opening rather than one in the middle.
+static WIDE_ENTRANCE: uint = 6;
+
+/// An abstraction of a large `GridGraph` for hierarchical pathfinding with
+/// the HPA* algorithm.
+///
+/// The grid is divided into square clusters, and pairs of cells on either
+/// side of the openings between neighbouring clusters become entrances.
+/// Preprocessing links the entrances to their partners across each border,
+/// and to the other entrances of the same cluster by the cheapest path that
+/// stays inside it. A query then only has to search this much smaller graph
+/// of entrances with `abstract_search()`, and `refine()` fills in the cells
+/// between them afterwards, one cluster at a time.
+///
+/// Paths are usually close to the cheapest, but not always, since they are
+/// forced through the chosen entrances.
+pub struct HierarchicalGrid<'a> {
+    grid: &'a graph::GridGraph,
+    cluster_size: uint,
+    // The entrances in each cluster, and the edges between entrances.
+    entrances: HashMap<(uint, uint), Vec<&'a (uint, uint)>>,
+    edges: HashMap<&'a (uint, uint), Vec<(uint, &'a (uint, uint))>>
+}
+
+impl<'a> HierarchicalGrid<'a> {
+    pub fn new(grid: &'a graph::GridGraph, cluster_size: uint) -> HierarchicalGrid<'a> {
+        assert!(cluster_size > 0, "clusters must be at least one cell across");
+
+        let mut hierarchy = HierarchicalGrid {
+            grid: grid,
+            cluster_size: cluster_size,
+            entrances: HashMap::new(),
+            edges: HashMap::new()
+        };
+
+        let (width, height) = (grid.width(), grid.height());
+        let columns = (width + cluster_size - 1) / cluster_size;
+        let rows = (height + cluster_size - 1) / cluster_size;
+
+        // Look for openings along the right and bottom border of each
+        // cluster.
+        for cy in range(0, rows) {
+            for cx in range(0, columns) {
+                if cx + 1 < columns {
+                    let x = (cx + 1) * cluster_size - 1;
+                    let border: Vec<((uint, uint), (uint, uint))> =
+                        range(cy * cluster_size, min((cy + 1) * cluster_size, height))
+                            .map(|y| ((x, y), (x + 1, y))).collect();
+                    hierarchy.add_entrances(border.as_slice());
+                }
+
+                if cy + 1 < rows {
+                    let y = (cy + 1) * cluster_size - 1;
+                    let border: Vec<((uint, uint), (uint, uint))> =
+                        range(cx * cluster_size, min((cx + 1) * cluster_size, width))
+                            .map(|x| ((x, y), (x, y + 1))).collect();
+                    hierarchy.add_entrances(border.as_slice());
+                }
+            }
+        }
+
+        // Then link up the entrances within each cluster.
+        let clusters: Vec<Vec<&'a (uint, uint)>> = hierarchy.entrances.values().map(|e| e.clone()).collect();
+        for entrances in clusters.iter() {
+            for &from in entrances.iter() {
+                let (_, cost_so_far) = hierarchy.local_search(from, None);
+                for &to in entrances.iter() {
+                    match cost_so_far.find(&to) {
+                        Some(&cost) if to != from => hierarchy.edges.get_mut(&from).push((cost, to)),
+                        _ => ()
+                    }
+                }
+            }
+        }
+
+        hierarchy
+    }
+
+    pub fn cluster_size(&self) -> uint {
+        self.cluster_size
+    }
+
+    /// The cells that were chosen as entrances.
+    pub fn entrances(&self) -> Vec<&'a (uint, uint)> {
+        self.edges.keys().map(|&cell| cell).collect()
+    }
+
+    /// The cluster a cell belongs to, as its column and row.
+    fn cluster_of(&self, cell: &(uint, uint)) -> (uint, uint) {
+        let &(x, y) = cell;
+        (x / self.cluster_size, y / self.cluster_size)
+    }
+
+    /// Place entrances on an opening between two clusters, given as the
+    /// pairs of cells that face each other across the border, in order.
+    fn add_entrances(&mut self, border: &[((uint, uint), (uint, uint))]) {
+        let mut i = 0;
+        while i < border.len() {
+            // Measure the next run of pairs that can be stepped between.
+            let mut length = 0;
+            while i + length < border.len() {
+                let (a, b) = border[i + length];
+                if self.step_cost(&a, &b).is_none() {
+                    break;
+                }
+                length += 1;
+            }
+
+            let chosen = if length == 0 {
+                vec!()
+            } else if length < WIDE_ENTRANCE {
+                vec!(i + length / 2)
+            } else {
+                vec!(i, i + length - 1)
+            };
+
+            for &j in chosen.iter() {
+                let (a, b) = border[j];
+                let (a, b) = (self.grid.find_cell(&a).unwrap(), self.grid.find_cell(&b).unwrap());
+                let cost = self.step_cost(a, b).unwrap();
+
+                for &(from, to) in [(a, b), (b, a)].iter() {
+                    let cluster = self.cluster_of(from);
+                    let entrances = self.entrances.find_or_insert(cluster, Vec::new());
+                    if !entrances.contains(&from) {
+                        entrances.push(from);
+                    }
+                    self.edges.find_or_insert(from, Vec::new()).push((cost, to));
+                }
+            }
+
+            i += length + 1;
+        }
+    }
+
+    /// The cost of stepping directly from one cell to another, if that's
+    /// allowed.
+    fn step_cost(&self, from: &(uint, uint), to: &(uint, uint)) -> Option<uint> {
+        self.grid.neighbours(from).find(|&(_, next)| next == to).map(|(cost, _)| cost)
+    }
+
+    /// Run `dijkstra_search` without leaving the cluster containing `from`.
+    fn local_search(&self, from: &'a (uint, uint), to: Option<&'a (uint, uint)>)
+                    -> (HashMap<&'a (uint, uint), &'a (uint, uint)>, HashMap<&'a (uint, uint), uint>) {
+        let cluster = self.cluster_of(from);
+        // Grid weights are never negative, so this can't fail.
+        dijkstra_tables(self.grid, from, |n| to == Some(n), &mut None, |_, next| self.cluster_of(next) == cluster).unwrap()
+    }
+
+    /// An admissible estimate of the cost between two cells.
+    fn estimate(&self, from: &(uint, uint), to: &(uint, uint)) -> uint {
+        heuristic::for_grid(self.grid).estimate(from, to)
+    }
+
+    /// Find the cheapest route from `start` to `goal` through the graph of
+    /// entrances, returning the entrances along the way (with the endpoints)
+    /// and the total cost.
+    pub fn abstract_search(&self, start: &'a (uint, uint), goal: &'a (uint, uint))
+                           -> Option<(Vec<&'a (uint, uint)>, uint)> {
+        if !self.grid.is_walkable(start) || !self.grid.is_walkable(goal) {
+            return None;
+        }
+
+        // Temporarily link the endpoints to the entrances of their own
+        // clusters, and to each other if they share one.
+        let (_, from_start) = self.local_search(start, None);
+        let (_, to_goal) = self.local_search(goal, None);
+
+        let mut start_edges = Vec::new();
+        for (&cell, &cost) in from_start.iter() {
+            if cell == goal || (cell != start && self.edges.contains_key(&cell)) {
+                start_edges.push((cost, cell));
+            }
+        }
+
+        let mut frontier = PriorityQueue::new();
+        let mut came_from = HashMap::new();
+        let mut cost_so_far = HashMap::new();
+
+        frontier.push(MinPriorityNode { node: start, cost: self.estimate(start, goal) });
+        came_from.insert(start, start);
+        cost_so_far.insert(start, 0u);
+
+        while !frontier.is_empty() {
+            let MinPriorityNode { node: current, cost: _ } = frontier.pop().unwrap();
+            let current_cost = *cost_so_far.get(&current);
+
+            if current == goal {
+                return Some((reconstruct_path(&came_from, start, goal), current_cost));
+            }
+
+            let mut steps = Vec::new();
+            if current == start {
+                steps.push_all(start_edges.as_slice());
+            }
+            match self.edges.find(&current) {
+                Some(edges) => steps.push_all(edges.as_slice()),
+                None => ()
+            }
+            match to_goal.find(&current) {
+                Some(&cost) => steps.push((cost, goal)),
+                None => ()
+            }
+
+            for &(cost, next) in steps.iter() {
+                let new_cost = current_cost + cost;
+                if cost_so_far.find(&next).map_or(true, |&c| new_cost < c) {
+                    cost_so_far.insert(next, new_cost);
+                    came_from.insert(next, current);
+                    frontier.push(MinPriorityNode { node: next, cost: new_cost + self.estimate(next, goal) });
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Expand a path from `abstract_search()` into every cell along it.
+    pub fn refine(&self, path: &[&'a (uint, uint)]) -> Vec<&'a (uint, uint)> {
+        let mut cells = path.head().map_or(Vec::new(), |&cell| vec!(cell));
+
+        for pair in path.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+
+            // Steps across a border are already a single move.
+            if self.cluster_of(from) != self.cluster_of(to) {
+                cells.push(to);
+                continue;
+            }
+
+            let (came_from, _) = self.local_search(from, Some(to));
+            cells.push_all(reconstruct_path(&came_from, from, to).slice_from(1));
+        }
+
+        cells
+    }
+
+    /// Find a path from `start` to `goal` and its cost, by way of
+    /// `abstract_search()` and `refine()`.
+    pub fn search(&self, start: &'a (uint, uint), goal: &'a (uint, uint))
+                  -> Option<(Vec<&'a (uint, uint)>, uint)> {
+        self.abstract_search(start, goal).map(|(path, cost)| (self.refine(path.as_slice()), cost))
+    }
+}
+
+/// A sparse graph of waypoints extracted from a `GridGraph`, for fast
+/// any-angle pathfinding on maps that don't change.
+///
+/// The waypoints are the walkable cells just off each convex corner of an
+/// obstacle, which are the only places a shortest path ever needs to turn,
+/// and two waypoints are linked if a straight line between them stays on
+/// walkable cells. Searches then only have to visit a handful of waypoints
+/// rather than every cell. Costs are straight-line distances, and paths are
+/// given as the cells where they turn, as with `theta_star_search`.
+pub struct WaypointGraph<'a> {
+    grid: &'a graph::GridGraph,
+    waypoints: Vec<&'a (uint, uint)>,
+    edges: HashMap<&'a (uint, uint), Vec<(OrderedFloat<f64>, &'a (uint, uint))>>
+}
+
+impl<'a> WaypointGraph<'a> {
+    pub fn new(grid: &'a graph::GridGraph) -> WaypointGraph<'a> {
+        let mut waypoints = Vec::new();
+        for y in range(0, grid.height()) {
+            for x in range(0, grid.width()) {
+                if !grid.is_walkable(&(x, y)) {
+                    continue;
+                }
+
+                // A convex corner has a blocked cell diagonally beside it
+                // with both of the cells in between open.
+                let corner = [(-1i, -1i), (1, -1), (1, 1), (-1, 1)].iter().any(|&(dx, dy)| {
+                    let (nx, ny) = (x as int + dx, y as int + dy);
+                    if nx < 0 || ny < 0 || nx as uint >= grid.width() || ny as uint >= grid.height() {
+                        return false;
+                    }
+                    let (nx, ny) = (nx as uint, ny as uint);
+                    !grid.is_walkable(&(nx, ny)) && grid.is_walkable(&(nx, y)) && grid.is_walkable(&(x, ny))
+                });
+
+                if corner {
+                    waypoints.push(grid.find_cell(&(x, y)).unwrap());
+                }
+            }
+        }
+
+        let mut edges = HashMap::new();
+        for &from in waypoints.iter() {
+            let visible = waypoints.iter()
+                .filter(|&&to| to != from && grid.has_line_of_sight(from, to))
+                .map(|&to| (grid_distance(from, to), to))
+                .collect();
+            edges.insert(from, visible);
+        }
+
+        WaypointGraph { grid: grid, waypoints: waypoints, edges: edges }
+    }
+
+    pub fn waypoints(&self) -> &[&'a (uint, uint)] {
+        self.waypoints.as_slice()
+    }
+
+    /// The waypoints in sight of `waypoint`, with the distance to each, or
+    /// `None` if it isn't a waypoint.
+    pub fn edges(&self, waypoint: &'a (uint, uint)) -> Option<&[(OrderedFloat<f64>, &'a (uint, uint))]> {
+        self.edges.find(&waypoint).map(|edges| edges.as_slice())
+    }
+
+    /// Project an arbitrary cell onto the waypoint graph, giving the
+    /// waypoints in sight of it and the distance to each. This is how a
+    /// search gets on and off the graph.
+    pub fn project(&self, cell: &'a (uint, uint)) -> Vec<(OrderedFloat<f64>, &'a (uint, uint))> {
+        if !self.grid.is_walkable(cell) {
+            return Vec::new();
+        }
+
+        self.waypoints.iter()
+            .filter(|&&w| w != cell && self.grid.has_line_of_sight(cell, w))
+            .map(|&w| (grid_distance(cell, w), w))
+            .collect()
+    }
+
+    /// Find the shortest any-angle path from `start` to `goal` by way of the
+    /// waypoints, returning the cells where it turns (with the endpoints)
+    /// and its length.
+    pub fn search(&self, start: &'a (uint, uint), goal: &'a (uint, uint))
+                  -> Option<(Vec<&'a (uint, uint)>, OrderedFloat<f64>)> {
+        if !self.grid.is_walkable(start) || !self.grid.is_walkable(goal) {
+            return None;
+        }
+
+        // Temporarily link the endpoints to the waypoints they can see, and
+        // to each other if there's nothing in the way.
+        let mut start_edges = self.project(start);
+        if self.grid.has_line_of_sight(start, goal) {
+            start_edges.push((grid_distance(start, goal), goal));
+        }
+        let to_goal: HashMap<&'a (uint, uint), OrderedFloat<f64>> =
+            self.project(goal).move_iter().map(|(cost, w)| (w, cost)).collect();
+
+        let mut frontier = PriorityQueue::new();
+        let mut came_from = HashMap::new();
+        let mut cost_so_far = HashMap::new();
+
+        frontier.push(MinPriorityNode { node: start, cost: grid_distance(start, goal) });
+        cost_so_far.insert(start, OrderedFloat(0.0f64));
+
+        while !frontier.is_empty() {
+            let MinPriorityNode { node: current, cost: _ } = frontier.pop().unwrap();
+            let current_cost = *cost_so_far.get(&current);
+
+            if current == goal {
+                return Some((reconstruct_path(&came_from, start, goal), current_cost));
+            }
+
+            let mut steps = Vec::new();
+            if current == start {
+                steps.push_all(start_edges.as_slice());
+            } else {
+                match self.edges(current) {
+                    Some(edges) => steps.push_all(edges),
+                    None => ()
+                }
+            }
+            match to_goal.find(&current) {
+                Some(&cost) => steps.push((cost, goal)),
+                None => ()
+            }
+
+            for &(cost, next) in steps.iter() {
+                let new_cost = current_cost + cost;
+                if cost_so_far.find(&next).map_or(true, |&c| new_cost < c) {
+                    cost_so_far.insert(next, new_cost);
+                    came_from.insert(next, current);
+                    frontier.push(MinPriorityNode { node: next, cost: new_cost + grid_distance(next, goal) });
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Check whether `a < b`, where `None` stands for an infinite cost.
+fn infinite_lt<W: Ord + Copy>(a: Option<W>, b: Option<W>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a < b,
+        (Some(_), None) => true,
+        (None, _) => false
+    }
+}
+
+/// The smaller of two costs, where `None` stands for an infinite cost.
+fn infinite_min<W: Ord + Copy>(a: Option<W>, b: Option<W>) -> Option<W> {
+    if infinite_lt(b, a) { b } else { a }
+}
+
+/// The bookkeeping shared by the incremental planners, `DStarLite` and
+/// `LifelongPlanningAStar`.
+struct IncrementalState<'a, T, W, I, J> {
+    graph: &'a graph::WeightedGraph<'a, T, W, I>,
+    reverse: &'a graph::WeightedGraph<'a, T, W, J>,
+    // Missing entries are infinite.
+    g: HashMap<&'a T, W>,
+    rhs: HashMap<&'a T, W>,
+    // Entries are removed lazily: an entry in `queue` is only live if it
+    // matches the key recorded for its node in `queued`.
+    queue: PriorityQueue<MinPriorityNode<'a, &'a T, (W, W)>>,
+    queued: HashMap<&'a T, (W, W)>,
+    // Edge costs that differ from the graph's, where `None` is impassable.
+    changed: HashMap<(&'a T, &'a T), Option<W>>
+}
+
+impl<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>, J: Iterator<(W, &'a T)>>
+    IncrementalState<'a, T, W, I, J> {
+
+    fn new(graph: &'a graph::WeightedGraph<'a, T, W, I>,
+           reverse: &'a graph::WeightedGraph<'a, T, W, J>) -> IncrementalState<'a, T, W, I, J> {
+        IncrementalState {
+            graph: graph,
+            reverse: reverse,
+            g: HashMap::new(),
+            rhs: HashMap::new(),
+            queue: PriorityQueue::new(),
+            queued: HashMap::new(),
+            changed: HashMap::new()
+        }
+    }
+
+    fn g(&self, node: &'a T) -> Option<W> {
+        self.g.find(&node).map(|&cost| cost)
+    }
+
+    fn rhs(&self, node: &'a T) -> Option<W> {
+        self.rhs.find(&node).map(|&cost| cost)
+    }
+
+    fn set_rhs(&mut self, node: &'a T, cost: Option<W>) {
+        match cost {
+            Some(cost) => { self.rhs.insert(node, cost); },
+            None => { self.rhs.remove(&node); }
+        }
+    }
+
+    /// The outgoing edges of a node, taking changed costs into account.
+    fn successors(&self, from: &'a T) -> Vec<(W, &'a T)> {
+        let mut edges = Vec::new();
+
+        for (cost, to) in self.graph.neighbours(from) {
+            match self.changed.find(&(from, to)) {
+                Some(&Some(changed)) => edges.push((changed, to)),
+                Some(&None) => (),
+                None => edges.push((cost, to))
+            }
+        }
+
+        edges
+    }
+
+    /// The incoming edges of a node, taking changed costs into account.
+    fn predecessors(&self, to: &'a T) -> Vec<(W, &'a T)> {
+        let mut edges = Vec::new();
+
+        for (cost, from) in self.reverse.neighbours(to) {
+            match self.changed.find(&(from, to)) {
+                Some(&Some(changed)) => edges.push((changed, from)),
+                Some(&None) => (),
+                None => edges.push((cost, from))
+            }
+        }
+
+        edges
+    }
+
+    fn enqueue(&mut self, node: &'a T, key: (W, W)) {
+        self.queued.insert(node, key);
+        self.queue.push(MinPriorityNode { node: node, cost: key });
+    }
+
+    fn dequeue(&mut self, node: &'a T) {
+        self.queued.remove(&node);
+    }
+
+    /// Discard any stale entries at the top of the queue, and return the
+    /// smallest live key.
+    fn top_key(&mut self) -> Option<(W, W)> {
+        loop {
+            let (node, key) = match self.queue.top() {
+                Some(&MinPriorityNode { node, cost }) => (node, cost),
+                None => return None
+            };
+
+            if self.queued.find(&node) == Some(&key) {
+                return Some(key);
+            }
+
+            self.queue.pop();
+        }
+    }
+
+    /// Remove and return the live entry with the smallest key. This must only
+    /// be called after `top_key()` has returned `Some`.
+    fn pop(&mut self) -> (&'a T, (W, W)) {
+        let MinPriorityNode { node, cost } = self.queue.pop().unwrap();
+        self.queued.remove(&node);
+        (node, cost)
+    }
+}
+
+/// An incremental planner using the D* Lite algorithm.
+///
+/// D* Lite searches backwards from the goal, so that when an agent moving
+/// along the path discovers that some edge costs have changed it can repair
+/// the existing search rather than starting over. A typical loop is:
+///
+/// ```ignore
+/// let mut planner = try!(DStarLite::new(&g, &g, start, goal, |a, b| distance(a, b)));
+/// let mut path = planner.replan();
+///
+/// // ... move along the path, and on discovering an obstacle:
+/// planner.move_to(current);
+/// planner.update_edge(current, blocked, None);
+/// path = planner.replan();
+/// ```
+///
+/// As with `bidirectional_dijkstra`, `reverse` must contain the edges of
+/// `graph` with their directions flipped. The `heuristic` estimates the cost
+/// between any two nodes, and must never overestimate it. Construction fails
+/// if either endpoint isn't in `graph`.
+pub struct DStarLite<'a, T, W, I, J> {
+    state: IncrementalState<'a, T, W, I, J>,
+    heuristic: |&T, &T|: 'a -> W,
+    start: &'a T,
+    goal: &'a T,
+    // Where the agent was when the queue's keys were last adjusted, and the
+    // total heuristic distance it has moved since the search began.
+    last: &'a T,
+    km: W
+}
+
+impl<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>, J: Iterator<(W, &'a T)>>
+    DStarLite<'a, T, W, I, J> {
+
+    pub fn new(graph: &'a graph::WeightedGraph<'a, T, W, I>,
+               reverse: &'a graph::WeightedGraph<'a, T, W, J>,
+               start: &'a T, goal: &'a T, heuristic: |&T, &T|: 'a -> W)
+               -> Result<DStarLite<'a, T, W, I, J>, SearchError> {
+
+        try!(check_endpoints(graph, start, Some(goal)));
+        let mut planner = DStarLite {
+            state: IncrementalState::new(graph, reverse),
+            heuristic: heuristic,
+            start: start,
+            goal: goal,
+            last: start,
+            km: Zero::zero()
+        };
+
+        planner.state.set_rhs(goal, Some(Zero::zero()));
+        let key = planner.calculate_key(goal).unwrap();
+        planner.state.enqueue(goal, key);
+        Ok(planner)
+    }
+
+    pub fn start(&self) -> &'a T {
+        self.start
+    }
+
+    pub fn goal(&self) -> &'a T {
+        self.goal
+    }
+
+    /// Record that the agent has moved to `node`, so that the next path is
+    /// planned from there.
+    pub fn move_to(&mut self, node: &'a T) {
+        self.start = node;
+    }
+
+    /// Change the cost of the edge from `from` to `to`, where `None` makes it
+    /// impassable. Only edges that exist in the graph can be changed.
+    pub fn update_edge(&mut self, from: &'a T, to: &'a T, cost: Option<W>) {
+        // Rather than re-keying the whole queue when the agent moves, keys
+        // computed from now on are inflated by the distance it has moved.
+        if self.last != self.start {
+            let (last, start) = (self.last, self.start);
+            self.km = self.km + (self.heuristic)(last, start);
+            self.last = start;
+        }
+
+        self.state.changed.insert((from, to), cost);
+        self.update_vertex(from);
+    }
+
+    /// Bring the search up to date with any changes and return the current
+    /// cheapest path from the agent's position to the goal, and its cost.
+    pub fn replan(&mut self) -> Option<(Vec<&'a T>, W)> {
+        self.compute_shortest_path();
+        self.extract_path()
+    }
+
+    fn calculate_key(&mut self, node: &'a T) -> Option<(W, W)> {
+        match infinite_min(self.state.g(node), self.state.rhs(node)) {
+            Some(cost) => {
+                let estimate = (self.heuristic)(self.start, node);
+                Some((cost + estimate + self.km, cost))
+            },
+            None => None
+        }
+    }
+
+    fn update_vertex(&mut self, node: &'a T) {
+        if node != self.goal {
+            let mut best = None;
+            for &(cost, next) in self.state.successors(node).iter() {
+                best = infinite_min(best, self.state.g(next).map(|g| g + cost));
+            }
+            self.state.set_rhs(node, best);
+        }
+
+        self.state.dequeue(node);
+
+        if self.state.g(node) != self.state.rhs(node) {
+            let key = self.calculate_key(node).unwrap();
+            self.state.enqueue(node, key);
+        }
+    }
+
+    fn compute_shortest_path(&mut self) {
+        loop {
+            let top = match self.state.top_key() {
+                Some(key) => key,
+                None => break
+            };
+
+            let start = self.start;
+            let start_key = self.calculate_key(start);
+            if !infinite_lt(Some(top), start_key) && self.state.rhs(start) == self.state.g(start) {
+                break;
+            }
+
+            let (node, old_key) = self.state.pop();
+            let new_key = self.calculate_key(node);
+
+            if infinite_lt(Some(old_key), new_key) {
+                // The key is out of date because the agent has moved.
+                self.state.enqueue(node, new_key.unwrap());
+            } else if infinite_lt(self.state.rhs(node), self.state.g(node)) {
+                let rhs = self.state.rhs(node).unwrap();
+                self.state.g.insert(node, rhs);
+
+                for &(_, prev) in self.state.predecessors(node).iter() {
+                    self.update_vertex(prev);
+                }
+            } else {
+                self.state.g.remove(&node);
+
+                for &(_, prev) in self.state.predecessors(node).iter() {
+                    self.update_vertex(prev);
+                }
+                self.update_vertex(node);
+            }
+        }
+    }
+
+    /// Follow the cheapest edges from the start to the goal.
+    fn extract_path(&self) -> Option<(Vec<&'a T>, W)> {
+        let mut path = vec!(self.start);
+        let mut current = self.start;
+        let mut total: W = Zero::zero();
+
+        while current != self.goal {
+            let mut best: Option<(W, W, &'a T)> = None;
+
+            for &(cost, next) in self.state.successors(current).iter() {
+                match self.state.g(next) {
+                    Some(g) => if best.map_or(true, |(b, _, _)| cost + g < b) {
+                        best = Some((cost + g, cost, next));
+                    },
+                    None => ()
+                }
+            }
+
+            match best {
+                Some((_, cost, next)) => {
+                    total = total + cost;
+                    current = next;
+                    path.push(next);
+                },
+                None => return None
+            }
+
+            // Zero-cost cycles could otherwise lead us around in circles.
+            if path.len() > self.state.g.len() + 1 {
+                return None;
+            }
+        }
+
+        Some((path, total))
+    }
+}
+
+/// An incremental planner using the Lifelong Planning A* (LPA*) algorithm.
+///
+/// LPA* answers repeated queries between a fixed start and goal on a graph
+/// whose edge costs change between queries. The costs computed by previous
+/// queries are kept, and only parts of the graph affected by the changes are
+/// searched again:
+///
+/// ```ignore
+/// let mut planner = try!(LifelongPlanningAStar::new(&g, &g, start, goal, |n| distance(n, goal)));
+/// let path = planner.replan();
+///
+/// planner.notify_edge_changed(a, b, Some(10));
+/// let new_path = planner.replan();
+/// ```
+///
+/// Unlike `DStarLite`, the search runs forwards from the start, and so the
+/// `heuristic` only needs to estimate the cost from each node to the goal.
+/// `reverse` must contain the edges of `graph` with their directions flipped,
+/// and construction fails if either endpoint isn't in `graph`.
+pub struct LifelongPlanningAStar<'a, T, W, I, J> {
+    state: IncrementalState<'a, T, W, I, J>,
+    heuristic: |&T|: 'a -> W,
+    start: &'a T,
+    goal: &'a T
+}
+
+impl<'a, T: Eq + Hash, W: Cost, I: Iterator<(W, &'a T)>, J: Iterator<(W, &'a T)>>
+    LifelongPlanningAStar<'a, T, W, I, J> {
+
+    pub fn new(graph: &'a graph::WeightedGraph<'a, T, W, I>,
+               reverse: &'a graph::WeightedGraph<'a, T, W, J>,
+               start: &'a T, goal: &'a T, heuristic: |&T|: 'a -> W)
+               -> Result<LifelongPlanningAStar<'a, T, W, I, J>, SearchError> {
+
+        try!(check_endpoints(graph, start, Some(goal)));
+        let mut planner = LifelongPlanningAStar {
+            state: IncrementalState::new(graph, reverse),
+            heuristic: heuristic,
+            start: start,
+            goal: goal
+        };
+
+        planner.state.set_rhs(start, Some(Zero::zero()));
+        let key = planner.calculate_key(start).unwrap();
+        planner.state.enqueue(start, key);
+        Ok(planner)
+    }
+
+    pub fn start(&self) -> &'a T {
+        self.start
+    }
+
+    pub fn goal(&self) -> &'a T {
+        self.goal
+    }
+
+    /// Change the cost of the edge from `from` to `to`, where `None` makes it
+    /// impassable. Only edges that exist in the graph can be changed, and the
+    /// change takes effect on the next call to `replan()`.
+    pub fn notify_edge_changed(&mut self, from: &'a T, to: &'a T, cost: Option<W>) {
+        self.state.changed.insert((from, to), cost);
+        self.update_vertex(to);
+    }
+
+    /// Bring the search up to date with any changes and return the current
+    /// cheapest path from the start to the goal, and its cost.
+    pub fn replan(&mut self) -> Option<(Vec<&'a T>, W)> {
+        self.compute_shortest_path();
+        self.extract_path()
+    }
+
+    fn calculate_key(&mut self, node: &'a T) -> Option<(W, W)> {
+        match infinite_min(self.state.g(node), self.state.rhs(node)) {
+            Some(cost) => Some((cost + (self.heuristic)(node), cost)),
+            None => None
+        }
+    }
+
+    fn update_vertex(&mut self, node: &'a T) {
+        if node != self.start {
+            let mut best = None;
+            for &(cost, prev) in self.state.predecessors(node).iter() {
+                best = infinite_min(best, self.state.g(prev).map(|g| g + cost));
+            }
+            self.state.set_rhs(node, best);
+        }
+
+        self.state.dequeue(node);
+
+        if self.state.g(node) != self.state.rhs(node) {
+            let key = self.calculate_key(node).unwrap();
+            self.state.enqueue(node, key);
+        }
+    }
+
+    fn compute_shortest_path(&mut self) {
+        loop {
+            let top = match self.state.top_key() {
+                Some(key) => key,
+                None => break
+            };
+
+            let goal = self.goal;
+            let goal_key = self.calculate_key(goal);
+            if !infinite_lt(Some(top), goal_key) && self.state.rhs(goal) == self.state.g(goal) {
+                break;
+            }
+
+            let (node, _) = self.state.pop();
+
+            if infinite_lt(self.state.rhs(node), self.state.g(node)) {
+                let rhs = self.state.rhs(node).unwrap();
+                self.state.g.insert(node, rhs);
+
+                for &(_, next) in self.state.successors(node).iter() {
+                    self.update_vertex(next);
+                }
+            } else {
+                self.state.g.remove(&node);
+
+                for &(_, next) in self.state.successors(node).iter() {
+                    self.update_vertex(next);
+                }
+                self.update_vertex(node);
+            }
+        }
+    }
+
+    /// Follow the cheapest edges backwards from the goal to the start.
+    fn extract_path(&self) -> Option<(Vec<&'a T>, W)> {
+        let total = match self.state.g(self.goal) {
+            Some(cost) => cost,
+            None => return None
+        };
+
+        let mut path = vec!(self.goal);
+        let mut current = self.goal;
+
+        while current != self.start {
+            let mut best: Option<(W, &'a T)> = None;
+
+            for &(cost, prev) in self.state.predecessors(current).iter() {
+                match self.state.g(prev) {
+                    Some(g) => if best.map_or(true, |(b, _)| g + cost < b) {
+                        best = Some((g + cost, prev));
+                    },
+                    None => ()
+                }
+            }
+
+            match best {
+                Some((_, prev)) => {
+                    current = prev;
+                    path.push(prev);
+                },
+                None => return None
+            }
+
+            // Zero-cost cycles could otherwise lead us around in circles.
+            if path.len() > self.state.g.len() + 1 {
+                return None;
+            }
+        }
+
+        path.reverse();
+        Some((path, total))
+    }
+}
+
+/// Graphs with fewer nodes than this, and frontiers with fewer entries, are
+/// processed on a single thread by the parallel searches, since the cost of
+/// handing the work out would outweigh any gain. `SharedGraph::with_threshold`
+/// overrides it.
+static PARALLEL_THRESHOLD: uint = 4096;
+
+/// A copy of the edges between a fixed set of nodes, in a form that can be
+/// shared between threads.
+///
+/// `WeightedGraph` hands out nodes borrowed from the graph, which can't be
+/// sent to another task, so the parallel searches work on nodes numbered by
+/// their position in `nodes` instead. Building the copy takes a single pass
+/// over the graph, after which it can be reused for any number of searches.
+pub struct SharedGraph<'a, T, W> {
+    nodes: Vec<&'a T>,
+    index: HashMap<&'a T, uint>,
+    edges: Arc<Vec<Vec<(uint, W)>>>,
+    threshold: uint
+}
+
+impl<'a, T: Eq + Hash, W: Send + Share> SharedGraph<'a, T, W> {
+
+    /// Copy the graph made up of `nodes`. Edges that lead outside of `nodes`
+    /// are ignored.
+    pub fn new<I: Iterator<(W, &'a T)>>(graph: &'a graph::WeightedGraph<'a, T, W, I>,
+                                       nodes: &[&'a T]) -> SharedGraph<'a, T, W> {
+        let mut index = HashMap::new();
+        for (i, &node) in nodes.iter().enumerate() {
+            index.insert(node, i);
+        }
+
+        let mut edges = Vec::with_capacity(nodes.len());
+        for &node in nodes.iter() {
+            let mut out = Vec::new();
+            for (cost, next) in graph.neighbours(node) {
+                match index.find(&next) {
+                    Some(&j) => out.push((j, cost)),
+                    None => ()
+                }
+            }
+            edges.push(out);
+        }
+
+        SharedGraph { nodes: nodes.to_vec(), index: index, edges: Arc::new(edges), threshold: PARALLEL_THRESHOLD }
+    }
+
+    /// Only share the work of a search out between threads once the graph,
+    /// or a single step's worth of work, has at least `threshold` nodes,
+    /// rather than `PARALLEL_THRESHOLD`.
+    pub fn with_threshold(self, threshold: uint) -> SharedGraph<'a, T, W> {
+        SharedGraph { threshold: threshold, ..self }
+    }
+
+    pub fn nodes<'b>(&'b self) -> &'b [&'a T] {
+        self.nodes.as_slice()
+    }
+
+    /// The number of the given node, if it's part of the graph.
+    fn index_of(&self, node: &'a T) -> Option<uint> {
+        self.index.find(&node).map(|&i| i)
+    }
+
+    /// Whether a search over the graph should bother to spread out over
+    /// more than one thread.
+    fn is_worth_splitting(&self, threads: uint) -> bool {
+        threads > 1 && self.nodes.len() >= self.threshold
+    }
+}
+
+/// The relaxations requested by the edges out of each `(node, cost)` in
+/// `frontier` that are light (no heavier than `delta`) or heavy, as asked.
+fn delta_requests(edges: &Vec<Vec<(uint, uint)>>, frontier: &[(uint, uint)],
+                  delta: uint, light: bool) -> Vec<(uint, uint)> {
+    let mut requests = Vec::new();
+    for &(node, cost) in frontier.iter() {
+        for &(next, weight) in edges.get(node).iter() {
+            if (weight <= delta) == light {
+                requests.push((next, cost + weight));
+            }
+        }
+    }
+    requests
+}
+
+/// Work out the requests for a frontier as `delta_requests` does, splitting
+/// it between `threads` tasks if it's large enough to be worth it.
+fn parallel_delta_requests(edges: &Arc<Vec<Vec<(uint, uint)>>>, frontier: Vec<(uint, uint)>,
+                           delta: uint, light: bool, threads: uint, threshold: uint) -> Vec<(uint, uint)> {
+    if threads <= 1 || frontier.len() < threshold {
+        return delta_requests(&**edges, frontier.as_slice(), delta, light);
+    }
+
+    let chunk = (frontier.len() + threads - 1) / threads;
+    let mut results = Vec::new();
+    for part in frontier.as_slice().chunks(chunk) {
+        let (tx, rx) = channel();
+        let (edges, part) = (edges.clone(), part.to_vec());
+        spawn(proc() {
+            tx.send(delta_requests(&*edges, part.as_slice(), delta, light));
+        });
+        results.push(rx);
+    }
+
+    let mut requests = Vec::new();
+    for rx in results.iter() {
+        requests.push_all(rx.recv().as_slice());
+    }
+    requests
+}
+
+/// Find the cost of the cheapest path from `start` to every node it can
+/// reach, using the delta-stepping algorithm on up to `threads` threads.
+///
+/// Nodes are put in buckets `delta` wide by their tentative cost, and each
+/// bucket is emptied in turn by relaxing all of its nodes' edges at once,
+/// which is where the parallelism comes from. Edges no heavier than `delta`
+/// can put nodes back into the current bucket, so they're relaxed
+/// repeatedly until it stays empty, and heavier edges only once afterwards.
+/// A small `delta` approaches Dijkstra's algorithm, doing little wasted work
+/// but having little to share out, while a large one approaches
+/// Bellman-Ford. Small graphs are simply searched on the current thread.
+pub fn delta_stepping<'a, T: Eq + Hash>(graph: &SharedGraph<'a, T, uint>, start: &'a T,
+                                        delta: uint, threads: uint) -> HashMap<&'a T, uint> {
+    assert!(delta > 0, "delta must be at least one");
+
+    let mut distances = HashMap::new();
+    let source = match graph.index_of(start) {
+        Some(source) => source,
+        None => return distances
+    };
+
+    let threads = if graph.is_worth_splitting(threads) { threads } else { 1 };
+    let mut cost: Vec<Option<uint>> = Vec::from_elem(graph.nodes.len(), None);
+    let mut buckets: Vec<Vec<uint>> = Vec::new();
+
+    let relax = |cost: &mut Vec<Option<uint>>, buckets: &mut Vec<Vec<uint>>, node: uint, new_cost: uint| {
+        if infinite_lt(Some(new_cost), *cost.get(node)) {
+            *cost.get_mut(node) = Some(new_cost);
+            let bucket = new_cost / delta;
+            while buckets.len() <= bucket {
+                buckets.push(Vec::new());
+            }
+            buckets.get_mut(bucket).push(node);
+        }
+    };
+
+    relax(&mut cost, &mut buckets, source, 0);
+
+    let mut i = 0;
+    while i < buckets.len() {
+        let mut settled = HashSet::new();
+
+        loop {
+            // Nodes that have since moved to an earlier bucket, or that
+            // appear more than once, only need to be handled once.
+            let mut frontier = Vec::new();
+            let mut seen = HashSet::new();
+            for &node in mem::replace(buckets.get_mut(i), Vec::new()).iter() {
+                let node_cost = cost.get(node).unwrap();
+                if node_cost / delta == i && seen.insert(node) {
+                    frontier.push((node, node_cost));
+                    settled.insert(node);
+                }
+            }
+
+            if frontier.is_empty() {
+                break;
+            }
+
+            let requests = parallel_delta_requests(&graph.edges, frontier, delta, true, threads, graph.threshold);
+            for &(next, new_cost) in requests.iter() {
+                relax(&mut cost, &mut buckets, next, new_cost);
+            }
+        }
+
+        let frontier: Vec<(uint, uint)> = settled.iter().map(|&n| (n, cost.get(n).unwrap())).collect();
+        let requests = parallel_delta_requests(&graph.edges, frontier, delta, false, threads, graph.threshold);
+        for &(next, new_cost) in requests.iter() {
+            relax(&mut cost, &mut buckets, next, new_cost);
+        }
+
+        i += 1;
+    }
+
+    for (node, c) in cost.iter().enumerate() {
+        match *c {
+            Some(c) => { distances.insert(*graph.nodes.get(node), c); },
+            None => ()
+        }
+    }
+    distances
+}
+
+/// Claim every unvisited neighbour of the nodes in `frontier`, returning
+/// them as this thread's share of the next level.
+fn claim_level<W>(edges: &Vec<Vec<(uint, W)>>, visited: &Vec<AtomicBool>, frontier: &[uint]) -> Vec<uint> {
+    let mut next_level = Vec::new();
+    for &node in frontier.iter() {
+        for &(next, _) in edges.get(node).iter() {
+            // Only the first thread to flip the flag gets to keep the node.
+            if !visited.get(next).compare_and_swap(false, true, SeqCst) {
+                next_level.push(next);
+            }
+        }
+    }
+    next_level
+}
+
+/// Find the number of edges between `start` and every node it can reach,
+/// breadth-first, using up to `threads` threads.
+///
+/// The search proceeds one level at a time. Each thread takes a share of the
+/// current level and collects the nodes it discovers into a buffer of its
+/// own, and the buffers are merged to form the next level, so the only
+/// contention between threads is over which of them discovers a node first.
+/// Small graphs are simply searched on the current thread.
+pub fn parallel_breadth_first_search<'a, T: Eq + Hash, W: Send + Share>
+    (graph: &SharedGraph<'a, T, W>, start: &'a T, threads: uint) -> HashMap<&'a T, uint> {
+
+    let mut depths = HashMap::new();
+    let source = match graph.index_of(start) {
+        Some(source) => source,
+        None => return depths
+    };
+
+    let threads = if graph.is_worth_splitting(threads) { threads } else { 1 };
+    let visited = Arc::new(Vec::from_fn(graph.nodes.len(), |_| AtomicBool::new(false)));
+    visited.get(source).store(true, SeqCst);
+
+    let mut level = vec!(source);
+    let mut depth = 0u;
+
+    while !level.is_empty() {
+        for &node in level.iter() {
+            depths.insert(*graph.nodes.get(node), depth);
+        }
+
+        level = if threads <= 1 || level.len() < graph.threshold {
+            claim_level(&*graph.edges, &*visited, level.as_slice())
+        } else {
+            let chunk = (level.len() + threads - 1) / threads;
+            let mut buffers = Vec::new();
+            for part in level.as_slice().chunks(chunk) {
+                let (tx, rx) = channel();
+                let (edges, visited, part) = (graph.edges.clone(), visited.clone(), part.to_vec());
+                spawn(proc() {
+                    tx.send(claim_level(&*edges, &*visited, part.as_slice()));
+                });
+                buffers.push(rx);
+            }
+
+            let mut next_level = Vec::new();
+            for rx in buffers.iter() {
+                next_level.push_all(rx.recv().as_slice());
+            }
+            next_level
+        };
+
+        depth += 1;
+    }
+
+    depths
+}
+
+/// How many nodes each HDA* worker may expand between synchronisations.
+static HDA_EXPANSIONS_PER_ROUND: uint = 64;
+
+/// The messages exchanged between HDA* workers: an offer of a path to a
+/// node with its cost and the node it came from, or notice that the sender
+/// has nothing more to say this round.
+enum HdaMessage<W> {
+    HdaRelax(uint, W, uint),
+    HdaEndOfRound
+}
+
+/// The HDA* worker responsible for a node.
+fn hda_owner(node: uint, workers: uint) -> uint {
+    (hash(&node) % workers as u64) as uint
+}
+
+/// The body of one HDA* worker, which owns the open list and best known
+/// costs of every node hashed to it.
+///
+/// Each round, the worker waits to be told the cost of the best path to the
+/// goal found so far, expands its most promising nodes that could still
+/// improve on it, and sends their successors to their owners. It then takes
+/// in whatever the other workers sent it, and reports back with the
+/// smallest estimate left on its open list and its best path to the goal.
+/// When told to stop, it hands over its table of costs and predecessors.
+fn hda_worker<T, W: Cost + Send>(edges: Arc<Vec<Vec<(uint, W)>>>, values: Arc<Vec<T>>, goal: uint,
+                                 heuristic: fn(&T, &T) -> W, inbox: Receiver<HdaMessage<W>>,
+                                 peers: Vec<Sender<HdaMessage<W>>>, control: Receiver<(bool, Option<W>)>,
+                                 report: Sender<(Option<W>, Option<W>)>,
+                                 finished: Sender<HashMap<uint, (W, uint)>>) {
+    let workers = peers.len();
+    let mut open = PriorityQueue::new();
+    let mut best: HashMap<uint, (W, uint)> = HashMap::new();
+    let mut goal_cost = None;
+
+    loop {
+        let (running, incumbent) = control.recv();
+        if !running {
+            break;
+        }
+
+        let mut expanded = 0u;
+        while expanded < HDA_EXPANSIONS_PER_ROUND && !open.is_empty() {
+            if incumbent.map_or(false, |c| open.top().unwrap().cost >= c) {
+                break;
+            }
+
+            let MinPriorityNode { node: (node, cost), cost: _ } = open.pop().unwrap();
+            let &(known, _) = best.get(&node);
+            if cost > known {
+                continue;
+            }
+            expanded += 1;
+
+            if node == goal {
+                continue;
+            }
+
+            for &(next, weight) in edges.get(node).iter() {
+                peers.get(hda_owner(next, workers)).send(HdaRelax(next, cost + weight, node));
+            }
+        }
+
+        for peer in peers.iter() {
+            peer.send(HdaEndOfRound);
+        }
+
+        let mut ended = 0;
+        while ended < workers {
+            match inbox.recv() {
+                HdaEndOfRound => ended += 1,
+                HdaRelax(node, cost, parent) => {
+                    if best.find(&node).map_or(true, |&(c, _)| cost < c) {
+                        best.insert(node, (cost, parent));
+                        if node == goal {
+                            goal_cost = Some(cost);
+                        }
+
+                        let estimate = cost + heuristic(values.get(node), values.get(goal));
+                        open.push(MinPriorityNode { node: (node, cost), cost: estimate });
+                    }
+                }
+            }
+        }
+
+        // Stale entries would make the report look more promising than it
+        // really is, so clear them off first.
+        loop {
+            let stale = match open.top() {
+                Some(&MinPriorityNode { node: (node, cost), cost: _ }) => {
+                    let &(known, _) = best.get(&node);
+                    cost > known
+                },
+                None => false
+            };
+
+            if !stale {
+                break;
+            }
+            open.pop();
+        }
+
+        report.send((open.top().map(|top| top.cost), goal_cost));
+    }
+
+    finished.send(best);
+}
+
+/// Find the cheapest path from `start` to `goal` with A*, spread over up to
+/// `threads` threads using Hash Distributed A* (HDA*).
+///
+/// Every node is assigned to a worker thread by its hash, and each worker
+/// keeps the open and closed lists for its own nodes, sending successors to
+/// their owners over channels. The workers expand nodes in rounds, and the
+/// search stops once no worker has a node whose estimate beats the best path
+/// found. The `heuristic` estimates the cost between two nodes (the second
+/// being the goal) and must be admissible; it's a plain function so that it
+/// can be called from any thread. The nodes are copied for the workers'
+/// benefit, so this only pays off for expensive queries on large graphs.
+pub fn hash_distributed_a_star<'a, T: Eq + Hash + Clone + Send + Share, W: Cost + Send + Share>
+    (graph: &SharedGraph<'a, T, W>, start: &'a T, goal: &'a T, heuristic: fn(&T, &T) -> W,
+     threads: uint) -> Option<(Vec<&'a T>, W)> {
+
+    let (source, target) = match (graph.index_of(start), graph.index_of(goal)) {
+        (Some(s), Some(t)) => (s, t),
+        _ => return None
+    };
+
+    if source == target {
+        return Some((vec!(start), Zero::zero()));
+    }
+
+    let workers = if graph.is_worth_splitting(threads) { threads } else { 1 };
+    let values: Arc<Vec<T>> = Arc::new(graph.nodes.iter().map(|&n| n.clone()).collect());
+
+    let mut inboxes = Vec::new();
+    let mut peers = Vec::new();
+    for _ in range(0, workers) {
+        let (tx, rx) = channel();
+        peers.push(tx);
+        inboxes.push(rx);
+    }
+
+    let (report_tx, reports) = channel();
+    let (finished_tx, finished) = channel();
+    let mut controls = Vec::new();
+
+    for inbox in inboxes.move_iter() {
+        let (control_tx, control) = channel();
+        controls.push(control_tx);
+
+        let (edges, values, peers) = (graph.edges.clone(), values.clone(), peers.clone());
+        let (report, finished) = (report_tx.clone(), finished_tx.clone());
+        spawn(proc() {
+            hda_worker(edges, values, target, heuristic, inbox, peers, control, report, finished);
+        });
+    }
+
+    peers.get(hda_owner(source, workers)).send(HdaRelax(source, Zero::zero(), source));
+
+    let mut incumbent = None;
+    loop {
+        for control in controls.iter() {
+            control.send((true, incumbent));
+        }
+
+        let mut lowest = None;
+        for _ in range(0, workers) {
+            let (estimate, goal_cost) = reports.recv();
+            lowest = infinite_min(lowest, estimate);
+            incumbent = infinite_min(incumbent, goal_cost);
+        }
+
+        match (incumbent, lowest) {
+            (_, None) => break,
+            (Some(cost), Some(estimate)) if cost <= estimate => break,
+            _ => ()
+        }
+    }
+
+    for control in controls.iter() {
+        control.send((false, None));
+    }
+
+    let mut came_from = HashMap::new();
+    for _ in range(0, workers) {
+        for (&node, &(_, parent)) in finished.recv().iter() {
+            came_from.insert(node, parent);
+        }
+    }
+
+    incumbent.map(|cost| {
+        let mut path = vec!(goal);
+        let mut node = target;
+        while node != source {
+            node = *came_from.get(&node);
+            path.push(*graph.nodes.get(node));
+        }
+        path.reverse();
+        (path, cost)
+    })
+}
+
+fn main() {
+    let g = graph::SimpleGraph::from_edges(&[
+        ("A", "B", 1u),
+        ("B", "A", 1), ("B", "C", 1), ("B", "D", 1),
+        ("C", "A", 1),
+        ("D", "E", 1), ("D", "A", 1),
+        ("E", "B", 1)
+    ]);
+
+    println!("Searching over the whole graph:");
+    breadth_first_search(&g, &"A", None, Some(|node, _| println!("    Visiting: {}", node))).unwrap();
 
     println!("Searching over the graph with goal 'D':");
-    dijkstra_search(&g, &"A", &"D");
+    match breadth_first_search(&g, &"A", Some(&"D"), Some(|node, _| println!("    Visiting: {}", node))) {
+        Ok(BreadthFirstResult { path: Some((path, depth)), .. }) => println!("    Path: {} ({} edges)", path, depth),
+        Ok(_) => println!("    No path found."),
+        Err(e) => println!("    Search failed: {}", e)
+    }
+
+    println!("Searching over the graph with goal 'D':");
+    match dijkstra_search(&g, &"A", &"D", Some(|node, _| println!("    Visiting: {}", node))) {
+        Ok(Some((path, cost))) => println!("    Path: {} (cost {})", path, cost),
+        Ok(None) => println!("    No path found."),
+        Err(e) => println!("    Search failed: {}", e)
+    }
+
+    println!("A* search over the graph with goal 'D':");
+    match a_star_search(&g, &"A", &"D", |_| 0, None) {
+        Ok(Some((path, cost))) => println!("    Path: {} (cost {})", path, cost),
+        Ok(None) => println!("    No path found."),
+        Err(e) => println!("    Search failed: {}", e)
+    }
+
+    // A 4x3 grid with a wall down the middle that has a gap at the bottom.
+    let mut blocked = HashSet::new();
+    blocked.insert((2u, 0u));
+    blocked.insert((2u, 1u));
+
+    let grid = graph::GridGraph::new(4, 3, blocked);
+
+    println!("A* search over a grid from (0, 0) to (3, 0):");
+    match a_star_search(&grid, &(0, 0), &(3, 0), |&(x, y)| (3 - x) + y, None) {
+        Ok(Some((path, cost))) => println!("    Path: {} (cost {})", path, cost),
+        Ok(None) => println!("    No path found."),
+        Err(e) => println!("    Search failed: {}", e)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{HashMap, HashSet};
+    use std::io::{MemReader, MemWriter};
+
+    use super::{a_star_search, betweenness_centrality, bidirectional_a_star_search_with_stats,
+                bidirectional_breadth_first_search_with_stats, bidirectional_dijkstra_with_stats,
+                breadth_first_search, breadth_first_search_with_stats, delta_stepping, dijkstra_all,
+                dijkstra_search, dijkstra_search_limited, dijkstra_search_with_stats,
+                fringe_search_with_stats, graph, hash_distributed_a_star, ida_star_search_with_stats,
+                parallel_breadth_first_search, partition_breadth_first, weighted_a_star_search_with_stats,
+                AnytimeRepairingAStar, ArcFlags, ContractionHierarchy, Dijkstra, DStarLite, GoalNotFound,
+                HierarchicalGrid, JumpPointTable, LifelongPlanningAStar, MemoryBoundedAStar, Reached,
+                SearchLimits, SharedGraph, StartNotFound};
+    use super::graph::WeightedGraph;
+
+    static A: &'static str = "A";
+    static B: &'static str = "B";
+    static C: &'static str = "C";
+
+    // A small maze with walls that make paths turn and jump points that
+    // matter.
+    fn maze(connectivity: graph::Connectivity) -> graph::GridGraph {
+        let mut blocked = HashSet::new();
+        for &cell in [(2u, 0u), (2, 1), (2, 2), (4, 2), (4, 3), (4, 4), (1, 4), (0, 3)].iter() {
+            blocked.insert(cell);
+        }
+        graph::GridGraph::with_connectivity(7, 6, blocked, connectivity)
+    }
+
+    fn cells(grid: &graph::GridGraph) -> Vec<(uint, uint)> {
+        let mut cells = Vec::new();
+        for y in range(0, grid.height()) {
+            for x in range(0, grid.width()) {
+                cells.push((x, y));
+            }
+        }
+        cells
+    }
+
+    // A ring of six nodes with two chords, and a slower parallel edge
+    // alongside two of the others. Every edge has a different power of two
+    // as its weight, so no two paths cost the same and the cheapest path
+    // between any pair is unique.
+    fn ring() -> graph::MultiGraph<uint, uint> {
+        let mut g = graph::MultiGraph::new(graph::AllParallelEdges);
+        let mut weight = 1u;
+        for &(a, b) in [(0u, 1u), (1, 2), (2, 3), (3, 4), (4, 5), (5, 0), (1, 4), (0, 3)].iter() {
+            g.add_edge(a, b, weight);
+            g.add_edge(b, a, weight * 2);
+            weight *= 4;
+        }
+        g.add_edge(2, 3, weight);
+        g.add_edge(4, 1, weight * 2);
+        g
+    }
+
+    // The cost of a step in `lattice`, which is more than the extra added to
+    // any path, so that `manhattan` distances times this never overestimate.
+    static STEP: uint = 4096;
+
+    // A three by three lattice of nodes joined in both directions. Each of
+    // the twelve links costs `STEP` plus a different power of two, which
+    // makes the cheapest path between any pair unique. `change` is given
+    // the ends and weight of each directed edge, and returns the weight to
+    // use or `None` to leave the edge out.
+    fn lattice(change: |&(uint, uint), &(uint, uint), uint| -> Option<uint>)
+        -> graph::SimpleGraph<(uint, uint), uint> {
+
+        let mut edges = Vec::new();
+        let mut extra = 1u;
+        for y in range(0u, 3) {
+            for x in range(0u, 3) {
+                for &(nx, ny) in [(x + 1, y), (x, y + 1)].iter() {
+                    if nx < 3 && ny < 3 {
+                        for &(a, b) in [((x, y), (nx, ny)), ((nx, ny), (x, y))].iter() {
+                            match change(&a, &b, STEP + extra) {
+                                Some(weight) => edges.push((a, b, weight)),
+                                None => ()
+                            }
+                        }
+                        extra *= 2;
+                    }
+                }
+            }
+        }
+        graph::SimpleGraph::from_edges(edges.as_slice())
+    }
+
+    fn lattice_cells() -> Vec<(uint, uint)> {
+        let mut cells = Vec::new();
+        for y in range(0u, 3) {
+            for x in range(0u, 3) {
+                cells.push((x, y));
+            }
+        }
+        cells
+    }
+
+    fn manhattan(a: &(uint, uint), b: &(uint, uint)) -> uint {
+        let (&(ax, ay), &(bx, by)) = (a, b);
+        ((ax as int - bx as int).abs() + (ay as int - by as int).abs()) as uint * STEP
+    }
+
+    // The route to "C" through "B" supersedes the direct edge after "C" has
+    // already been queued, leaving a stale entry ahead of the goal.
+    fn superseded() -> graph::SimpleGraph<&'static str, uint> {
+        graph::SimpleGraph::from_edges(&[
+            ("A", "C", 10u), ("A", "B", 1), ("B", "C", 1), ("C", "D", 20)
+        ])
+    }
+
+    #[test]
+    fn expansion_limit_counts_distinct_nodes() {
+        let g = superseded();
+        match dijkstra_search_limited(&g, &"A", &"D", &SearchLimits::none().with_expansions(3)) {
+            Ok(Reached(path, cost)) => {
+                assert_eq!(path, vec!(&"A", &"B", &"C", &"D"));
+                assert_eq!(cost, 22);
+            },
+            other => fail!("expected to reach the goal, got {}", other)
+        }
+    }
+
+    #[test]
+    fn stats_count_each_expansion_once() {
+        let g = superseded();
+        let result = dijkstra_search_with_stats(&g, &"A", &"D").unwrap();
+        assert_eq!(result.stats.nodes_expanded, 3);
+    }
+
+    #[test]
+    fn stats_come_with_the_same_paths() {
+        let g = lattice(|_, _, weight| Some(weight));
+        let cells = lattice_cells();
+
+        // The lattice is the same in both directions, so it is its own
+        // reverse.
+        for start in cells.iter() {
+            for goal in cells.iter() {
+                let expected = dijkstra_search(&g, start, goal, None).unwrap();
+                let results = vec!(
+                    weighted_a_star_search_with_stats(&g, start, goal, |n| manhattan(n, goal), 1).unwrap(),
+                    ida_star_search_with_stats(&g, start, goal, |n| manhattan(n, goal)).unwrap(),
+                    fringe_search_with_stats(&g, start, goal, |n| manhattan(n, goal)).unwrap(),
+                    bidirectional_dijkstra_with_stats(&g, &g, start, goal).unwrap(),
+                    bidirectional_a_star_search_with_stats(&g, &g, start, goal, |n| manhattan(n, goal),
+                                                           |n| manhattan(start, n)).unwrap());
+                for result in results.iter() {
+                    assert_eq!(result.path, expected);
+                    assert_eq!(result.stats.nodes_expanded > 0, start != goal);
+                }
+
+                let edges = manhattan(start, goal) / STEP;
+                let results = vec!(
+                    breadth_first_search_with_stats(&g, start, goal).unwrap(),
+                    bidirectional_breadth_first_search_with_stats(&g, &g, start, goal).unwrap());
+                for result in results.iter() {
+                    let (ref path, cost) = *result.path.as_ref().unwrap();
+                    assert_eq!((path.len(), cost), (edges + 1, edges));
+                    assert_eq!(result.stats.nodes_expanded > 0, start != goal);
+                    assert!(result.stats.nodes_generated >= edges);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn resumable_dijkstra_finishes_with_only_stale_entries_left() {
+        let g = superseded();
+        let mut search = Dijkstra::new(&g, &"A");
+
+        // The direct edge to "C" is still queued after all four nodes have
+        // been settled.
+        assert_eq!(search.run_for(4), Ok(true));
+        assert_eq!(search.distance(&"D"), Some(22));
+    }
+
+    #[test]
+    fn resumable_dijkstra_reports_missing_endpoints() {
+        let g = superseded();
+        assert_eq!(Dijkstra::new(&g, &"Z").run_for(1), Err(StartNotFound));
+        assert_eq!(Dijkstra::new(&g, &"A").run_until(&"Z", 1), Err(GoalNotFound));
+    }
+
+    #[test]
+    fn betweenness_ignores_zero_weight_edges_back_to_settled_nodes() {
+        let g = graph::SimpleGraph::from_edges(&[(A, B, 0u), (B, A, 0), (B, C, 1), (C, B, 1)]);
+        let centrality = betweenness_centrality(&g, &[&A, &B, &C], None).unwrap();
+
+        // Only the paths between A and C pass through anything, in each
+        // direction.
+        assert_eq!(*centrality.get(&&A), 0.0);
+        assert_eq!(*centrality.get(&&B), 2.0);
+        assert_eq!(*centrality.get(&&C), 0.0);
+    }
+
+    #[test]
+    fn memory_bounded_a_star_follows_parallel_edges() {
+        let mut g = graph::MultiGraph::new(graph::AllParallelEdges);
+        g.add_edge(A, B, 2u);
+        g.add_edge(A, B, 1);
+        g.add_edge(B, C, 10);
+
+        let result = MemoryBoundedAStar::new(&g, 10, |_| 0u).search(&A, &C).unwrap().unwrap();
+        assert_eq!(result.path, vec!(&A, &B, &C));
+        assert_eq!(result.cost, 11);
+        assert!(result.optimal);
+    }
+
+    #[test]
+    fn jump_point_search_costs_match_a_star() {
+        let grid = maze(graph::EightWayNoCorners);
+        let table = JumpPointTable::new(&grid);
+
+        for &start in cells(&grid).iter() {
+            for &goal in cells(&grid).iter() {
+                let expected = match (grid.find_cell(&start), grid.find_cell(&goal)) {
+                    (Some(s), Some(g)) if grid.is_walkable(s) && grid.is_walkable(g) => {
+                        a_star_search(&grid, s, g, |_| 0u, None).unwrap().map(|(_, cost)| cost)
+                    },
+                    _ => None
+                };
+                assert_eq!(table.search(start, goal).map(|(_, cost)| cost), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn jump_point_table_round_trips() {
+        let table = JumpPointTable::new(&maze(graph::EightWayNoCorners));
+        let mut writer = MemWriter::new();
+        table.write_to(&mut writer).unwrap();
+        let bytes = writer.unwrap();
+
+        let loaded = JumpPointTable::read_from(&mut MemReader::new(bytes.clone())).unwrap();
+        let mut rewritten = MemWriter::new();
+        loaded.write_to(&mut rewritten).unwrap();
+        assert_eq!(rewritten.unwrap(), bytes);
+        assert_eq!(loaded.search((0, 0), (6, 5)), table.search((0, 0), (6, 5)));
+    }
+
+    #[test]
+    fn jump_point_table_rejects_jumps_off_the_grid() {
+        let table = JumpPointTable::new(&maze(graph::EightWayNoCorners));
+        let mut writer = MemWriter::new();
+        table.write_to(&mut writer).unwrap();
+        let mut bytes = writer.unwrap();
+
+        // Make the first cell jump five steps north, off the top edge.
+        let first_distance = 8 + table.width() * table.height();
+        *bytes.get_mut(first_distance) = 5;
+        assert!(JumpPointTable::read_from(&mut MemReader::new(bytes)).is_err());
+    }
+
+    #[test]
+    fn contraction_hierarchy_matches_dijkstra() {
+        let g = ring();
+        let nodes = g.nodes();
+        let hierarchy = ContractionHierarchy::new(&g, nodes.as_slice()).unwrap();
+
+        for &start in nodes.iter() {
+            for &goal in nodes.iter() {
+                assert_eq!(hierarchy.search(start, goal), dijkstra_search(&g, start, goal, None).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn d_star_lite_replans_around_a_blocked_edge() {
+        let g = lattice(|_, _, weight| Some(weight));
+        let (start, goal) = ((0u, 0u), (2u, 2u));
+        let mut planner = DStarLite::new(&g, &g, &start, &goal, |a, b| manhattan(a, b)).unwrap();
+
+        let (path, cost) = planner.replan().unwrap();
+        assert_eq!(Some((path.clone(), cost)), dijkstra_search(&g, &start, &goal, None).unwrap());
+
+        // Take a step along the path, then find the next edge blocked. The
+        // step makes the keys already queued out of date.
+        let (here, ahead) = (*path.get(1), *path.get(2));
+        planner.move_to(here);
+        planner.update_edge(here, ahead, None);
+
+        let blocked = lattice(|a, b, weight| if a == here && b == ahead { None } else { Some(weight) });
+        assert_eq!(planner.replan(), dijkstra_search(&blocked, here, &goal, None).unwrap());
+    }
+
+    #[test]
+    fn lifelong_planning_a_star_follows_edge_cost_changes() {
+        let g = lattice(|_, _, weight| Some(weight));
+        let (start, goal) = ((0u, 0u), (2u, 2u));
+        let mut planner = LifelongPlanningAStar::new(&g, &g, &start, &goal, |n| manhattan(n, &goal)).unwrap();
+
+        let (path, cost) = planner.replan().unwrap();
+        assert_eq!(Some((path.clone(), cost)), dijkstra_search(&g, &start, &goal, None).unwrap());
+
+        // Make an edge on the path dearer, so that the path goes around it,
+        // then cheaper than it was to begin with.
+        let (from, to) = (*path.get(1), *path.get(2));
+        for &change in [4 * STEP, 0].iter() {
+            planner.notify_edge_changed(from, to, Some(STEP + change));
+
+            let changed = lattice(|a, b, weight| Some(if a == from && b == to { STEP + change } else { weight }));
+            assert_eq!(planner.replan(), dijkstra_search(&changed, &start, &goal, None).unwrap());
+        }
+    }
+
+    #[test]
+    fn hash_distributed_a_star_matches_a_star_with_several_workers() {
+        let g = lattice(|_, _, weight| Some(weight));
+        let cells = lattice_cells();
+        let nodes: Vec<&(uint, uint)> = cells.iter().collect();
+
+        // Share out even this small graph, so that the workers really do
+        // have to pass nodes between them and agree when to stop.
+        let shared = SharedGraph::new(&g, nodes.as_slice()).with_threshold(1);
+
+        for &start in nodes.iter() {
+            for &goal in nodes.iter() {
+                let expected = a_star_search(&g, start, goal, |n| manhattan(n, goal), None).unwrap();
+                assert_eq!(hash_distributed_a_star(&shared, start, goal, manhattan, 3), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn delta_stepping_matches_dijkstra_with_several_threads() {
+        let g = lattice(|_, _, weight| Some(weight));
+        let cells = lattice_cells();
+        let nodes: Vec<&(uint, uint)> = cells.iter().collect();
+        let shared = SharedGraph::new(&g, nodes.as_slice()).with_threshold(1);
+
+        // With a delta just over one step, the five cheapest links are light
+        // and the rest heavy, so both kinds of relaxation get exercised.
+        for &start in nodes.iter() {
+            assert_eq!(delta_stepping(&shared, start, STEP + 16, 3), dijkstra_all(&g, start).unwrap());
+        }
+    }
+
+    #[test]
+    fn parallel_breadth_first_search_matches_breadth_first_search_with_several_threads() {
+        let g = lattice(|_, _, weight| Some(weight));
+        let cells = lattice_cells();
+        let nodes: Vec<&(uint, uint)> = cells.iter().collect();
+        let shared = SharedGraph::new(&g, nodes.as_slice()).with_threshold(1);
+
+        for &start in nodes.iter() {
+            let mut expected = HashMap::new();
+            breadth_first_search(&g, start, None, Some(|node, depth| { expected.insert(node, depth); })).unwrap();
+            assert_eq!(parallel_breadth_first_search(&shared, start, 3), expected);
+        }
+    }
+
+    #[test]
+    fn anytime_repairing_a_star_tightens_towards_the_optimum() {
+        let g = lattice(|_, _, weight| Some(weight));
+        let (start, goal) = ((0u, 0u), (2u, 2u));
+        let (_, optimum) = dijkstra_search(&g, &start, &goal, None).unwrap().unwrap();
+
+        let planner = AnytimeRepairingAStar::new(&g, &start, &goal, |n| manhattan(n, &goal), 5, 1).unwrap();
+        let solutions: Vec<(uint, uint)> = planner.map(|(solution, epsilon)| (solution.cost, epsilon)).collect();
+        assert!(!solutions.is_empty());
+
+        let mut previous = None;
+        for &(cost, epsilon) in solutions.iter() {
+            assert!(cost <= epsilon * optimum);
+            assert!(previous.map_or(true, |p| epsilon < p));
+            previous = Some(epsilon);
+        }
+
+        let &(last, _) = solutions.last().unwrap();
+        assert_eq!(last, optimum);
+    }
+
+    #[test]
+    fn anytime_repairing_a_star_stops_when_epsilon_cannot_fall() {
+        let g = lattice(|_, _, weight| Some(weight));
+        let (start, goal) = ((0u, 0u), (2u, 2u));
+        let planner = AnytimeRepairingAStar::new(&g, &start, &goal, |n| manhattan(n, &goal), 5, 0).unwrap();
+        assert_eq!(planner.take(10).count(), 1);
+    }
+
+    // Check that `path` is a walk between neighbouring cells of `grid` whose
+    // steps add up to `cost`.
+    fn assert_walk(grid: &graph::GridGraph, path: &[&(uint, uint)], cost: uint) {
+        let mut total = 0u;
+        for pair in path.windows(2) {
+            assert!(grid.is_walkable(pair[0]) && grid.is_walkable(pair[1]));
+            match grid.neighbours(pair[0]).find(|&(_, next)| next == pair[1]) {
+                Some((step, _)) => total += step,
+                None => fail!("{} doesn't lead to {}", pair[0], pair[1])
+            }
+        }
+        assert_eq!(total, cost);
+    }
+
+    #[test]
+    fn hierarchical_grid_within_one_cluster() {
+        let grid = maze(graph::FourWay);
+        let hierarchy = HierarchicalGrid::new(&grid, 3);
+        let (start, goal) = (grid.find_cell(&(0, 0)).unwrap(), grid.find_cell(&(1, 2)).unwrap());
+
+        let (path, cost) = hierarchy.search(start, goal).unwrap();
+        assert_eq!(path.head(), Some(&start));
+        assert_eq!(path.last(), Some(&goal));
+        assert_eq!(Some(cost), a_star_search(&grid, start, goal, |_| 0u, None).unwrap().map(|(_, c)| c));
+        assert_walk(&grid, path.as_slice(), cost);
+    }
+
+    #[test]
+    fn hierarchical_grid_across_clusters() {
+        let grid = maze(graph::FourWay);
+        let hierarchy = HierarchicalGrid::new(&grid, 3);
+        let (start, goal) = (grid.find_cell(&(0, 0)).unwrap(), grid.find_cell(&(6, 5)).unwrap());
+
+        // The path has to wind through several clusters, and may not be
+        // the very cheapest since it's forced through their entrances.
+        let (path, cost) = hierarchy.search(start, goal).unwrap();
+        let (_, optimum) = a_star_search(&grid, start, goal, |_| 0u, None).unwrap().unwrap();
+        assert!(cost >= optimum);
+        assert_eq!(path.head(), Some(&start));
+        assert_eq!(path.last(), Some(&goal));
+        let (entrances, _) = hierarchy.abstract_search(start, goal).unwrap();
+        assert!(entrances.len() > 2);
+    }
+
+    #[test]
+    fn hierarchical_grid_refines_into_a_walk() {
+        let grid = maze(graph::FourWay);
+        let hierarchy = HierarchicalGrid::new(&grid, 3);
+
+        for &start in cells(&grid).iter() {
+            for &goal in cells(&grid).iter() {
+                let (start, goal) = (grid.find_cell(&start).unwrap(), grid.find_cell(&goal).unwrap());
+                match hierarchy.abstract_search(start, goal) {
+                    Some((entrances, cost)) => {
+                        let path = hierarchy.refine(entrances.as_slice());
+                        assert_eq!(path.head(), Some(&start));
+                        assert_eq!(path.last(), Some(&goal));
+                        assert_walk(&grid, path.as_slice(), cost);
+                    },
+                    None => assert!(!grid.is_walkable(start) || !grid.is_walkable(goal)
+                                    || a_star_search(&grid, start, goal, |_| 0u, None).unwrap().is_none())
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn arc_flags_match_dijkstra() {
+        let g = lattice(|_, _, weight| Some(weight));
+        let cells = lattice_cells();
+        let nodes: Vec<&(uint, uint)> = cells.iter().collect();
+        let regions = partition_breadth_first(&g, nodes.as_slice(), 3);
+        let flags = ArcFlags::new(&g, &g, nodes.as_slice(), regions).unwrap();
+
+        for &start in nodes.iter() {
+            for &goal in nodes.iter() {
+                assert_eq!(flags.search(start, goal), dijkstra_search(&g, start, goal, None).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn arc_flags_round_trip() {
+        let g = lattice(|_, _, weight| Some(weight));
+        let cells = lattice_cells();
+        let nodes: Vec<&(uint, uint)> = cells.iter().collect();
+        let regions = partition_breadth_first(&g, nodes.as_slice(), 3);
+        let flags = ArcFlags::new(&g, &g, nodes.as_slice(), regions.clone()).unwrap();
+
+        let mut writer = MemWriter::new();
+        flags.write_to(&mut writer).unwrap();
+        let bytes = writer.unwrap();
+
+        let loaded = ArcFlags::read_from(&g, nodes.as_slice(), regions, &mut MemReader::new(bytes.clone())).unwrap();
+        let mut rewritten = MemWriter::new();
+        loaded.write_to(&mut rewritten).unwrap();
+        assert_eq!(rewritten.unwrap(), bytes);
+
+        for &start in nodes.iter() {
+            for &goal in nodes.iter() {
+                assert_eq!(loaded.search(start, goal), flags.search(start, goal));
+            }
+        }
+    }
 }